@@ -9,29 +9,46 @@ extern crate nerve_base;
 extern crate nerve;
 
 mod util;
+mod scan;
 
 use std::io::{stdout, Write};
 use std::env;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::fs::read_to_string;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
 use chrono::{Local, DateTime};
 use tokio;
-use ipnet::{Ipv4Net};
+use ipnet::IpNet;
 use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
 use nerve_base::ScanStatus;
 use nerve_base::{interface, arp};
-use nerve::{PortScanner, HostScanner, UriScanner, DomainScanner};
-use nerve::PortScanType;
+use nerve::{UriScanner, DomainScanner};
 use util::{option, validator};
+use util::option::ScanType;
+use scan::{PortState, PortStateResult};
+use scan::connect::ConnectScanner;
+use scan::host::{PingScanner, HostStateResult};
+use scan::ndp::NdpScanner;
+use scan::arp::ArpScanner;
+use scan::rdns::ReverseResolver;
+use scan::localname::{LocalNameResolver, LocalNameInfo};
+use scan::tcp::TcpScanner;
+use scan::udp::UdpScanner;
+use scan::service::{ServiceDetector, ServiceInfo};
+use scan::tls::{self, TlsInspector, TlsInfo};
+use scan::os::{OsDetector, OsFingerprint, OsGuess};
 use util::sys::{self, SPACE4};
 use util::db;
 use crossterm::style::Colorize;
 use dns_lookup::lookup_host;
+use futures::stream::{self, StreamExt};
 
 const CRATE_UPDATE_DATE: &str = "2021/3/8";
 const CRATE_AUTHOR_GITHUB: &str = "toref <https://github.com/toref-sh>";
+const DEFAULT_TOP_PORTS: u16 = 1000;
 
 #[cfg(target_os = "windows")]
 fn get_os_type() -> String{"windows".to_owned()}
@@ -79,6 +96,32 @@ async fn main() {
                     },
                 }
             }
+        }else if sub_matches.is_present("probe"){
+            if let Some(v) = sub_matches.value_of("probe") {
+                db::init_db();
+                match db::update_service_probe(&v.to_string()) {
+                    Ok(_) =>{
+                        println!("{}", "Done".green());
+                        println!("Service probe data has been updated.");
+                    },
+                    Err(_) => {
+                        println!("{}", "Failed".red());
+                    },
+                }
+            }
+        }else if sub_matches.is_present("os"){
+            if let Some(v) = sub_matches.value_of("os") {
+                db::init_db();
+                match db::update_os_signature(&v.to_string()) {
+                    Ok(_) =>{
+                        println!("{}", "Done".green());
+                        println!("OS signature data has been updated.");
+                    },
+                    Err(_) => {
+                        println!("{}", "Failed".red());
+                    },
+                }
+            }
         }else if sub_matches.is_present("oui"){
             if let Some(v) = sub_matches.value_of("oui") {
                 db::init_db();
@@ -102,26 +145,82 @@ async fn main() {
     //Scan
     show_banner_with_starttime();
     if matches.is_present("port"){
-        if !sys::check_root() {
-            println!("{} This feature requires administrator privileges. ","error:".red());
-            std::process::exit(0);
-        }
         if let Some(v) = matches.value_of("port") {
             let mut opt = option::PortOption::new();
-            opt.set_option(v.to_string());
+            if let Err(e) = opt.set_option(v.to_string()) {
+                println!("{} {}", "error:".red(), e);
+                std::process::exit(0);
+            }
+            if let Some(x) = matches.value_of("exclude") {
+                if let Err(e) = opt.set_exclude(x.to_string()) {
+                    println!("{} {}", "error:".red(), e);
+                    std::process::exit(0);
+                }
+            }
             if let Some(w) = matches.value_of("word") {
                 opt.set_file_path(w.to_string());
             }
+            if let Some(p) = matches.value_of("parallel") {
+                opt.set_parallelism(p.to_string());
+            }
             if let Some(i) = matches.value_of("interface") {
                 opt.set_if_name(i.to_string());
             }
+            if let Some(st) = matches.value_of("scantype") {
+                opt.set_scan_type(st.to_string());
+            }
+            if let Some(n) = matches.value_of("topports") {
+                opt.set_top_ports(n.to_string());
+            }
+            opt.set_service_detect(matches.is_present("servicedetect"));
+            opt.set_tls_inspect(matches.is_present("tlsinspect"));
+            opt.set_os_detect(matches.is_present("osdetect"));
+            if let Some(v) = matches.value_of("show") {
+                opt.set_show_states(v.to_string());
+            }
+            if let Some(v) = matches.value_of("timing") {
+                opt.set_timing(v.to_string());
+            }
+            if let Some(v) = matches.value_of("rate") {
+                opt.set_rate(v.to_string());
+            }
+            if let Some(v) = matches.value_of("maxretries") {
+                opt.set_max_retries(v.to_string());
+            }
+            if let Some(v) = matches.value_of("sourceport") {
+                opt.set_source_port(v.to_string());
+            }
+            opt.set_randomize_ports(matches.is_present("randomizeports"));
             if let Some(t) = matches.value_of("timeout") {
                 opt.set_timeout(t.to_string());
             }
             if let Some(s) = matches.value_of("save") {
                 opt.set_save_path(s.to_string());
             }
-            handle_port_scan(opt);
+            if !sys::check_root() {
+                if opt.scan_type == ScanType::SynScan {
+                    println!("{} Raw sockets are not available. Falling back to TCP connect scan.", "info:".cyan());
+                    println!();
+                    opt.scan_type = ScanType::ConnectScan;
+                }else if opt.scan_type != ScanType::ConnectScan {
+                    println!("{} This feature requires administrator privileges. ","error:".red());
+                    std::process::exit(0);
+                }
+            }
+            if opt.scan_type == ScanType::ConnectScan && matches.is_present("sourceport") {
+                println!("{} Source port is chosen by the OS in TCP connect scan.", "info:".cyan());
+                println!();
+            }
+            if opt.scan_type == ScanType::UdpScan && opt.src_port_specified && opt.parallelism > 1 && opt.targets.len() > 1 {
+                println!("{} A fixed UDP source port can only be bound once. Scanning one host at a time.", "info:".cyan());
+                println!();
+                opt.parallelism = 1;
+            }
+            if opt.os_detect && opt.scan_type != ScanType::SynScan {
+                println!("{} OS detection uses SYN/ACK replies and needs a SYN scan.", "info:".cyan());
+                println!();
+            }
+            handle_port_scan(opt).await;
         }
     }else if matches.is_present("host") {
         if !sys::check_root() {
@@ -130,9 +229,67 @@ async fn main() {
         }
         if let Some(v) = matches.value_of("host") {
             let mut opt = option::HostOption::new();
+            if let Some(i) = matches.value_of("interface") {
+                opt.set_if_name(i.to_string());
+            }
+            if let Some(x) = matches.value_of("exclude") {
+                opt.set_exclude(x.to_string());
+            }
             opt.set_option(v.to_string());
             if let Some(w) = matches.value_of("word") {
-                opt.set_file_path(w.to_string());
+                let errors = opt.set_file_path(w.to_string());
+                for e in &errors {
+                    println!("{} Skipped {}: {}", "warning:".yellow(), w, e);
+                }
+                if !errors.is_empty() {
+                    println!();
+                }
+            }
+            let target_name = if opt.use_wordlist { opt.wordlist_path.to_string() } else { opt.ip_addr.to_string() };
+            let host_count = opt.get_host_count();
+            if host_count == 0 && !opt.is_ipv6() {
+                if opt.excludes.is_empty() {
+                    println!("{} No targets in {}", "error:".red(), target_name);
+                }else{
+                    println!("{} All targets in {} are excluded", "error:".red(), target_name);
+                }
+                std::process::exit(0);
+            }
+            if host_count > sys::MAX_TARGET_HOSTS {
+                println!("{} Too many hosts in {} (max {})", "error:".red(), target_name, sys::MAX_TARGET_HOSTS);
+                std::process::exit(0);
+            }
+            if host_count > option::LARGE_HOST_SCAN && !matches.is_present("allowlarge") {
+                println!("{} {} has {} hosts. Use --allow-large to scan more than {} hosts.", "error:".red(), target_name, host_count, option::LARGE_HOST_SCAN);
+                std::process::exit(0);
+            }
+            opt.set_os_detect(matches.is_present("osdetect"));
+            opt.set_arp(matches.is_present("arp"));
+            opt.set_resolve(!matches.is_present("noresolve"));
+            opt.set_name_discovery(matches.is_present("namediscovery"));
+            if let Some(v) = matches.value_of("dnsserver") {
+                opt.set_dns_server(v.to_string());
+            }
+            if let Some(v) = matches.value_of("dnsparallel") {
+                opt.set_dns_parallelism(v.to_string());
+            }
+            if let Some(probes) = matches.values_of("probe") {
+                for v in probes {
+                    opt.add_probe(v.to_string());
+                }
+                if opt.arp || opt.is_ipv6() {
+                    println!("{} --probe applies to IPv4 ping scans. ARP and IPv6 discovery ignore it.", "info:".cyan());
+                    println!();
+                }
+            }
+            if let Some(v) = matches.value_of("timing") {
+                opt.set_timing(v.to_string());
+            }
+            if let Some(v) = matches.value_of("rate") {
+                opt.set_rate(v.to_string());
+            }
+            if let Some(v) = matches.value_of("maxretries") {
+                opt.set_max_retries(v.to_string());
             }
             if let Some(t) = matches.value_of("timeout") {
                 opt.set_timeout(t.to_string());
@@ -140,7 +297,7 @@ async fn main() {
             if let Some(s) = matches.value_of("save") {
                 opt.set_save_path(s.to_string());
             }
-            handle_host_scan(opt);
+            handle_host_scan(opt).await;
         }
     }else if matches.is_present("uri"){
         if let Some(v) = matches.value_of("uri") {
@@ -172,6 +329,126 @@ async fn main() {
             }
             handle_domain_scan(opt).await;
         }
+    }else if let Some(sub_matches) = matches.subcommand_matches("sweep") {
+        if let Some(v) = sub_matches.value_of("target") {
+            let mut host_opt = option::HostOption::new();
+            let mut port_opt = option::PortOption::new();
+            if let Some(i) = sub_matches.value_of("interface") {
+                host_opt.set_if_name(i.to_string());
+                port_opt.set_if_name(i.to_string());
+            }
+            if let Some(x) = sub_matches.value_of("exclude") {
+                host_opt.set_exclude(x.to_string());
+            }
+            host_opt.set_option(v.to_string());
+            if host_opt.is_ipv6() {
+                println!("{} Sweep needs IPv4 targets. IPv6 networks are discovered with -n.", "error:".red());
+                std::process::exit(0);
+            }
+            let host_count = host_opt.get_host_count();
+            if host_count == 0 {
+                println!("{} All targets in {} are excluded", "error:".red(), host_opt.ip_addr);
+                std::process::exit(0);
+            }
+            if host_count > sys::MAX_TARGET_HOSTS {
+                println!("{} Too many hosts in {} (max {})", "error:".red(), host_opt.ip_addr, sys::MAX_TARGET_HOSTS);
+                std::process::exit(0);
+            }
+            if host_count > option::LARGE_HOST_SCAN && !sub_matches.is_present("allowlarge") {
+                println!("{} {} has {} hosts. Use --allow-large to scan more than {} hosts.", "error:".red(), host_opt.ip_addr, host_count, option::LARGE_HOST_SCAN);
+                std::process::exit(0);
+            }
+            host_opt.set_arp(sub_matches.is_present("arp"));
+            host_opt.set_resolve(!sub_matches.is_present("noresolve"));
+            host_opt.set_name_discovery(sub_matches.is_present("namediscovery"));
+            if let Some(v) = sub_matches.value_of("dnsserver") {
+                host_opt.set_dns_server(v.to_string());
+            }
+            if let Some(v) = sub_matches.value_of("dnsparallel") {
+                host_opt.set_dns_parallelism(v.to_string());
+            }
+            if let Some(probes) = sub_matches.values_of("probe") {
+                for v in probes {
+                    host_opt.add_probe(v.to_string());
+                }
+                if host_opt.arp {
+                    println!("{} --probe applies to IPv4 ping scans. ARP discovery ignores it.", "info:".cyan());
+                    println!();
+                }
+            }
+            if let Some(v) = sub_matches.value_of("ports") {
+                if let Err(e) = port_opt.set_ports(v.to_string()) {
+                    println!("{} {}", "error:".red(), e);
+                    std::process::exit(0);
+                }
+            }
+            if let Some(n) = sub_matches.value_of("topports") {
+                port_opt.set_top_ports(n.to_string());
+            }
+            if let Some(st) = sub_matches.value_of("scantype") {
+                port_opt.set_scan_type(st.to_string());
+            }
+            port_opt.set_service_detect(sub_matches.is_present("servicedetect"));
+            port_opt.set_tls_inspect(sub_matches.is_present("tlsinspect"));
+            port_opt.set_os_detect(sub_matches.is_present("osdetect"));
+            if let Some(v) = sub_matches.value_of("show") {
+                port_opt.set_show_states(v.to_string());
+            }
+            if let Some(p) = sub_matches.value_of("parallel") {
+                port_opt.set_parallelism(p.to_string());
+            }
+            if let Some(v) = sub_matches.value_of("timing") {
+                host_opt.set_timing(v.to_string());
+                port_opt.set_timing(v.to_string());
+            }
+            if let Some(v) = sub_matches.value_of("rate") {
+                host_opt.set_rate(v.to_string());
+                port_opt.set_rate(v.to_string());
+            }
+            if let Some(v) = sub_matches.value_of("maxretries") {
+                host_opt.set_max_retries(v.to_string());
+                port_opt.set_max_retries(v.to_string());
+            }
+            if let Some(v) = sub_matches.value_of("sourceport") {
+                port_opt.set_source_port(v.to_string());
+            }
+            if let Some(t) = sub_matches.value_of("timeout") {
+                host_opt.set_timeout(t.to_string());
+                port_opt.set_timeout(t.to_string());
+            }
+            if let Some(s) = sub_matches.value_of("save") {
+                port_opt.set_save_path(s.to_string());
+            }
+            if !sys::check_root() {
+                if port_opt.scan_type == ScanType::SynScan {
+                    println!("{} Raw sockets are not available. Falling back to TCP connect scan.", "info:".cyan());
+                    port_opt.scan_type = ScanType::ConnectScan;
+                }else if port_opt.scan_type != ScanType::ConnectScan {
+                    println!("{} This feature requires administrator privileges. ","error:".red());
+                    std::process::exit(0);
+                }
+                if host_opt.arp || !host_opt.probes.is_empty() {
+                    println!("{} --arp and --probe need raw sockets and are ignored.", "info:".cyan());
+                }
+                println!("{} Hosts are discovered by the TCP connect scan. Up hosts answer with an open or closed port.", "info:".cyan());
+                println!();
+                host_opt.set_connect_discovery(true);
+            }
+            if port_opt.scan_type == ScanType::ConnectScan && sub_matches.is_present("sourceport") {
+                println!("{} Source port is chosen by the OS in TCP connect scan.", "info:".cyan());
+                println!();
+            }
+            if port_opt.scan_type == ScanType::UdpScan && port_opt.src_port_specified && port_opt.parallelism > 1 {
+                println!("{} A fixed UDP source port can only be bound once. Scanning one host at a time.", "info:".cyan());
+                println!();
+                port_opt.parallelism = 1;
+            }
+            if port_opt.os_detect && port_opt.scan_type != ScanType::SynScan {
+                println!("{} OS detection uses SYN/ACK replies and needs a SYN scan.", "info:".cyan());
+                println!();
+            }
+            handle_sweep(host_opt, port_opt).await;
+        }
     }else{
         println!();
         println!("Error: Scan mode not specified.");
@@ -185,21 +462,28 @@ fn get_app_settings<'a, 'b>() -> App<'a, 'b> {
         .author(CRATE_AUTHOR_GITHUB)
         .about(crate_description!())
         .arg(Arg::with_name("port")
-            .help("Port Scan - Ex: -p 192.168.1.8:1-1000")
+            .help("Port Scan - Ex: -p 192.168.1.8:1-1000, -p 192.168.1.0/28:22,80,8000-8100, -p [2001:db8::1]:1-1000")
             .short("p")
             .long("port")
             .takes_value(true)
-            .value_name("ip_addr:port_range")
+            .value_name("target:ports")
             .validator(validator::validate_port_opt)
         )
         .arg(Arg::with_name("host")
-            .help("Scan hosts in specified network - Ex: -n 192.168.1.0")
+            .help("Scan hosts in specified networks or ranges - Ex: -n 192.168.1.0, -n 10.0.0.0/22,10.0.1.10-50, -n fe80::/64 (IPv6 finds hosts answering multicast echo)")
             .short("n")
             .long("host")
             .takes_value(true)
             .value_name("ip_addr")
             .validator(validator::validate_host_opt)
         )
+        .arg(Arg::with_name("exclude")
+            .help("Skip addresses, ranges, networks or addresses listed in a file - Ex: --exclude 192.168.1.1,192.168.1.200-254")
+            .long("exclude")
+            .takes_value(true)
+            .value_name("targets")
+            .validator(validator::validate_exclude)
+        )
         .arg(Arg::with_name("uri")
             .help("URI Scan - Ex: -u http://192.168.1.8/xvwa/ -w common.txt")
             .short("u")
@@ -215,6 +499,117 @@ fn get_app_settings<'a, 'b>() -> App<'a, 'b> {
             .value_name("domain_name")
             .validator(validator::validate_domain_opt)
         )
+        .arg(Arg::with_name("scantype")
+            .help("Set port scan type (syn, fin, xmas, null, connect, udp) - Ex: --scan-type fin")
+            .long("scan-type")
+            .takes_value(true)
+            .value_name("type")
+            .validator(validator::validate_scan_type)
+        )
+        .arg(Arg::with_name("topports")
+            .help("Scan the N most common ports - Ex: --top-ports 100")
+            .long("top-ports")
+            .takes_value(true)
+            .value_name("num")
+            .validator(validator::validate_top_ports)
+        )
+        .arg(Arg::with_name("timing")
+            .help("Set timing template (paranoid, sneaky, polite, normal, aggressive, insane or 0-5)")
+            .long("timing")
+            .takes_value(true)
+            .value_name("template")
+            .validator(validator::validate_timing)
+        )
+        .arg(Arg::with_name("rate")
+            .help("Limit probes sent per second")
+            .long("rate")
+            .takes_value(true)
+            .value_name("pps")
+            .validator(validator::validate_rate)
+        )
+        .arg(Arg::with_name("maxretries")
+            .help("Set number of retransmissions of unanswered probes")
+            .long("max-retries")
+            .takes_value(true)
+            .value_name("num")
+            .validator(validator::validate_max_retries)
+        )
+        .arg(Arg::with_name("sourceport")
+            .help("Set source port of probes, or random for a new port per probe - Ex: --source-port 53")
+            .long("source-port")
+            .takes_value(true)
+            .value_name("port")
+            .validator(validator::validate_source_port)
+        )
+        .arg(Arg::with_name("randomizeports")
+            .help("Probe ports in random order")
+            .long("randomize-ports")
+        )
+        .arg(Arg::with_name("show")
+            .help("Also report ports in given states (closed, filtered, all)")
+            .long("show")
+            .takes_value(true)
+            .value_name("states")
+            .validator(validator::validate_show_states)
+        )
+        .arg(Arg::with_name("servicedetect")
+            .help("Detect service and version on open ports")
+            .long("service-detect")
+        )
+        .arg(Arg::with_name("tlsinspect")
+            .help("Inspect TLS certificates on open ports")
+            .long("tls-inspect")
+        )
+        .arg(Arg::with_name("probe")
+            .help("Host discovery probe, repeatable - Ex: --probe syn:22,443 --probe icmp (icmp, timestamp, syn, ack, udp)")
+            .long("probe")
+            .takes_value(true)
+            .value_name("method")
+            .multiple(true)
+            .number_of_values(1)
+            .validator(validator::validate_probe)
+        )
+        .arg(Arg::with_name("noresolve")
+            .help("Do not look up host names of discovered hosts")
+            .long("no-resolve")
+        )
+        .arg(Arg::with_name("namediscovery")
+            .help("Ask discovered hosts for names, model and services with mDNS, NetBIOS and SSDP")
+            .long("name-discovery")
+        )
+        .arg(Arg::with_name("dnsserver")
+            .help("Set DNS server for reverse lookups, instead of the system resolver - Ex: --dns-server 192.168.1.1")
+            .long("dns-server")
+            .takes_value(true)
+            .value_name("ip_addr")
+            .validator(validator::validate_dns_server)
+        )
+        .arg(Arg::with_name("dnsparallel")
+            .help("Set number of reverse lookups run in parallel - Ex: --dns-parallel 32")
+            .long("dns-parallel")
+            .takes_value(true)
+            .value_name("num")
+            .validator(validator::validate_parallel)
+        )
+        .arg(Arg::with_name("arp")
+            .help("Discover hosts on the local subnet with ARP instead of ICMP echo")
+            .long("arp")
+        )
+        .arg(Arg::with_name("allowlarge")
+            .help("Allow host scan of networks with more than 4096 hosts")
+            .long("allow-large")
+        )
+        .arg(Arg::with_name("osdetect")
+            .help("Guess OS family of hosts from reply TTL and TCP options")
+            .long("os-detect")
+        )
+        .arg(Arg::with_name("parallel")
+            .help("Set number of hosts port scanned in parallel - Ex: --parallel 16")
+            .long("parallel")
+            .takes_value(true)
+            .value_name("num")
+            .validator(validator::validate_parallel)
+        )
         .arg(Arg::with_name("timeout")
             .help("Set timeout in ms - Ex: -t 10000")
             .short("t")
@@ -232,7 +627,7 @@ fn get_app_settings<'a, 'b>() -> App<'a, 'b> {
             .validator(validator::validate_interface)
         )
         .arg(Arg::with_name("word")
-            .help("Use word list - Ex: -w common.txt")
+            .help("Use word list (port list for -p, host list for -n) - Ex: -w common.txt")
             .short("w")
             .long("word")
             .takes_value(true)
@@ -261,8 +656,23 @@ fn get_app_settings<'a, 'b>() -> App<'a, 'b> {
                 .value_name("file_path")
                 .validator(validator::validate_filepath)
             )
+            .arg(Arg::with_name("probe")
+                .help("Update service probe data")
+                .short("r")
+                .long("probe")
+                .takes_value(true)
+                .value_name("file_path")
+                .validator(validator::validate_filepath)
+            )
+            .arg(Arg::with_name("os")
+                .help("Update OS signature data")
+                .long("os")
+                .takes_value(true)
+                .value_name("file_path")
+                .validator(validator::validate_filepath)
+            )
             .arg(Arg::with_name("oui")
-                .help("Update oui data")
+                .help("Update oui data from an IEEE registry CSV or manuf file")
                 .short("o")
                 .long("oui")
                 .takes_value(true)
@@ -270,6 +680,159 @@ fn get_app_settings<'a, 'b>() -> App<'a, 'b> {
                 .validator(validator::validate_filepath)
             )
         )
+        .subcommand(SubCommand::with_name("sweep")
+            .about("Discover up hosts, then port scan each of them - Ex: nscan sweep 192.168.1.0/24 --ports 1-1024")
+            .arg(Arg::with_name("target")
+                .help("Networks or ranges to sweep - Ex: 192.168.1.0/24, 10.0.0.10-50")
+                .required(true)
+                .index(1)
+                .validator(validator::validate_host_opt)
+            )
+            .arg(Arg::with_name("ports")
+                .help("Ports to scan on each up host - Ex: --ports 22,80,8000-8100")
+                .long("ports")
+                .takes_value(true)
+                .value_name("ports")
+                .validator(validator::validate_port_list)
+            )
+            .arg(Arg::with_name("topports")
+                .help("Scan the N most common ports - Ex: --top-ports 100")
+                .long("top-ports")
+                .takes_value(true)
+                .value_name("num")
+                .validator(validator::validate_top_ports)
+            )
+            .arg(Arg::with_name("scantype")
+                .help("Set port scan type (syn, fin, xmas, null, connect, udp) - Ex: --scan-type fin")
+                .long("scan-type")
+                .takes_value(true)
+                .value_name("type")
+                .validator(validator::validate_scan_type)
+            )
+            .arg(Arg::with_name("servicedetect")
+                .help("Detect service and version on open ports")
+                .long("service-detect")
+            )
+            .arg(Arg::with_name("tlsinspect")
+                .help("Inspect TLS certificates on open ports")
+                .long("tls-inspect")
+            )
+            .arg(Arg::with_name("osdetect")
+                .help("Guess OS family of up hosts from SYN/ACK replies of the SYN scan")
+                .long("os-detect")
+            )
+            .arg(Arg::with_name("show")
+                .help("Also report ports in given states (closed, filtered, all)")
+                .long("show")
+                .takes_value(true)
+                .value_name("states")
+                .validator(validator::validate_show_states)
+            )
+            .arg(Arg::with_name("exclude")
+                .help("Skip addresses, ranges, networks or addresses listed in a file - Ex: --exclude 192.168.1.1")
+                .long("exclude")
+                .takes_value(true)
+                .value_name("targets")
+                .validator(validator::validate_exclude)
+            )
+            .arg(Arg::with_name("arp")
+                .help("Discover hosts on the local subnet with ARP instead of ICMP echo")
+                .long("arp")
+            )
+            .arg(Arg::with_name("probe")
+                .help("Host discovery probe, repeatable - Ex: --probe syn:22,443 --probe icmp")
+                .long("probe")
+                .takes_value(true)
+                .value_name("method")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validator::validate_probe)
+            )
+            .arg(Arg::with_name("noresolve")
+                .help("Do not look up host names of discovered hosts")
+                .long("no-resolve")
+            )
+            .arg(Arg::with_name("namediscovery")
+                .help("Ask discovered hosts for names, model and services with mDNS, NetBIOS and SSDP")
+                .long("name-discovery")
+            )
+            .arg(Arg::with_name("dnsserver")
+                .help("Set DNS server for reverse lookups - Ex: --dns-server 192.168.1.1")
+                .long("dns-server")
+                .takes_value(true)
+                .value_name("ip_addr")
+                .validator(validator::validate_dns_server)
+            )
+            .arg(Arg::with_name("dnsparallel")
+                .help("Set number of reverse lookups run in parallel - Ex: --dns-parallel 32")
+                .long("dns-parallel")
+                .takes_value(true)
+                .value_name("num")
+                .validator(validator::validate_parallel)
+            )
+            .arg(Arg::with_name("allowlarge")
+                .help("Allow sweep of networks with more than 4096 hosts")
+                .long("allow-large")
+            )
+            .arg(Arg::with_name("parallel")
+                .help("Set number of hosts port scanned in parallel - Ex: --parallel 16")
+                .long("parallel")
+                .takes_value(true)
+                .value_name("num")
+                .validator(validator::validate_parallel)
+            )
+            .arg(Arg::with_name("timing")
+                .help("Set timing template (paranoid, sneaky, polite, normal, aggressive, insane or 0-5)")
+                .long("timing")
+                .takes_value(true)
+                .value_name("template")
+                .validator(validator::validate_timing)
+            )
+            .arg(Arg::with_name("rate")
+                .help("Limit probes sent per second, in discovery and in port scans")
+                .long("rate")
+                .takes_value(true)
+                .value_name("pps")
+                .validator(validator::validate_rate)
+            )
+            .arg(Arg::with_name("maxretries")
+                .help("Set number of retransmissions of unanswered probes")
+                .long("max-retries")
+                .takes_value(true)
+                .value_name("num")
+                .validator(validator::validate_max_retries)
+            )
+            .arg(Arg::with_name("sourceport")
+                .help("Set source port of port scan probes, or random for a new port per probe - Ex: --source-port 53")
+                .long("source-port")
+                .takes_value(true)
+                .value_name("port")
+                .validator(validator::validate_source_port)
+            )
+            .arg(Arg::with_name("timeout")
+                .help("Set timeout of each stage in ms - Ex: -t 10000")
+                .short("t")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("duration")
+                .validator(validator::validate_timeout)
+            )
+            .arg(Arg::with_name("interface")
+                .help("Specify network interface by name - Ex: -i en0")
+                .short("i")
+                .long("interface")
+                .takes_value(true)
+                .value_name("name")
+                .validator(validator::validate_interface)
+            )
+            .arg(Arg::with_name("save")
+                .help("Save sweep result to file - Ex: -s result.txt")
+                .short("s")
+                .long("save")
+                .takes_value(true)
+                .value_name("file_path")
+            )
+        )
         .group(ArgGroup::with_name("mode")
             .args(&["port", "host", "uri", "domain"])
         )
@@ -301,7 +864,7 @@ fn show_banner_with_starttime() {
 }
 
 // handler 
-fn handle_port_scan(opt: option::PortOption) {
+async fn handle_port_scan(mut opt: option::PortOption) {
     let conn = match db::get_db_connection() {
         Ok(conn) => conn,
         Err(e) => {
@@ -309,50 +872,457 @@ fn handle_port_scan(opt: option::PortOption) {
             return;
         },
     };
+    if let Err(e) = add_listed_ports(&conn, &mut opt) {
+        println!("{}: {}", "Error".red(), e);
+        return;
+    }
+    let probe_count = opt.ports.len();
+    opt.adjust_timeout(probe_count);
     opt.show_options();
     println!();
     print!("Scanning... ");
     stdout().flush().unwrap();
-    let mut if_name: Option<&str> = None;
-    if !opt.if_name.is_empty(){
-        if_name = Some(&opt.if_name);
-    }
-    let mut port_scanner = match PortScanner::new(None, if_name){
-        Ok(scanner) => (scanner),
-        Err(e) => panic!("Error creating scanner: {}", e),
-    };
-    port_scanner.set_target_ipaddr(&opt.ip_addr);
-    port_scanner.set_range(opt.start_port, opt.end_port);
-    port_scanner.set_scan_type(PortScanType::SynScan);
-    port_scanner.set_timeout(opt.timeout);
-    port_scanner.run_scan();
-    let result = port_scanner.get_result();
-    match result.scan_status {
+    let start_time = Instant::now();
+    let opt = Arc::new(opt);
+    let results = run_port_scan(&opt).await;
+    match get_port_scan_status(&results) {
         ScanStatus::Done => {println!("{}", "Done".green())},
         ScanStatus::Timeout => {println!("{}", "Timed out".yellow())},
         _ => {println!("{}", "Error".red())},
     }
     println!();
+    let mut services: HashMap<IpAddr, Vec<(u16, ServiceInfo)>> = HashMap::new();
+    if opt.service_detect && opt.scan_type != ScanType::UdpScan {
+        print!("Detecting services... ");
+        stdout().flush().unwrap();
+        services = run_service_detection(&conn, &opt, &results).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut certs: HashMap<IpAddr, Vec<(u16, TlsInfo)>> = HashMap::new();
+    let tls_targets = if !opt.tls_inspect || opt.scan_type == ScanType::UdpScan { vec![] } else { get_tls_targets(&results, &services) };
+    if !tls_targets.is_empty() {
+        print!("Inspecting TLS... ");
+        stdout().flush().unwrap();
+        certs = run_tls_inspection(&opt, tls_targets).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut os_guesses: HashMap<IpAddr, OsGuess> = HashMap::new();
+    if opt.os_detect {
+        let fingerprints = results.iter().filter_map(|(ip_addr, result)| Some((*ip_addr, result.fingerprint.clone()?))).collect();
+        os_guesses = run_os_detection(&conn, fingerprints);
+    }
     sys::print_fix32("Scan Reports", sys::FillStr::Hyphen);
-    for port in result.open_ports {
-        match db::get_service(&conn, &port, "tcp"){
+    for (ip_addr, result) in &results {
+        println!("{}", ip_addr.to_string().cyan());
+        println!("{}{}", SPACE4, get_state_summary(result));
+        if let Some(rtt) = result.rtt {
+            println!("{}RTT: {}", SPACE4, format_rtt(rtt));
+        }
+        if opt.os_detect {
+            match os_guesses.get(ip_addr) {
+                Some(guess) => println!("{}OS: {}", SPACE4, guess.get_detail()),
+                None => println!("{}OS: Unknown", SPACE4),
+            }
+        }
+        print_port_result(&conn, &opt, ip_addr, result, &services, &certs);
+    }
+    sys::print_fix32("", sys::FillStr::Hyphen);
+    println!("Scan Time: {:?}", Instant::now().duration_since(start_time));
+    if !opt.save_path.is_empty() {
+        save_port_result(&conn, &opt, results, &services, &certs, &os_guesses);
+    }
+}
+
+// Status of the whole port scan. Done only if every host is done.
+fn get_port_scan_status(results: &Vec<(IpAddr, PortStateResult)>) -> ScanStatus {
+    let mut scan_status = ScanStatus::Done;
+    for (_, result) in results {
+        match result.scan_status {
+            ScanStatus::Done => {},
+            status => {scan_status = status},
+        }
+    }
+    scan_status
+}
+
+// Add ports from the port list file and the top ports. Without either, the default number of top ports is scanned.
+fn add_listed_ports(conn: &rusqlite::Connection, opt: &mut option::PortOption) -> Result<(), String> {
+    if opt.use_wordlist {
+        let ports = read_port_list_file(conn, &opt.wordlist_path, &opt.get_protocol())?;
+        opt.add_ports(ports);
+    }
+    if opt.ports.is_empty() && opt.top_ports == 0 {
+        opt.top_ports = DEFAULT_TOP_PORTS;
+    }
+    if opt.top_ports > 0 {
+        match db::get_top_ports(conn, &opt.get_protocol(), opt.top_ports) {
+            Ok(ports) => opt.add_ports(ports),
+            Err(e) => return Err(format!("Failed to get top ports. {}", e)),
+        }
+    }
+    Ok(())
+}
+
+// Reported ports of a host with their services and certificates
+fn print_port_result(conn: &rusqlite::Connection, opt: &option::PortOption, ip_addr: &IpAddr, result: &PortStateResult, services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>, certs: &HashMap<IpAddr, Vec<(u16, TlsInfo)>>) {
+    let protocol = opt.get_protocol();
+    for (port, state) in get_reported_ports(opt, result) {
+        let detected = get_detected_service(services, ip_addr, port);
+        match db::get_service(conn, &port.to_string(), &protocol){
             Ok(service) => {
-                print_service(service);
+                print_service(service, state.name(), detected);
             },
             Err(_) => {
-                println!("{}{}{}Unknown service", SPACE4, port.cyan(), SPACE4);
+                print_unknown_service(port.to_string(), state.name(), detected);
             }, 
         };
+        if let Some(info) = get_tls_info(certs, ip_addr, port) {
+            print_tls_info(info);
+        }
     }
-    sys::print_fix32("", sys::FillStr::Hyphen);
-    println!("Scan Time: {:?}", result.scan_time);
-    if !opt.save_path.is_empty() {
-        let s_result = port_scanner.get_result();
-        save_port_result(&conn, &opt, s_result);
+}
+
+// Open TCP ports on well-known TLS ports or detected as TLS
+fn get_tls_targets(results: &Vec<(IpAddr, PortStateResult)>, services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>) -> Vec<SocketAddr> {
+    let mut targets: Vec<SocketAddr> = vec![];
+    for (ip_addr, result) in results {
+        for (port, state) in &result.ports {
+            if *state != PortState::Open {
+                continue;
+            }
+            let detected_tls = match get_detected_service(services, ip_addr, *port) {
+                Some(info) => info.service_name == "ssl",
+                None => false,
+            };
+            if detected_tls || tls::TLS_PORTS.contains(port) {
+                targets.push(SocketAddr::new(*ip_addr, *port));
+            }
+        }
+    }
+    targets
+}
+
+async fn run_tls_inspection(opt: &option::PortOption, targets: Vec<SocketAddr>) -> HashMap<IpAddr, Vec<(u16, TlsInfo)>> {
+    let server_name = if sys::is_hostname(&opt.ip_addr) { opt.ip_addr.to_string() } else { String::new() };
+    let timeout = opt.timeout;
+    let results: Vec<(SocketAddr, Result<TlsInfo, String>)> = stream::iter(targets)
+        .map(|addr| {
+            let server_name = server_name.clone();
+            async move {
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut inspector = TlsInspector::new(addr.ip());
+                    inspector.set_server_name(server_name);
+                    inspector.set_timeout(timeout);
+                    inspector.get_tls_info(addr.port())
+                }).await;
+                match result {
+                    Ok(result) => (addr, result),
+                    Err(e) => (addr, Err(format!("{}", e))),
+                }
+            }
+        })
+        .buffer_unordered(opt.parallelism)
+        .collect().await;
+    let mut certs: HashMap<IpAddr, Vec<(u16, TlsInfo)>> = HashMap::new();
+    for (addr, result) in results {
+        match result {
+            Ok(info) => certs.entry(addr.ip()).or_insert(vec![]).push((addr.port(), info)),
+            Err(e) => warn!("TLS handshake failed on {}: {}", addr, e),
+        }
+    }
+    certs
+}
+
+fn get_tls_info<'a>(certs: &'a HashMap<IpAddr, Vec<(u16, TlsInfo)>>, ip_addr: &IpAddr, port: u16) -> Option<&'a TlsInfo> {
+    match certs.get(ip_addr) {
+        Some(host_certs) => host_certs.iter().find(|(p, _)| *p == port).map(|(_, info)| info),
+        None => None,
+    }
+}
+
+async fn run_service_detection(conn: &rusqlite::Connection, opt: &option::PortOption, results: &Vec<(IpAddr, PortStateResult)>) -> HashMap<IpAddr, Vec<(u16, ServiceInfo)>> {
+    let mut services: HashMap<IpAddr, Vec<(u16, ServiceInfo)>> = HashMap::new();
+    let probes = match db::get_service_probes(conn) {
+        Ok(probes) => probes,
+        Err(e) => {
+            println!("{}: Failed to load service probes. {}", "Error".red(), e);
+            return services;
+        },
+    };
+    let mut detector = ServiceDetector::new(probes);
+    detector.set_timeout(opt.timeout);
+    for (ip_addr, result) in results {
+        let open_ports: Vec<u16> = result.ports.iter().filter(|(_, state)| *state == PortState::Open).map(|(port, _)| *port).collect();
+        if !open_ports.is_empty() {
+            services.insert(*ip_addr, detector.run_detection(*ip_addr, open_ports).await);
+        }
+    }
+    services
+}
+
+// Look up host names of the addresses, skipping those without a PTR record
+async fn run_reverse_lookup(opt: &option::HostOption, hosts: Vec<IpAddr>) -> HashMap<IpAddr, String> {
+    let dns_server = opt.dns_server;
+    let timeout = opt.timeout;
+    let results: Vec<(IpAddr, Result<String, String>)> = stream::iter(hosts)
+        .map(|ip_addr| {
+            async move {
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut resolver = ReverseResolver::new();
+                    resolver.set_timeout(timeout);
+                    if let Some(dns_server) = dns_server {
+                        resolver.set_dns_server(dns_server);
+                    }
+                    resolver.lookup(ip_addr)
+                }).await;
+                match result {
+                    Ok(result) => (ip_addr, result),
+                    Err(e) => (ip_addr, Err(format!("{}", e))),
+                }
+            }
+        })
+        .buffer_unordered(opt.dns_parallelism)
+        .collect().await;
+    let mut hostnames: HashMap<IpAddr, String> = HashMap::new();
+    for (ip_addr, result) in results {
+        match result {
+            Ok(name) => {hostnames.insert(ip_addr, name);},
+            Err(e) => debug!("Reverse lookup failed for {}: {}", ip_addr, e),
+        }
+    }
+    hostnames
+}
+
+// Ask each host for its mDNS, NetBIOS and UPnP names, skipping hosts that answer none of them
+async fn run_local_name_discovery(opt: &option::HostOption, hosts: Vec<IpAddr>) -> HashMap<IpAddr, LocalNameInfo> {
+    let timeout = opt.timeout;
+    let results: Vec<(IpAddr, Result<LocalNameInfo, String>)> = stream::iter(hosts)
+        .map(|ip_addr| {
+            async move {
+                let result = tokio::task::spawn_blocking(move || {
+                    let mut resolver = LocalNameResolver::new();
+                    resolver.set_timeout(timeout);
+                    resolver.lookup(ip_addr)
+                }).await;
+                match result {
+                    Ok(info) => (ip_addr, Ok(info)),
+                    Err(e) => (ip_addr, Err(format!("{}", e))),
+                }
+            }
+        })
+        .buffer_unordered(opt.dns_parallelism)
+        .collect().await;
+    let mut local_names: HashMap<IpAddr, LocalNameInfo> = HashMap::new();
+    for (ip_addr, result) in results {
+        match result {
+            Ok(info) if !info.is_empty() => {local_names.insert(ip_addr, info);},
+            Ok(_) => {},
+            Err(e) => debug!("Name discovery failed for {}: {}", ip_addr, e),
+        }
+    }
+    local_names
+}
+
+// Guess OS family of each host from the header values of its replies
+fn run_os_detection(conn: &rusqlite::Connection, fingerprints: Vec<(IpAddr, OsFingerprint)>) -> HashMap<IpAddr, OsGuess> {
+    let signatures = match db::get_os_signatures(conn) {
+        Ok(signatures) => signatures,
+        Err(e) => {
+            error!("Failed to get OS signatures. {}", e);
+            vec![]
+        },
+    };
+    let detector = OsDetector::new(signatures);
+    let mut os_guesses: HashMap<IpAddr, OsGuess> = HashMap::new();
+    for (ip_addr, fingerprint) in fingerprints {
+        os_guesses.insert(ip_addr, detector.guess(&fingerprint));
+    }
+    os_guesses
+}
+
+fn get_detected_service(services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>, ip_addr: &IpAddr, port: u16) -> Option<ServiceInfo> {
+    match services.get(ip_addr) {
+        Some(host_services) => host_services.iter().find(|(p, _)| *p == port).map(|(_, info)| info.clone()),
+        None => None,
+    }
+}
+
+// Each line is a port, a port range or a service name. '#' starts a comment.
+fn read_port_list_file(conn: &rusqlite::Connection, file_path: &str, protocol: &str) -> Result<Vec<u16>, String> {
+    let text = match read_to_string(file_path) {
+        Ok(content) => content,
+        Err(e) => return Err(format!("Could not open or find file: {}", e)),
+    };
+    let mut ports: Vec<u16> = vec![];
+    let mut errors: Vec<String> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let word = line.split("#").next().unwrap_or("").trim();
+        if word.is_empty() {
+            continue;
+        }
+        // Some service names start with a digit (9pfs, 3com-tsmux), so names are looked up
+        // whenever the word is not a port list
+        let parse_error = match sys::parse_port_list(word) {
+            Ok(p) => {
+                ports.extend(p);
+                continue;
+            },
+            Err(e) => e,
+        };
+        match db::get_service_ports(conn, word, protocol) {
+            Ok(p) if !p.is_empty() => ports.extend(p),
+            Ok(_) => {
+                if word.chars().next().map_or(false, |c| c.is_ascii_digit()) {
+                    errors.push(format!("{}:{}: {}", file_path, i + 1, parse_error));
+                }else{
+                    errors.push(format!("{}:{}: Unknown {} service name: {}", file_path, i + 1, protocol, word));
+                }
+            },
+            Err(e) => errors.push(format!("{}:{}: {}", file_path, i + 1, e)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!("Invalid port list\n{}", errors.join("\n")));
+    }
+    Ok(ports)
+}
+
+// Scan each target host with at most `opt.parallelism` hosts in flight.
+async fn run_port_scan(opt: &Arc<option::PortOption>) -> Vec<(IpAddr, PortStateResult)> {
+    let mut results: Vec<(IpAddr, PortStateResult)> = stream::iter(opt.targets.clone())
+        .map(|ip_addr| {
+            let opt = Arc::clone(opt);
+            async move {
+                let result = match opt.scan_type {
+                    ScanType::ConnectScan => run_connect_port_scan(&opt, ip_addr).await,
+                    ScanType::UdpScan => {
+                        run_blocking(move || run_udp_port_scan(&opt, ip_addr)).await
+                    },
+                    _ => {
+                        run_blocking(move || run_raw_port_scan(&opt, ip_addr)).await
+                    },
+                };
+                (ip_addr, result)
+            }
+        })
+        .buffer_unordered(opt.parallelism)
+        .collect().await;
+    results.sort_by_key(|(ip_addr, _)| *ip_addr);
+    results
+}
+
+async fn run_blocking<F>(f: F) -> PortStateResult where F: FnOnce() -> PortStateResult + Send + 'static {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) => panic!("Error running scan: {}", e),
+    }
+}
+
+fn run_raw_port_scan(opt: &option::PortOption, ip_addr: IpAddr) -> PortStateResult {
+    let scan_type = match opt.scan_type.to_raw_scan_type() {
+        Some(scan_type) => scan_type,
+        None => nerve::PortScanType::SynScan,
+    };
+    let mut port_scanner = TcpScanner::new(ip_addr);
+    if !opt.if_name.is_empty() {
+        match get_interface_ipaddr(&opt.if_name, ip_addr) {
+            Some((interface, src_ipaddr)) => {
+                port_scanner.set_interface(interface);
+                port_scanner.set_src_ipaddr(src_ipaddr);
+            },
+            None => return get_failed_result(&format!("No address of target IP version on interface {}", opt.if_name)),
+        }
+    }
+    port_scanner.set_src_port(opt.app_port);
+    port_scanner.set_random_src_port(opt.random_src_port);
+    port_scanner.set_scan_type(scan_type);
+    port_scanner.set_ports(opt.get_probe_ports());
+    port_scanner.set_timing(opt.timing.clone());
+    port_scanner.set_timeout(opt.timeout);
+    match port_scanner.run_scan() {
+        Ok(result) => result,
+        Err(e) => get_failed_result(&e),
+    }
+}
+
+// Result of a host that could not be scanned
+fn get_failed_result(e: &str) -> PortStateResult {
+    error!("{}", e);
+    PortStateResult {
+        ports: vec![],
+        rtt: None,
+        fingerprint: None,
+        scan_status: ScanStatus::Error,
+    }
+}
+
+// Interface and its address to use as source. For IPv6, a link-local
+// source is only used for link-local targets.
+fn get_interface_ipaddr(if_name: &str, target_ipaddr: IpAddr) -> Option<(pnet::datalink::NetworkInterface, IpAddr)> {
+    let iface = pnet::datalink::interfaces().into_iter().find(|iface| iface.name == if_name)?;
+    let ips: Vec<IpAddr> = iface.ips.iter().map(|ip| ip.ip()).filter(|ip| ip.is_ipv6() == target_ipaddr.is_ipv6()).collect();
+    let src_ipaddr = match target_ipaddr {
+        IpAddr::V4(_) => ips.first().cloned(),
+        IpAddr::V6(_) => {
+            let target_link_local = is_link_local(&target_ipaddr);
+            ips.iter().cloned().find(|ip| is_link_local(ip) == target_link_local).or(ips.first().cloned())
+        },
+    }?;
+    Some((iface, src_ipaddr))
+}
+
+// Interface given with -i, or the interface of the default route
+fn get_host_scan_interface(if_name: &str) -> Result<pnet::datalink::NetworkInterface, String> {
+    let interfaces = pnet::datalink::interfaces();
+    if !if_name.is_empty() {
+        return match interfaces.into_iter().find(|iface| iface.name == if_name) {
+            Some(iface) => Ok(iface),
+            None => Err(format!("Interface {} not found", if_name)),
+        };
+    }
+    let if_index = match interface::get_default_interface_index() {
+        Some(if_index) => if_index,
+        None => return Err(String::from("Failed to get default interface. Specify one with -i")),
+    };
+    match interfaces.into_iter().find(|iface| iface.index == if_index) {
+        Some(iface) => Ok(iface),
+        None => Err(String::from("Failed to get default interface. Specify one with -i")),
+    }
+}
+
+fn is_link_local(ip_addr: &IpAddr) -> bool {
+    match ip_addr {
+        IpAddr::V4(ipv4_addr) => ipv4_addr.is_link_local(),
+        IpAddr::V6(ipv6_addr) => ipv6_addr.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+async fn run_connect_port_scan(opt: &option::PortOption, ip_addr: IpAddr) -> PortStateResult {
+    let mut connect_scanner = ConnectScanner::new(ip_addr);
+    connect_scanner.set_ports(opt.get_probe_ports());
+    connect_scanner.set_timing(opt.timing.clone());
+    connect_scanner.set_timeout(opt.timeout);
+    connect_scanner.run_scan().await
+}
+
+fn run_udp_port_scan(opt: &option::PortOption, ip_addr: IpAddr) -> PortStateResult {
+    let mut udp_scanner = UdpScanner::new(ip_addr);
+    // Without --source-port the OS picks a free port, so hosts can be scanned in parallel
+    if opt.src_port_specified {
+        udp_scanner.set_src_port(opt.app_port);
+    }
+    udp_scanner.set_ports(opt.get_probe_ports());
+    udp_scanner.set_timing(opt.timing.clone());
+    udp_scanner.set_timeout(opt.timeout);
+    match udp_scanner.run_scan() {
+        Ok(result) => result,
+        Err(e) => get_failed_result(&e),
     }
 }
 
-fn handle_host_scan(opt: option::HostOption) {
+async fn handle_host_scan(mut opt: option::HostOption) {
     let conn = match db::get_db_connection() {
         Ok(conn) => conn,
         Err(e) => {
@@ -364,113 +1334,383 @@ fn handle_host_scan(opt: option::HostOption) {
     println!();
     print!("Scanning...");
     stdout().flush().unwrap();
-    let mut host_scanner = match HostScanner::new(){
-        Ok(scanner) => (scanner),
-        Err(e) => panic!("Error creating scanner: {}", e),
+    let interface = match get_host_scan_interface(&opt.if_name) {
+        Ok(interface) => interface,
+        Err(e) => {
+            println!("{}", "Error".red());
+            println!("{}: {}", "Error".red(), e);
+            return;
+        },
+    };
+    let result = match run_host_discovery(&mut opt, &interface) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("{}", "Error".red());
+            println!("{}: {}", "Error".red(), e);
+            return;
+        },
     };
-    if opt.scan_host_addr {
-        let addr = IpAddr::from_str(&opt.ip_addr);
-        match addr {
-            Ok(ip_addr) => {
-                match ip_addr {
-                    IpAddr::V4(ipv4_addr) => {
-                        let net: Ipv4Net = Ipv4Net::new(ipv4_addr, 24).unwrap();
-                        let nw_addr = Ipv4Net::new(net.network(), 24).unwrap();
-                        let hosts: Vec<Ipv4Addr> = nw_addr.hosts().collect();
-                        for host in hosts{
-                            host_scanner.add_ipaddr(&host.to_string());
-                        }
-                    },
-                    IpAddr::V6(_ipv6_addr) => {
-                        error!("Currently not supported.");
-                        std::process::exit(0);
-                        /*
-                        let net: Ipv6Net = Ipv6Net::new(ipv6_addr, 24).unwrap();
-                        let nw_addr = Ipv6Net::new(net.network(), 24).unwrap();
-                        let hosts: Vec<Ipv6Addr> = nw_addr.hosts().collect();
-                        */
-                    },
-                }
-            },
-            Err(_) => {
-                error!("Invalid IP address");
-                std::process::exit(0);
-            }
-        }
-    }else if opt.use_wordlist {
-        let data = read_to_string(opt.wordlist_path.to_string());
-        let text = match data {
-            Ok(content) => content,
-            Err(e) => {panic!("Could not open or find file: {}", e);}
-        };
-        let word_list: Vec<&str> = text.trim().split("\n").collect();
-        for host in word_list {
-            let addr = IpAddr::from_str(&host);
-            match addr {
-                Ok(_) => {
-                    host_scanner.add_ipaddr(&host.to_string());        
-                },
-                Err(_) => {
-                    
-                }
-            }
-        }
-    }
-    host_scanner.set_timeout(opt.timeout);
-    host_scanner.run_scan();
-    let result = host_scanner.get_result();
     match result.scan_status {
         ScanStatus::Done => {println!("{}", "Done".green())},
         ScanStatus::Timeout => {println!("{}", "Timed out".yellow())},
         _ => {println!("{}", "Error".red())},
     }
     println!();
-    let def_if_index = interface::get_default_interface_index();
-    let if_index = match def_if_index {
-        Some(if_index) => if_index,
-        None => 0,
-    };
-    let interface_ip = match nerve_base::ip::get_local_ipaddr(){
-        Some(ip) => ip,
-        None => String::new(),
-    };
+    let mut hostnames: HashMap<IpAddr, String> = HashMap::new();
+    if opt.resolve && !result.up_hosts.is_empty() {
+        print!("Resolving host names... ");
+        stdout().flush().unwrap();
+        hostnames = run_reverse_lookup(&opt, result.up_hosts.iter().map(|(host, _)| *host).collect()).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut local_names: HashMap<IpAddr, LocalNameInfo> = HashMap::new();
+    if opt.name_discovery && !result.up_hosts.is_empty() {
+        print!("Discovering local names... ");
+        stdout().flush().unwrap();
+        local_names = run_local_name_discovery(&opt, result.up_hosts.iter().map(|(host, _)| *host).collect()).await;
+        println!("{}", "Done".green());
+        println!();
+    }
     let mut result_map: HashMap<String, Option<db::Oui>> = HashMap::new();
-    let interfaces = pnet::datalink::interfaces();
-    let interface = interfaces.into_iter().filter(|interface: &pnet::datalink::NetworkInterface| interface.index == if_index).next().expect("Failed to get Interface");
+    let mut os_guesses: HashMap<IpAddr, OsGuess> = HashMap::new();
+    if opt.os_detect {
+        os_guesses = run_os_detection(&conn, result.fingerprints.clone());
+    }
     sys::print_fix32("Scan Reports", sys::FillStr::Hyphen);
-    for host in result.up_hosts {
-        match host.parse::<Ipv4Addr>(){
-            Ok(ipaddr) => {
-                let mac_addr: pnet::datalink::MacAddr = arp::get_mac_through_arp(&interface, ipaddr);
-                match db::get_vendor_info(&conn, &mac_addr.to_string()){
-                    Ok(oui) => {
-                        print_host_info(ipaddr.to_string(), mac_addr.to_string(), oui.clone());
-                        result_map.insert(ipaddr.to_string(), Some(oui));
-                    },
-                    Err(_) => {
-                        print!("{}{}{}", SPACE4, ipaddr.to_string().cyan(), " ".repeat(16 - ipaddr.to_string().len()));
-                        print!("{}{}", SPACE4, mac_addr);
-                        if ipaddr.to_string() == interface_ip {
-                            println!(" Own device");
-                        }else{
-                            println!(" Unknown");
-                        }
-                        result_map.insert(ipaddr.to_string(), None);
-                    },
+    for (host, rtt) in &result.up_hosts {
+        let rtt = format_rtt(*rtt);
+        let probe = get_probe_name(&result.probe_names, host);
+        let hostname = match hostnames.get(host) {
+            Some(name) => format!("{}{}", SPACE4, name),
+            None => String::new(),
+        };
+        let os = match os_guesses.get(host) {
+            Some(guess) => format!("{}[{}]", SPACE4, guess.get_detail()),
+            None => String::new(),
+        };
+        let local_name = match local_names.get(host) {
+            Some(info) => format!("{}{}", SPACE4, info.get_detail()),
+            None => String::new(),
+        };
+        let mac_addr = get_host_mac(&result, &interface, host);
+        let oui = db::get_vendor_info(&conn, &mac_addr.to_string()).ok();
+        let vendor_name = match &oui {
+            Some(oui) if oui.mac_prefix != "00:00:00" => oui.vendor_name_detail.to_string(),
+            Some(_) => String::from("Unknown"),
+            None if is_own_device(&interface, host, mac_addr) => String::from("Own device"),
+            None => String::from("Unknown"),
+        };
+        print_host_info(&HostInfo {
+            ip_addr: host.to_string(),
+            rtt: rtt,
+            probe: probe,
+            mac_addr: mac_addr.to_string(),
+            vendor_name: vendor_name,
+            os: os,
+            hostname: hostname,
+            local_name: local_name,
+        });
+        result_map.insert(host.to_string(), oui);
+    }
+    sys::print_fix32("", sys::FillStr::Hyphen);
+    println!("Scan Time: {:?}", result.scan_time);
+    if !opt.save_path.is_empty() {
+        save_host_result(&opt, result_map, &result.up_hosts, &result.probe_names, &hostnames, &local_names, &os_guesses);
+    }
+}
+
+// Discover up hosts with NDP for IPv6 networks, otherwise with ARP or ping probes
+fn run_host_discovery(opt: &mut option::HostOption, interface: &pnet::datalink::NetworkInterface) -> Result<HostStateResult, String> {
+    let mut targets: Vec<IpAddr> = vec![];
+    let mut ndp_scanner: Option<NdpScanner> = None;
+    if opt.is_ipv6() {
+        let ipv6_net = match IpNet::from_str(&opt.ip_addr) {
+            Ok(IpNet::V6(ipv6_net)) => ipv6_net,
+            _ => return Err(String::from("Invalid network address")),
+        };
+        // Multicast echo reaches every host on the link, so excluded hosts can not be skipped
+        if sys::is_overlapping(&opt.excludes, &IpNet::V6(ipv6_net)) {
+            return Err(format!("Exclusions overlap {}. IPv6 discovery uses multicast and can not skip hosts.", ipv6_net));
+        }
+        let scanner = match NdpScanner::new(ipv6_net, interface.index) {
+            Ok(mut scanner) if !opt.if_name.is_empty() => scanner.set_interface(interface.clone()).map(|_| scanner),
+            result => result,
+        };
+        ndp_scanner = Some(scanner?);
+    }else{
+        // Targets from -n or the word list
+        for host in &opt.targets {
+            if !opt.is_excluded(host) {
+                targets.push(*host);
+            }
+        }
+    }
+    match ndp_scanner {
+        Some(mut ndp_scanner) => {
+            ndp_scanner.set_timing(opt.timing.clone());
+            ndp_scanner.set_timeout(opt.timeout);
+            ndp_scanner.run_scan()
+        },
+        None if opt.arp => {
+            let mut arp_scanner = ArpScanner::new();
+            for target in targets {
+                arp_scanner.add_ipaddr(target);
+            }
+            if !opt.if_name.is_empty() {
+                arp_scanner.set_interface(interface.clone());
+            }
+            opt.adjust_timeout(arp_scanner.get_target_count());
+            arp_scanner.set_timing(opt.timing.clone());
+            arp_scanner.set_timeout(opt.timeout);
+            arp_scanner.run_scan()
+        },
+        None => {
+            let mut host_scanner = PingScanner::new();
+            for target in targets {
+                host_scanner.add_ipaddr(target);
+            }
+            for probe in &opt.probes {
+                host_scanner.add_probe(*probe);
+            }
+            if !opt.if_name.is_empty() {
+                match interface.ips.iter().find(|ip| ip.is_ipv4()) {
+                    Some(ip) => host_scanner.set_src_ipaddr(ip.ip()),
+                    None => return Err(format!("No IPv4 address on interface {}", interface.name)),
                 }
+                host_scanner.set_interface(interface.clone());
+            }
+            opt.adjust_timeout(host_scanner.get_probe_count());
+            host_scanner.set_timing(opt.timing.clone());
+            host_scanner.set_timeout(opt.timeout);
+            host_scanner.run_scan()
+        },
+    }
+}
+
+// ARP and NDP discovery resolve MAC addresses during the scan, hosts found with ping probes are resolved here
+fn get_host_mac(result: &HostStateResult, interface: &pnet::datalink::NetworkInterface, host: &IpAddr) -> pnet::datalink::MacAddr {
+    match result.mac_addrs.iter().find(|(h, _)| h == host) {
+        Some((_, mac_addr)) => *mac_addr,
+        None => match *host {
+            IpAddr::V4(ipaddr) => arp::get_mac_through_arp(interface, ipaddr),
+            IpAddr::V6(_) => pnet::datalink::MacAddr::zero(),
+        },
+    }
+}
+
+// A refused connection also shows that the host is up
+fn is_connect_answered(result: &PortStateResult) -> bool {
+    result.ports.iter().any(|(_, state)| *state == PortState::Open || *state == PortState::Closed)
+}
+
+// Host result of a sweep without raw sockets, where the connect scan is the discovery
+fn get_connect_discovery_result(results: &Vec<(IpAddr, PortStateResult)>, scan_time: Duration) -> HostStateResult {
+    HostStateResult {
+        up_hosts: results.iter().filter_map(|(host, result)| Some((*host, result.rtt?))).collect(),
+        probe_names: results.iter().map(|(host, _)| (*host, String::from("connect"))).collect(),
+        fingerprints: vec![],
+        mac_addrs: vec![],
+        scan_time: scan_time,
+        scan_status: get_port_scan_status(results),
+    }
+}
+
+fn is_own_device(interface: &pnet::datalink::NetworkInterface, host: &IpAddr, mac_addr: pnet::datalink::MacAddr) -> bool {
+    interface.ips.iter().any(|ip| ip.ip() == *host) || interface.mac == Some(mac_addr)
+}
+
+// Everything found about one host in a sweep, printed and saved
+struct SweepHostResult {
+    ip_addr: IpAddr,
+    port_result: PortStateResult,
+    hostname: Option<String>,
+    /// MAC address and vendor name. Not resolved without raw sockets.
+    vendor: Option<(pnet::datalink::MacAddr, String)>,
+    local_name: Option<LocalNameInfo>,
+    /// RTT and name of the probe that found the host.
+    rtt: Option<(Duration, String)>,
+    os_guess: Option<OsGuess>,
+}
+
+async fn handle_sweep(mut host_opt: option::HostOption, mut port_opt: option::PortOption) {
+    let conn = match db::get_db_connection() {
+        Ok(conn) => conn,
+        Err(e) => {
+            println!("{}: {}", "Error".red(), e);
+            return;
+        },
+    };
+    if let Err(e) = add_listed_ports(&conn, &mut port_opt) {
+        println!("{}: {}", "Error".red(), e);
+        return;
+    }
+    port_opt.set_targets(host_opt.ip_addr.to_string(), vec![]);
+    host_opt.show_options();
+    port_opt.show_options();
+    println!();
+    let start_time = Instant::now();
+    let mut interface: Option<pnet::datalink::NetworkInterface> = None;
+    let mut host_result: Option<HostStateResult> = None;
+    let targets: Vec<IpAddr> = if host_opt.connect_discovery {
+        host_opt.targets.iter().filter(|host| !host_opt.is_excluded(host)).cloned().collect()
+    }else{
+        print!("Discovering hosts... ");
+        stdout().flush().unwrap();
+        let host_interface = match get_host_scan_interface(&host_opt.if_name) {
+            Ok(interface) => interface,
+            Err(e) => {
+                println!("{}", "Error".red());
+                println!("{}: {}", "Error".red(), e);
+                return;
             },
-            Err(_) => {
-                println!("{}{}", SPACE4, host.cyan());
+        };
+        let result = match run_host_discovery(&mut host_opt, &host_interface) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("{}", "Error".red());
+                println!("{}: {}", "Error".red(), e);
+                return;
             },
+        };
+        match result.scan_status {
+            ScanStatus::Done => {println!("{}", "Done".green())},
+            ScanStatus::Timeout => {println!("{}", "Timed out".yellow())},
+            _ => {println!("{}", "Error".red())},
+        }
+        println!();
+        let up_hosts: Vec<IpAddr> = result.up_hosts.iter().map(|(host, _)| *host).collect();
+        if up_hosts.is_empty() {
+            println!("No up hosts in {}", host_opt.ip_addr);
+            return;
         }
+        interface = Some(host_interface);
+        host_result = Some(result);
+        up_hosts
+    };
+    print!("Scanning ports of {} hosts... ", targets.len());
+    stdout().flush().unwrap();
+    port_opt.set_targets(host_opt.ip_addr.to_string(), targets);
+    let probe_count = port_opt.ports.len();
+    port_opt.adjust_timeout(probe_count);
+    let port_opt = Arc::new(port_opt);
+    let mut results = run_port_scan(&port_opt).await;
+    match get_port_scan_status(&results) {
+        ScanStatus::Done => {println!("{}", "Done".green())},
+        ScanStatus::Timeout => {println!("{}", "Timed out".yellow())},
+        _ => {println!("{}", "Error".red())},
+    }
+    println!();
+    let host_result = match host_result {
+        Some(host_result) => host_result,
+        None => {
+            results.retain(|(_, result)| is_connect_answered(result));
+            if results.is_empty() {
+                println!("No up hosts in {}", host_opt.ip_addr);
+                return;
+            }
+            get_connect_discovery_result(&results, Instant::now().duration_since(start_time))
+        },
+    };
+    let up_hosts: Vec<IpAddr> = results.iter().map(|(host, _)| *host).collect();
+    let mut hostnames: HashMap<IpAddr, String> = HashMap::new();
+    if host_opt.resolve {
+        print!("Resolving host names... ");
+        stdout().flush().unwrap();
+        hostnames = run_reverse_lookup(&host_opt, up_hosts.clone()).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut local_names: HashMap<IpAddr, LocalNameInfo> = HashMap::new();
+    if host_opt.name_discovery {
+        print!("Discovering local names... ");
+        stdout().flush().unwrap();
+        local_names = run_local_name_discovery(&host_opt, up_hosts).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut services: HashMap<IpAddr, Vec<(u16, ServiceInfo)>> = HashMap::new();
+    if port_opt.service_detect && port_opt.scan_type != ScanType::UdpScan {
+        print!("Detecting services... ");
+        stdout().flush().unwrap();
+        services = run_service_detection(&conn, &port_opt, &results).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut certs: HashMap<IpAddr, Vec<(u16, TlsInfo)>> = HashMap::new();
+    let tls_targets = if !port_opt.tls_inspect || port_opt.scan_type == ScanType::UdpScan { vec![] } else { get_tls_targets(&results, &services) };
+    if !tls_targets.is_empty() {
+        print!("Inspecting TLS... ");
+        stdout().flush().unwrap();
+        certs = run_tls_inspection(&port_opt, tls_targets).await;
+        println!("{}", "Done".green());
+        println!();
+    }
+    let mut os_guesses: HashMap<IpAddr, OsGuess> = HashMap::new();
+    if port_opt.os_detect {
+        let fingerprints = results.iter().filter_map(|(ip_addr, result)| Some((*ip_addr, result.fingerprint.clone()?))).collect();
+        os_guesses = run_os_detection(&conn, fingerprints);
+    }
+    let rtts: HashMap<IpAddr, Duration> = host_result.up_hosts.iter().cloned().collect();
+    let probe_names: HashMap<IpAddr, String> = host_result.probe_names.iter().cloned().collect();
+    let mut hosts: Vec<SweepHostResult> = vec![];
+    for (ip_addr, port_result) in results {
+        // MAC addresses need ARP, so they are not resolved without raw sockets
+        let vendor = interface.as_ref().map(|interface| {
+            let mac_addr = get_host_mac(&host_result, interface, &ip_addr);
+            let vendor_name = match db::get_vendor_info(&conn, &mac_addr.to_string()) {
+                Ok(oui) if oui.mac_prefix != "00:00:00" => oui.vendor_name_detail,
+                _ if is_own_device(interface, &ip_addr, mac_addr) => String::from("Own device"),
+                _ => String::from("Unknown"),
+            };
+            (mac_addr, vendor_name)
+        });
+        let rtt = rtts.get(&ip_addr).map(|rtt| (*rtt, probe_names.get(&ip_addr).cloned().unwrap_or_default()));
+        hosts.push(SweepHostResult {
+            ip_addr: ip_addr,
+            port_result: port_result,
+            hostname: hostnames.remove(&ip_addr),
+            vendor: vendor,
+            local_name: local_names.remove(&ip_addr),
+            rtt: rtt,
+            os_guess: os_guesses.remove(&ip_addr),
+        });
+    }
+    sys::print_fix32("Scan Reports", sys::FillStr::Hyphen);
+    for host in &hosts {
+        print_sweep_host(&conn, &port_opt, host, &services, &certs);
     }
     sys::print_fix32("", sys::FillStr::Hyphen);
-    println!("Scan Time: {:?}", result.scan_time);
-    if !opt.save_path.is_empty() {
-        save_host_result(&opt, result_map);
+    println!("Scan Time: {:?}", Instant::now().duration_since(start_time));
+    if !port_opt.save_path.is_empty() {
+        save_sweep_result(&conn, &host_opt, &port_opt, &hosts, &services, &certs);
     }
 }
 
+fn print_sweep_host(conn: &rusqlite::Connection, opt: &option::PortOption, host: &SweepHostResult, services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>, certs: &HashMap<IpAddr, Vec<(u16, TlsInfo)>>) {
+    match &host.hostname {
+        Some(name) => println!("{}{}{}", host.ip_addr.to_string().cyan(), SPACE4, name),
+        None => println!("{}", host.ip_addr.to_string().cyan()),
+    }
+    if let Some((mac_addr, vendor_name)) = &host.vendor {
+        println!("{}MAC: {} {}", SPACE4, mac_addr, vendor_name);
+    }
+    if let Some(info) = &host.local_name {
+        println!("{}Local Names: {}", SPACE4, info.get_detail());
+    }
+    if let Some((rtt, probe)) = &host.rtt {
+        println!("{}RTT: {} ({})", SPACE4, format_rtt(*rtt), probe);
+    }
+    if opt.os_detect {
+        match &host.os_guess {
+            Some(guess) => println!("{}OS: {}", SPACE4, guess.get_detail()),
+            None => println!("{}OS: Unknown", SPACE4),
+        }
+    }
+    println!("{}{}", SPACE4, get_state_summary(&host.port_result));
+    print_port_result(conn, opt, &host.ip_addr, &host.port_result, services, certs);
+}
+
 async fn handle_uri_scan(opt: option::UriOption) {
     opt.show_options();
     println!();
@@ -576,52 +1816,232 @@ async fn handle_domain_scan(opt: option::DomainOption) {
     }
 }
 
-fn print_service(service: db::Service){
+// Closed and filtered ports are probed but not listed in the report.
+fn get_reported_ports(opt: &option::PortOption, result: &PortStateResult) -> Vec<(u16, PortState)> {
+    result.ports.iter().cloned().filter(|(_, state)| opt.is_shown_state(*state)).collect()
+}
+
+// Ex: "3 open, 995 closed, 2 filtered"
+fn get_state_summary(result: &PortStateResult) -> String {
+    let mut counts: Vec<String> = vec![];
+    for state in &[PortState::Open, PortState::OpenFiltered, PortState::Closed, PortState::Filtered] {
+        let count = result.ports.iter().filter(|(_, s)| s == state).count();
+        if count > 0 {
+            counts.push(format!("{} {}", count, state.name()));
+        }
+    }
+    if counts.is_empty() {
+        return String::from("No ports scanned");
+    }
+    counts.join(", ")
+}
+
+fn print_service(service: db::Service, state: &str, detected: Option<ServiceInfo>){
     print!("{}{}", " ".repeat(8 - service.port_number.len()),service.port_number.cyan());
-    println!("{}{}", SPACE4, service.service_name);
+    print!("{}{}{}", SPACE4, state, " ".repeat(13 - state.len()));
+    match detected {
+        Some(info) => println!("{}{:<16}{}{}", SPACE4, service.service_name, SPACE4, info.get_detail()),
+        None => println!("{}{}", SPACE4, service.service_name),
+    }
 }
 
-fn print_host_info(ip_addr: String, mac_addr: String, oui: db::Oui){
-    print!("{}{}{}", SPACE4, ip_addr.to_string().cyan(), " ".repeat(16 - ip_addr.len()));
-    print!("{}{}", SPACE4, mac_addr);
-    if oui.mac_prefix == "00:00:00".to_string() {
-        println!(" Unknown");
+fn print_tls_info(info: &TlsInfo){
+    let indent = " ".repeat(12);
+    println!("{}TLS: {}", indent, info.protocol_version);
+    println!("{}Subject: {}", indent, info.subject);
+    if !info.subject_alt_names.is_empty() {
+        println!("{}SAN: {}", indent, info.subject_alt_names.join(", "));
+    }
+    if info.self_signed {
+        println!("{}Issuer: {}", indent, info.get_issuer().yellow());
+    }else{
+        println!("{}Issuer: {}", indent, info.get_issuer());
+    }
+    if info.days_left < 30 {
+        println!("{}Validity: {}", indent, info.get_validity().yellow());
     }else{
-        println!("{}", oui.vendor_name_detail);
+        println!("{}Validity: {}", indent, info.get_validity());
     }
+    println!("{}Key: {}", indent, info.get_key());
 }
 
-fn save_port_result(conn: &rusqlite::Connection, opt: &option::PortOption, result: nerve::PortScanResult) {
+fn print_unknown_service(port: String, state: &str, detected: Option<ServiceInfo>){
+    print!("{}{}", " ".repeat(8 - port.len()), port.cyan());
+    print!("{}{}{}", SPACE4, state, " ".repeat(13 - state.len()));
+    match detected {
+        Some(info) if !info.service_name.is_empty() => println!("{}{:<16}{}{}", SPACE4, info.service_name, SPACE4, info.get_detail()),
+        Some(info) => println!("{}{:<16}{}{}", SPACE4, "Unknown service", SPACE4, info.get_detail()),
+        None => println!("{}Unknown service", SPACE4),
+    }
+}
+
+// Ex: "0.35ms"
+fn format_rtt(rtt: Duration) -> String {
+    format!("{:.2}ms", rtt.as_secs_f64() * 1000.0)
+}
+
+// One line of the host scan report. The optional columns are empty or start with a separator.
+struct HostInfo {
+    ip_addr: String,
+    rtt: String,
+    probe: String,
+    mac_addr: String,
+    vendor_name: String,
+    os: String,
+    hostname: String,
+    local_name: String,
+}
+
+fn print_host_info(info: &HostInfo){
+    print!("{}{}{}", SPACE4, info.ip_addr.as_str().cyan(), " ".repeat(16usize.saturating_sub(info.ip_addr.len())));
+    print!("{}{}{}", SPACE4, info.rtt, " ".repeat(10usize.saturating_sub(info.rtt.len())));
+    print!("{}{}{}", SPACE4, info.probe, " ".repeat(14usize.saturating_sub(info.probe.len())));
+    print!("{}{}", SPACE4, info.mac_addr);
+    println!(" {}{}{}{}", info.vendor_name, info.os, info.hostname, info.local_name);
+}
+
+fn save_port_result(conn: &rusqlite::Connection, opt: &option::PortOption, results: Vec<(IpAddr, PortStateResult)>, services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>, certs: &HashMap<IpAddr, Vec<(u16, TlsInfo)>>, os_guesses: &HashMap<IpAddr, OsGuess>) {
     let mut data = "[OPTIONS]".to_string();
-    data = format!("{}\nIP_ADDR:{}",data, opt.ip_addr.to_string());
-    data = format!("{}\nSTART_PORT:{}",data, opt.start_port.to_string());
-    data = format!("{}\nEND_PORT:{}",data, opt.end_port.to_string());
-    data = format!("{}\n[RESULTS]",data);
-    for port in result.open_ports {
-        match db::get_service(&conn, &port, "tcp"){
+    data = format!("{}\nTARGET:{}",data, opt.ip_addr.to_string());
+    data = format!("{}\nPORTS:{}",data, sys::format_port_list(&opt.ports));
+    data = format!("{}\nSCAN_TYPE:{}",data, opt.get_scan_type_name());
+    for (ip_addr, result) in results {
+        data = format!("{}\n[HOST:{}]",data, ip_addr);
+        data = format!("{}\nSUMMARY:{}",data, get_state_summary(&result));
+        if let Some(rtt) = result.rtt {
+            data = format!("{}\nRTT:{}",data, format_rtt(rtt));
+        }
+        if let Some(guess) = os_guesses.get(&ip_addr) {
+            data = format!("{}\nOS:{}",data, guess.os_family);
+            if let Some(hops) = guess.hop_distance {
+                data = format!("{}\nHOPS:{}",data, hops);
+            }
+        }
+        data = format!("{}{}",data, get_port_result_data(conn, opt, &ip_addr, &result, services, certs));
+    }
+    data = format!("{}\n",data);
+    sys::save_file(opt.save_path.to_string(), data);
+}
+
+// Lines of reported ports and certificates of a host in a saved result
+fn get_port_result_data(conn: &rusqlite::Connection, opt: &option::PortOption, ip_addr: &IpAddr, result: &PortStateResult, services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>, certs: &HashMap<IpAddr, Vec<(u16, TlsInfo)>>) -> String {
+    let mut data = String::new();
+    let protocol = opt.get_protocol();
+    for (port, state) in get_reported_ports(opt, result) {
+        let detail = match get_detected_service(services, ip_addr, port) {
+            Some(info) => format!(",{}", info.get_detail()),
+            None => String::new(),
+        };
+        match db::get_service(conn, &port.to_string(), &protocol){
             Ok(service) => {
-                data = format!("{}\n{},{},{},{}", data, service.port_number,service.protocol,service.service_name,service.description);
+                data = format!("{}\n{},{},{},{},{}{}", data, service.port_number,state.name(),service.protocol,service.service_name,service.description,detail);
             },
             Err(_) => {
-                data = format!("{}\n{},Unknown service", data, port);
+                data = format!("{}\n{},{},{},Unknown service{}", data, port, state.name(), protocol, detail);
             }, 
         };
     }
+    if let Some(host_certs) = certs.get(ip_addr) {
+        for (port, info) in host_certs {
+            data = format!("{}\n[TLS:{}]", data, SocketAddr::new(*ip_addr, *port));
+            data = format!("{}\nPROTOCOL:{}", data, info.protocol_version);
+            data = format!("{}\nSUBJECT:{}", data, info.subject);
+            data = format!("{}\nSAN:{}", data, info.subject_alt_names.join(", "));
+            data = format!("{}\nISSUER:{}", data, info.issuer);
+            data = format!("{}\nSELF_SIGNED:{}", data, info.self_signed);
+            data = format!("{}\nNOT_BEFORE:{}", data, info.not_before);
+            data = format!("{}\nNOT_AFTER:{}", data, info.not_after);
+            data = format!("{}\nDAYS_LEFT:{}", data, info.days_left);
+            data = format!("{}\nKEY:{}", data, info.get_key());
+        }
+    }
+    data
+}
+
+fn save_sweep_result(conn: &rusqlite::Connection, host_opt: &option::HostOption, port_opt: &option::PortOption, hosts: &[SweepHostResult], services: &HashMap<IpAddr, Vec<(u16, ServiceInfo)>>, certs: &HashMap<IpAddr, Vec<(u16, TlsInfo)>>) {
+    let mut data = "[OPTIONS]".to_string();
+    data = format!("{}\nTARGET:{}",data, host_opt.ip_addr.to_string());
+    data = format!("{}\nDISCOVERY:{}",data, host_opt.get_discovery_name());
+    data = format!("{}\nPORTS:{}",data, sys::format_port_list(&port_opt.ports));
+    data = format!("{}\nSCAN_TYPE:{}",data, port_opt.get_scan_type_name());
+    for host in hosts {
+        data = format!("{}\n[HOST:{}]",data, host.ip_addr);
+        if let Some(name) = &host.hostname {
+            data = format!("{}\nHOSTNAME:{}",data, name);
+        }
+        if let Some((mac_addr, vendor_name)) = &host.vendor {
+            data = format!("{}\nMAC:{}",data, mac_addr);
+            data = format!("{}\nVENDOR:{}",data, vendor_name);
+        }
+        if let Some(info) = &host.local_name {
+            data = format!("{}\nNAMES:{}",data, info.names.join("/"));
+            if let Some(workgroup) = &info.workgroup {
+                data = format!("{}\nWORKGROUP:{}",data, workgroup);
+            }
+            if let Some(model) = &info.model {
+                data = format!("{}\nMODEL:{}",data, model);
+            }
+            data = format!("{}\nSERVICES:{}",data, info.services.join(" "));
+        }
+        if let Some((rtt, probe)) = &host.rtt {
+            data = format!("{}\nRTT:{}",data, format_rtt(*rtt));
+            data = format!("{}\nPROBE:{}",data, probe);
+        }
+        if let Some(guess) = &host.os_guess {
+            data = format!("{}\nOS:{}",data, guess.os_family);
+            if let Some(hops) = guess.hop_distance {
+                data = format!("{}\nHOPS:{}",data, hops);
+            }
+        }
+        data = format!("{}\nSUMMARY:{}",data, get_state_summary(&host.port_result));
+        data = format!("{}{}",data, get_port_result_data(conn, port_opt, &host.ip_addr, &host.port_result, services, certs));
+    }
     data = format!("{}\n",data);
-    sys::save_file(opt.save_path.to_string(), data);
+    sys::save_file(port_opt.save_path.to_string(), data);
+}
+
+// Probe that got the first reply of a host. Ex: "syn:443"
+fn get_probe_name(probe_names: &Vec<(IpAddr, String)>, ip_addr: &IpAddr) -> String {
+    match probe_names.iter().find(|(host, _)| host == ip_addr) {
+        Some((_, name)) => name.to_string(),
+        None => String::new(),
+    }
 }
 
-fn save_host_result(opt: &option::HostOption, result_map: HashMap<String, Option<db::Oui>>){
+fn save_host_result(opt: &option::HostOption, result_map: HashMap<String, Option<db::Oui>>, rtts: &Vec<(IpAddr, Duration)>, probe_names: &Vec<(IpAddr, String)>, hostnames: &HashMap<IpAddr, String>, local_names: &HashMap<IpAddr, LocalNameInfo>, os_guesses: &HashMap<IpAddr, OsGuess>){
     let mut data = "[OPTIONS]".to_string();
     data = format!("{}\nNETWORK: {}",data, opt.ip_addr.to_string());
     data = format!("{}\n[RESULTS]",data);
+    // ip,mac,vendor,rtt,probe,os,hostname,names,workgroup,model,services. MAC is "Unknown" and vendor empty if not resolved.
+    // Fields with commas or quotes are quoted, so every row has the same columns
     for (ip, oui) in result_map{
+        let rtt = match rtts.iter().find(|(host, _)| host.to_string() == ip) {
+            Some((_, rtt)) => format_rtt(*rtt),
+            None => String::new(),
+        };
+        let probe = match probe_names.iter().find(|(host, _)| host.to_string() == ip) {
+            Some((_, name)) => name.to_string(),
+            None => String::new(),
+        };
+        let hostname = match hostnames.iter().find(|(host, _)| host.to_string() == ip) {
+            Some((_, name)) => quote_field(name),
+            None => String::new(),
+        };
+        let os = match os_guesses.iter().find(|(host, _)| host.to_string() == ip) {
+            Some((_, guess)) => quote_field(&guess.get_detail()),
+            None => String::new(),
+        };
+        // Names separated by "/", workgroup, model, and services separated by spaces. Empty without name discovery
+        let local_name = match local_names.iter().find(|(host, _)| host.to_string() == ip) {
+            Some((_, info)) => format!("{},{},{},{}", quote_field(&info.names.join("/")), quote_field(info.workgroup.as_deref().unwrap_or("")), quote_field(info.model.as_deref().unwrap_or("")), quote_field(&info.services.join(" "))),
+            None => String::from(",,,"),
+        };
         match oui {
             Some(oui) => {
-                data = format!("{}\n{},{},{}",data, ip, oui.mac_addr,oui.vendor_name_detail);
+                data = format!("{}\n{},{},{},{},{},{},{},{}",data, ip, oui.mac_addr,quote_field(&oui.vendor_name_detail),rtt,probe,os,hostname,local_name);
             },
             None => {
-                data = format!("{}\n{},Unknown",data, ip);
+                data = format!("{}\n{},Unknown,,{},{},{},{},{}",data, ip, rtt,probe,os,hostname,local_name);
             },
         }
     }
@@ -629,6 +2049,15 @@ fn save_host_result(opt: &option::HostOption, result_map: HashMap<String, Option
     sys::save_file(opt.save_path.to_string(), data);
 }
 
+// Quote a field that contains a comma, quote or line break. Quotes inside are doubled.
+fn quote_field(v: &str) -> String {
+    if v.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", v.replace("\"", "\"\""))
+    }else{
+        v.to_string()
+    }
+}
+
 fn save_uri_result(opt: &option::UriOption, result: nerve::UriScanResult){
     let mut data = "[OPTIONS]".to_string();
     data = format!("{}\nBASE_URI: {}",data, opt.base_uri.to_string());
@@ -0,0 +1,210 @@
+use std::thread;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use pnet::datalink::{self, Channel, DataLinkReceiver, MacAddr, NetworkInterface};
+use pnet::packet::Packet;
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use nerve_base::ScanStatus;
+use super::host::HostStateResult;
+use super::timing::{self, RttTracker, Timing};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ARP_PACKET_LEN: usize = 28;
+
+/// Structure for ARP host discovery on the local subnet.
+///
+/// Every host that answers an ARP request is up, even if it drops ICMP.
+/// Requests are sent one at a time with the probe interval of the timing template
+/// (1ms for normal, so a /24 takes about 0.3s per round) or at the `--rate` limit.
+/// Hosts that do not reply are asked again according to the timing settings.
+pub struct ArpScanner {
+    /// Set of target hosts.
+    target_hosts: HashSet<Ipv4Addr>,
+    /// Interface to send from. Chosen by the subnet of the targets if not set.
+    interface: Option<NetworkInterface>,
+    /// Probe pacing and retransmission.
+    timing: Timing,
+    /// Timeout setting of host scan.
+    timeout: Duration,
+}
+
+impl ArpScanner {
+    pub fn new() -> ArpScanner {
+        ArpScanner {
+            target_hosts: HashSet::new(),
+            interface: None,
+            timing: Timing::new(),
+            timeout: Duration::from_millis(30000),
+        }
+    }
+    pub fn add_ipaddr(&mut self, ip_addr: IpAddr){
+        if let IpAddr::V4(ipv4_addr) = ip_addr {
+            self.target_hosts.insert(ipv4_addr);
+        }
+    }
+    pub fn get_target_count(&self) -> usize {
+        self.target_hosts.len()
+    }
+    pub fn set_interface(&mut self, interface: NetworkInterface){
+        self.interface = Some(interface);
+    }
+    pub fn set_timing(&mut self, timing: Timing){
+        self.timing = timing;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        self.timeout = timeout;
+    }
+    // Interface and its address on the subnet of the lowest target
+    fn get_interface(&self) -> Result<(NetworkInterface, Ipv4Addr), String> {
+        let target = match self.target_hosts.iter().min() {
+            Some(target) => IpAddr::V4(*target),
+            None => return Err(String::from("No target hosts")),
+        };
+        if let Some(iface) = &self.interface {
+            let ip = iface.ips.iter().find(|ip| ip.is_ipv4() && ip.contains(target)).or(iface.ips.iter().find(|ip| ip.is_ipv4()));
+            return match ip.map(|ip| ip.ip()) {
+                Some(IpAddr::V4(src_ipaddr)) => Ok((iface.clone(), src_ipaddr)),
+                _ => Err(format!("No IPv4 address on interface {}", iface.name)),
+            };
+        }
+        for iface in datalink::interfaces() {
+            if let Some(ip) = iface.ips.iter().find(|ip| ip.is_ipv4() && ip.contains(target)) {
+                if let IpAddr::V4(src_ipaddr) = ip.ip() {
+                    return Ok((iface.clone(), src_ipaddr));
+                }
+            }
+        }
+        Err(format!("ARP discovery needs targets on a local subnet ({} is not)", target))
+    }
+    pub fn run_scan(&self) -> Result<HostStateResult, String> {
+        let start_time = Instant::now();
+        let (interface, src_ipaddr) = self.get_interface()?;
+        let src_mac = match interface.mac {
+            Some(mac) => mac,
+            None => return Err(format!("No MAC address on interface {}", interface.name)),
+        };
+        let config = datalink::Config {
+            read_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let (mut tx, rx) = match datalink::channel(&interface, config) {
+            Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
+            Ok(_) => return Err(String::from("Unsupported channel type")),
+            Err(e) => return Err(format!("Failed to open datalink channel: {}", e)),
+        };
+        let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+        let up_hosts: Arc<Mutex<HashMap<Ipv4Addr, (Duration, MacAddr)>>> = Arc::new(Mutex::new(HashMap::new()));
+        let rtt: Arc<Mutex<RttTracker<Ipv4Addr>>> = Arc::new(Mutex::new(RttTracker::new(&self.timing)));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            let up_hosts = Arc::clone(&up_hosts);
+            let rtt = Arc::clone(&rtt);
+            let target_hosts = self.target_hosts.clone();
+            thread::spawn(move || receive_arp_packets(rx, target_hosts, &stop, &up_hosts, &rtt))
+        };
+        let mut scan_status = ScanStatus::Done;
+        let mut send_failed = false;
+        // Hosts that do not reply are asked again in the next round
+        'rounds: for _ in 0..self.timing.max_retries as usize + 1 {
+            let pending: Vec<Ipv4Addr> = {
+                let up_hosts = up_hosts.lock().unwrap();
+                self.target_hosts.iter().cloned().filter(|host| !up_hosts.contains_key(host)).collect()
+            };
+            if pending.is_empty() {
+                break;
+            }
+            for host in pending {
+                if Instant::now().duration_since(start_time) > self.timeout {
+                    scan_status = ScanStatus::Timeout;
+                    break 'rounds;
+                }
+                thread::sleep(self.timing.get_send_delay());
+                let packet = build_arp_request(src_mac, src_ipaddr, host);
+                rtt.lock().unwrap().on_send(host);
+                if let Some(Err(e)) = tx.send_to(&packet, None) {
+                    // Logged once, the same error usually repeats for every request
+                    if !send_failed {
+                        warn!("Failed to send ARP request for {}: {}", host, e);
+                        send_failed = true;
+                    }
+                }
+            }
+            let elapsed = Instant::now().duration_since(start_time);
+            if elapsed >= self.timeout {
+                if up_hosts.lock().unwrap().len() < self.target_hosts.len() {
+                    scan_status = ScanStatus::Timeout;
+                }
+                break;
+            }
+            let remaining = self.timeout - elapsed;
+            let probe_timeout = rtt.lock().unwrap().get_timeout();
+            timing::wait_for_replies(if remaining < probe_timeout { remaining } else { probe_timeout }, || {
+                up_hosts.lock().unwrap().len() >= self.target_hosts.len()
+            });
+            // Probes sent again wait longer, until a new reply is measured
+            rtt.lock().unwrap().back_off();
+        }
+        *stop.lock().unwrap() = true;
+        let _ = handle.join();
+        let mut hosts: Vec<(Ipv4Addr, Duration, MacAddr)> = up_hosts.lock().unwrap().iter().map(|(host, (host_rtt, mac))| (*host, *host_rtt, *mac)).collect();
+        hosts.sort_by_key(|(host, _, _)| *host);
+        Ok(HostStateResult {
+            up_hosts: hosts.iter().map(|(host, host_rtt, _)| (IpAddr::V4(*host), *host_rtt)).collect(),
+            probe_names: hosts.iter().map(|(host, _, _)| (IpAddr::V4(*host), String::from("arp"))).collect(),
+            fingerprints: vec![],
+            mac_addrs: hosts.iter().map(|(host, _, mac)| (IpAddr::V4(*host), *mac)).collect(),
+            scan_time: Instant::now().duration_since(start_time),
+            scan_status: scan_status,
+        })
+    }
+}
+
+fn build_arp_request(src_mac: MacAddr, src_ipaddr: Ipv4Addr, target: Ipv4Addr) -> Vec<u8> {
+    let mut buf = vec![0u8; ETHERNET_HEADER_LEN + ARP_PACKET_LEN];
+    {
+        let mut arp_packet = MutableArpPacket::new(&mut buf[ETHERNET_HEADER_LEN..]).unwrap();
+        arp_packet.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp_packet.set_protocol_type(EtherTypes::Ipv4);
+        arp_packet.set_hw_addr_len(6);
+        arp_packet.set_proto_addr_len(4);
+        arp_packet.set_operation(ArpOperations::Request);
+        arp_packet.set_sender_hw_addr(src_mac);
+        arp_packet.set_sender_proto_addr(src_ipaddr);
+        arp_packet.set_target_hw_addr(MacAddr::zero());
+        arp_packet.set_target_proto_addr(target);
+    }
+    let mut ethernet_packet = MutableEthernetPacket::new(&mut buf).unwrap();
+    ethernet_packet.set_destination(MacAddr::broadcast());
+    ethernet_packet.set_source(src_mac);
+    ethernet_packet.set_ethertype(EtherTypes::Arp);
+    buf
+}
+
+fn receive_arp_packets(
+    mut rx: Box<dyn DataLinkReceiver>,
+    target_hosts: HashSet<Ipv4Addr>,
+    stop: &Arc<Mutex<bool>>,
+    up_hosts: &Arc<Mutex<HashMap<Ipv4Addr, (Duration, MacAddr)>>>,
+    rtt: &Arc<Mutex<RttTracker<Ipv4Addr>>>) {
+    loop {
+        if let Ok(frame) = rx.next() {
+            if let Some(ethernet_packet) = EthernetPacket::new(frame) {
+                if ethernet_packet.get_ethertype() == EtherTypes::Arp {
+                    if let Some(arp_packet) = ArpPacket::new(ethernet_packet.payload()) {
+                        let host = arp_packet.get_sender_proto_addr();
+                        if arp_packet.get_operation() == ArpOperations::Reply && target_hosts.contains(&host) {
+                            let host_rtt = rtt.lock().unwrap().on_reply(&host);
+                            up_hosts.lock().unwrap().entry(host).or_insert((host_rtt.unwrap_or(Duration::from_millis(0)), arp_packet.get_sender_hw_addr()));
+                        }
+                    }
+                }
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::ErrorKind;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use futures::stream::{self, StreamExt};
+use tokio::net::TcpStream;
+use tokio::time::{delay_for, timeout};
+use nerve_base::ScanStatus;
+use super::{PortState, PortStateResult};
+use super::timing::{RttEstimator, Timing};
+
+/// Maximum number of connection attempts in flight at once.  
+pub const DEFAULT_CONCURRENCY: usize = 100;
+
+/// Structure for TCP connect() port scan.  
+/// 
+/// Does not require raw sockets, so it can be used without administrator privileges.
+pub struct ConnectScanner {
+    /// IP Address of target host.  
+    target_ipaddr: IpAddr,
+    /// List of target ports.  
+    target_ports: Vec<u16>,
+    /// Maximum number of concurrent connection attempts.  
+    concurrency: usize,
+    /// Connection pacing, retries and timeout of each attempt.  
+    timing: Timing,
+    /// Timeout setting of port scan.  
+    timeout: Duration,
+}
+
+impl ConnectScanner {
+    pub fn new(target_ipaddr: IpAddr) -> ConnectScanner {
+        ConnectScanner {
+            target_ipaddr: target_ipaddr,
+            target_ports: vec![],
+            concurrency: DEFAULT_CONCURRENCY,
+            timing: Timing::new(),
+            timeout: Duration::from_millis(30000),
+        }
+    }
+    pub fn set_ports(&mut self, ports: Vec<u16>){
+        self.target_ports = ports;
+    }
+    pub fn set_timing(&mut self, timing: Timing){
+        self.timing = timing;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        self.timeout = timeout;
+    }
+    /// Run scan with current settings.  
+    /// 
+    /// Refused connections are reported as closed, unanswered ones as filtered.
+    pub async fn run_scan(&self) -> PortStateResult {
+        let port_states: Arc<Mutex<Vec<(u16, PortState)>>> = Arc::new(Mutex::new(vec![]));
+        let target_ipaddr = self.target_ipaddr;
+        let timing = &self.timing;
+        let rtt: Arc<Mutex<RttEstimator>> = Arc::new(Mutex::new(RttEstimator::new(timing)));
+        let probes = stream::iter(self.target_ports.clone())
+            .then(|port| async move {
+                delay_for(timing.get_send_delay()).await;
+                port
+            })
+            .for_each_concurrent(self.concurrency, |port| {
+                let port_states = Arc::clone(&port_states);
+                let rtt = Arc::clone(&rtt);
+                async move {
+                    let addr = SocketAddr::new(target_ipaddr, port);
+                    let mut state = PortState::Filtered;
+                    // Only attempts that got no answer at all are retried
+                    for attempt in 0..timing.max_retries as usize + 1 {
+                        let probe_timeout = rtt.lock().unwrap().get_timeout();
+                        let sent_time = Instant::now();
+                        match timeout(probe_timeout, TcpStream::connect(&addr)).await {
+                            Ok(Ok(_stream)) => state = PortState::Open,
+                            Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => state = PortState::Closed,
+                            Ok(Err(_)) => break,
+                            Err(_) => continue,
+                        }
+                        // Replies to retried attempts are ambiguous, so only the first is sampled
+                        if attempt == 0 {
+                            rtt.lock().unwrap().add_sample(Instant::now().duration_since(sent_time));
+                        }
+                        break;
+                    }
+                    port_states.lock().unwrap().push((port, state));
+                }
+            });
+        let scan_status = match timeout(self.timeout, probes).await {
+            Ok(_) => ScanStatus::Done,
+            Err(_) => ScanStatus::Timeout,
+        };
+        let mut ports = port_states.lock().unwrap().clone();
+        ports.sort_by_key(|(port, _)| *port);
+        let srtt = rtt.lock().unwrap().get_srtt();
+        PortStateResult {
+            ports: ports,
+            rtt: srtt,
+            fingerprint: None,
+            scan_status: scan_status,
+        }
+    }
+}
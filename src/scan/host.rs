@@ -0,0 +1,532 @@
+use std::{thread, time};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use pnet::datalink::{MacAddr, NetworkInterface};
+use pnet::packet::Packet;
+use pnet::packet::icmp::{IcmpPacket, IcmpTypes, MutableIcmpPacket};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::{MutableTcpPacket, TcpFlags, TcpPacket};
+use pnet::packet::udp::{MutableUdpPacket, UdpPacket};
+use pnet::transport::TransportChannelType::Layer3;
+use pnet::transport::{ipv4_packet_iter, TransportReceiver, TransportSender};
+use rand::Rng;
+use nerve_base::ScanStatus;
+use crate::util::sys;
+use super::os::OsFingerprint;
+use super::tcp;
+use super::timing::{self, RttTracker, Timing};
+
+const ICMP_ECHO_LEN: usize = 16;
+const ICMP_TIMESTAMP_LEN: usize = 20;
+const ICMP_IDENTIFIER: u16 = 0x6e73;
+const TCP_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+// Longest of the probes above
+const MAX_PROBE_LEN: usize = 20;
+const PROBE_SEQUENCE: u32 = 0x6e73_6361;
+// Default destination ports of probes given without a port list
+const DEFAULT_TCP_PORT: u16 = 80;
+const DEFAULT_UDP_PORT: u16 = 40125;
+// Range of source ports. Every probe gets its own port so replies can be matched.
+const MIN_SRC_PORT: u16 = 1024;
+const MAX_SRC_PORT: u16 = 60000;
+/// Maximum number of probes sent to each host.
+pub const MAX_PROBES: usize = 1024;
+
+/// Host discovery probe.
+#[derive(Clone, Copy, PartialEq)]
+pub enum HostProbe {
+    IcmpEcho,
+    IcmpTimestamp,
+    TcpSyn(u16),
+    TcpAck(u16),
+    Udp(u16),
+}
+
+impl HostProbe {
+    /// Parse a probe option such as `icmp`, `timestamp`, `syn:22,443`, `ack:80` or `udp:53`.
+    pub fn parse(v: &str) -> Result<Vec<HostProbe>, String> {
+        let (method, port_str) = match v.find(":") {
+            Some(i) => (v[..i].trim().to_lowercase(), Some(&v[i + 1..])),
+            None => (v.trim().to_lowercase(), None),
+        };
+        let ports = |default_port: u16| -> Result<Vec<u16>, String> {
+            match port_str {
+                Some(port_str) => sys::parse_port_list(port_str),
+                None => Ok(vec![default_port]),
+            }
+        };
+        match method.as_str() {
+            "icmp" | "echo" if port_str.is_none() => Ok(vec![HostProbe::IcmpEcho]),
+            "timestamp" if port_str.is_none() => Ok(vec![HostProbe::IcmpTimestamp]),
+            "syn" => Ok(ports(DEFAULT_TCP_PORT)?.into_iter().map(HostProbe::TcpSyn).collect()),
+            "ack" => Ok(ports(DEFAULT_TCP_PORT)?.into_iter().map(HostProbe::TcpAck).collect()),
+            "udp" => Ok(ports(DEFAULT_UDP_PORT)?.into_iter().map(HostProbe::Udp).collect()),
+            _ => Err(format!("Invalid probe: {}", v)),
+        }
+    }
+    /// Ex: "syn:443"
+    pub fn get_name(&self) -> String {
+        match *self {
+            HostProbe::IcmpEcho => String::from("icmp echo"),
+            HostProbe::IcmpTimestamp => String::from("icmp timestamp"),
+            HostProbe::TcpSyn(port) => format!("syn:{}", port),
+            HostProbe::TcpAck(port) => format!("ack:{}", port),
+            HostProbe::Udp(port) => format!("udp:{}", port),
+        }
+    }
+    fn get_protocol(&self) -> IpNextHeaderProtocol {
+        match *self {
+            HostProbe::IcmpEcho | HostProbe::IcmpTimestamp => IpNextHeaderProtocols::Icmp,
+            HostProbe::TcpSyn(_) | HostProbe::TcpAck(_) => IpNextHeaderProtocols::Tcp,
+            HostProbe::Udp(_) => IpNextHeaderProtocols::Udp,
+        }
+    }
+    fn get_dst_port(&self) -> Option<u16> {
+        match *self {
+            HostProbe::TcpSyn(port) | HostProbe::TcpAck(port) | HostProbe::Udp(port) => Some(port),
+            _ => None,
+        }
+    }
+}
+
+/// Result of host scan.
+#[derive(Clone)]
+pub struct HostStateResult {
+    /// List of hosts that replied, with the round-trip time of their first reply.
+    pub up_hosts: Vec<(IpAddr, Duration)>,
+    /// Name of the probe that got the first reply of each host. Ex: "syn:443"
+    pub probe_names: Vec<(IpAddr, String)>,
+    /// Header values of the first reply of each host, for OS detection.
+    pub fingerprints: Vec<(IpAddr, OsFingerprint)>,
+    /// MAC addresses resolved during the scan. Empty for ping scans, which use ARP afterwards.
+    pub mac_addrs: Vec<(IpAddr, MacAddr)>,
+    /// Time from start to end of scan.
+    pub scan_time: Duration,
+    /// Scan job status
+    pub scan_status: ScanStatus,
+}
+
+/// Structure for IPv4 ping host scan.
+///
+/// Every target gets each probe (ICMP echo by default), and any reply marks it up:
+/// echo or timestamp replies, SYN/ACK or RST to TCP probes, UDP replies and
+/// ICMP unreachable sent by the target itself.
+/// Hosts that do not reply are probed again according to the timing settings.
+pub struct PingScanner {
+    /// Set of target hosts.
+    target_hosts: HashSet<IpAddr>,
+    /// Probes sent to every host.
+    probes: Vec<HostProbe>,
+    /// Source address of TCP and UDP probes. Chosen by the routing table if not set.
+    src_ipaddr: Option<IpAddr>,
+    /// Interface to send from, with `src_ipaddr` on it. Chosen by the routing table if not set.
+    interface: Option<NetworkInterface>,
+    /// Probe pacing and retransmission.
+    timing: Timing,
+    /// Timeout setting of host scan.
+    timeout: Duration,
+}
+
+impl PingScanner {
+    pub fn new() -> PingScanner {
+        PingScanner {
+            target_hosts: HashSet::new(),
+            probes: vec![],
+            src_ipaddr: None,
+            interface: None,
+            timing: Timing::new(),
+            timeout: Duration::from_millis(30000),
+        }
+    }
+    pub fn add_ipaddr(&mut self, ip_addr: IpAddr){
+        if ip_addr.is_ipv4() {
+            self.target_hosts.insert(ip_addr);
+        }
+    }
+    pub fn add_probe(&mut self, probe: HostProbe){
+        if !self.probes.contains(&probe) {
+            self.probes.push(probe);
+        }
+    }
+    pub fn set_src_ipaddr(&mut self, src_ipaddr: IpAddr){
+        self.src_ipaddr = Some(src_ipaddr);
+    }
+    pub fn set_interface(&mut self, interface: NetworkInterface){
+        self.interface = Some(interface);
+    }
+    /// Number of probes in one round.
+    pub fn get_probe_count(&self) -> usize {
+        self.target_hosts.len() * self.get_probes().len()
+    }
+    pub fn set_timing(&mut self, timing: Timing){
+        self.timing = timing;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        self.timeout = timeout;
+    }
+    fn get_probes(&self) -> Vec<HostProbe> {
+        if self.probes.is_empty() {
+            vec![HostProbe::IcmpEcho]
+        }else{
+            self.probes.clone()
+        }
+    }
+    pub fn run_scan(&self) -> Result<HostStateResult, String> {
+        let start_time = Instant::now();
+        let probes = self.get_probes();
+        if probes.len() > MAX_PROBES {
+            return Err(format!("Too many probes per host: {} (max {})", probes.len(), MAX_PROBES));
+        }
+        // One channel pair per protocol in use
+        let mut protocols: Vec<IpNextHeaderProtocol> = vec![];
+        for probe in &probes {
+            if !protocols.contains(&probe.get_protocol()) {
+                protocols.push(probe.get_protocol());
+            }
+        }
+        // UDP probes to closed ports are answered with ICMP port unreachable
+        if protocols.contains(&IpNextHeaderProtocols::Udp) && !protocols.contains(&IpNextHeaderProtocols::Icmp) {
+            protocols.push(IpNextHeaderProtocols::Icmp);
+        }
+        // Source addresses are resolved before any receiver thread is started,
+        // so a failure can not leave them running
+        let mut src_ipaddrs: HashMap<IpAddr, IpAddr> = HashMap::new();
+        if probes.iter().any(|probe| probe.get_dst_port().is_some()) {
+            for host in &self.target_hosts {
+                let src_ipaddr = match self.src_ipaddr {
+                    Some(src_ipaddr) => src_ipaddr,
+                    None => tcp::get_src_ipaddr(*host)?,
+                };
+                src_ipaddrs.insert(*host, src_ipaddr);
+            }
+        }
+        let mut senders: Vec<(IpNextHeaderProtocol, TransportSender)> = vec![];
+        let mut receivers: Vec<TransportReceiver> = vec![];
+        for protocol in protocols {
+            // Probes carry their own IPv4 header, so replies are read with theirs and the TTL is available
+            match pnet::transport::transport_channel(4096, Layer3(protocol)) {
+                Ok((tx, rx)) => {
+                    if let (Some(interface), Some(src_ipaddr)) = (&self.interface, self.src_ipaddr) {
+                        tcp::bind_to_interface(tx.socket.fd, interface, src_ipaddr)?;
+                    }
+                    senders.push((protocol, tx));
+                    receivers.push(rx);
+                },
+                Err(e) => return Err(format!("Failed to open {} channel: {}", protocol, e)),
+            }
+        }
+        let src_port_base: u16 = rand::thread_rng().gen_range(MIN_SRC_PORT, MAX_SRC_PORT - probes.len() as u16);
+        let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+        let replies: Arc<Mutex<Replies>> = Arc::new(Mutex::new(Replies::new(&self.timing)));
+        let mut handles = vec![];
+        for rx in receivers {
+            let stop = Arc::clone(&stop);
+            let replies = Arc::clone(&replies);
+            let matcher = ReplyMatcher {
+                target_hosts: self.target_hosts.clone(),
+                probes: probes.clone(),
+                src_port_base: src_port_base,
+            };
+            handles.push(thread::spawn(move || receive_packets(rx, matcher, &stop, &replies)));
+        }
+        let mut scan_status = ScanStatus::Done;
+        let mut send_failed = false;
+        // Hosts that do not reply are probed again in the next round
+        'rounds: for _ in 0..self.timing.max_retries as usize + 1 {
+            let pending: Vec<IpAddr> = {
+                let replies = replies.lock().unwrap();
+                self.target_hosts.iter().cloned().filter(|host| !replies.up_hosts.contains_key(host)).collect()
+            };
+            if pending.is_empty() {
+                break;
+            }
+            for host in pending {
+                // Without TCP or UDP probes the OS fills in the source address
+                let src_ipaddr = src_ipaddrs.get(&host).cloned().or(self.src_ipaddr).unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+                for (index, probe) in probes.iter().enumerate() {
+                    if Instant::now().duration_since(start_time) > self.timeout {
+                        scan_status = ScanStatus::Timeout;
+                        break 'rounds;
+                    }
+                    if replies.lock().unwrap().up_hosts.contains_key(&host) {
+                        break;
+                    }
+                    thread::sleep(self.timing.get_send_delay());
+                    replies.lock().unwrap().rtt.on_send((host, index));
+                    if let Some((_, tx)) = senders.iter_mut().find(|(protocol, _)| *protocol == probe.get_protocol()) {
+                        if let Err(e) = send_probe(tx, *probe, src_ipaddr, src_port_base + index as u16, host, index as u16) {
+                            // Logged once, the same error usually repeats for every probe
+                            if !send_failed {
+                                warn!("Failed to send {} probe to {}: {}", probe.get_name(), host, e);
+                                send_failed = true;
+                            }
+                        }
+                    }
+                }
+            }
+            let elapsed = Instant::now().duration_since(start_time);
+            if elapsed >= self.timeout {
+                break;
+            }
+            let remaining = self.timeout - elapsed;
+            let probe_timeout = replies.lock().unwrap().rtt.get_timeout();
+            timing::wait_for_replies(if remaining < probe_timeout { remaining } else { probe_timeout }, || {
+                replies.lock().unwrap().up_hosts.len() >= self.target_hosts.len()
+            });
+            // Probes sent again wait longer, until a new reply is measured
+            replies.lock().unwrap().rtt.back_off();
+        }
+        *stop.lock().unwrap() = true;
+        #[cfg(not(target_os = "windows"))]
+        for handle in handles {
+            let _ = handle.join();
+        }
+        #[cfg(target_os = "windows")]
+        drop(handles);
+        let replies = replies.lock().unwrap();
+        let mut hosts: Vec<(IpAddr, Duration)> = replies.up_hosts.iter().map(|(host, host_rtt)| (*host, *host_rtt)).collect();
+        hosts.sort_by_key(|(host, _)| *host);
+        Ok(HostStateResult {
+            up_hosts: hosts,
+            probe_names: replies.probe_names.clone(),
+            fingerprints: replies.fingerprints.clone(),
+            mac_addrs: vec![],
+            scan_time: Instant::now().duration_since(start_time),
+            scan_status: scan_status,
+        })
+    }
+}
+
+// State shared with the receiver threads
+struct Replies {
+    /// Probes are keyed by host and index in the probe list.
+    rtt: RttTracker<(IpAddr, usize)>,
+    up_hosts: HashMap<IpAddr, Duration>,
+    probe_names: Vec<(IpAddr, String)>,
+    fingerprints: Vec<(IpAddr, OsFingerprint)>,
+}
+
+impl Replies {
+    fn new(timing: &Timing) -> Replies {
+        Replies {
+            rtt: RttTracker::new(timing),
+            up_hosts: HashMap::new(),
+            probe_names: vec![],
+            fingerprints: vec![],
+        }
+    }
+    fn add_up_host(&mut self, host: IpAddr, index: usize, probe: HostProbe, fingerprint: OsFingerprint) {
+        let host_rtt = self.rtt.on_reply(&(host, index));
+        if self.up_hosts.contains_key(&host) {
+            return;
+        }
+        self.up_hosts.insert(host, host_rtt.unwrap_or(Duration::from_millis(0)));
+        self.probe_names.push((host, probe.get_name()));
+        self.fingerprints.push((host, fingerprint));
+    }
+}
+
+// Identifies replies to our own probes
+#[derive(Clone)]
+struct ReplyMatcher {
+    target_hosts: HashSet<IpAddr>,
+    probes: Vec<HostProbe>,
+    src_port_base: u16,
+}
+
+impl ReplyMatcher {
+    fn find_icmp_probe(&self, probe: HostProbe) -> Option<usize> {
+        self.probes.iter().position(|p| *p == probe)
+    }
+    // Index of the TCP or UDP probe sent from `src_port` to `dst_port`
+    fn find_port_probe(&self, protocol: IpNextHeaderProtocol, src_port: u16, dst_port: u16) -> Option<usize> {
+        let index = src_port.checked_sub(self.src_port_base)? as usize;
+        let probe = self.probes.get(index)?;
+        if probe.get_protocol() == protocol && probe.get_dst_port() == Some(dst_port) {
+            Some(index)
+        }else{
+            None
+        }
+    }
+}
+
+fn send_probe(tx: &mut TransportSender, probe: HostProbe, src_ipaddr: IpAddr, src_port: u16, dst_ipaddr: IpAddr, sequence: u16) -> Result<(), String> {
+    let (src, dst) = match (src_ipaddr, dst_ipaddr) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => (src, dst),
+        _ => return Err(String::from("Not an IPv4 address")),
+    };
+    let mut buf = [0u8; MAX_PROBE_LEN];
+    let len = match probe {
+        HostProbe::IcmpEcho | HostProbe::IcmpTimestamp => {
+            build_icmp_request(&mut buf, probe, sequence).packet().len()
+        },
+        HostProbe::TcpSyn(dst_port) | HostProbe::TcpAck(dst_port) => {
+            build_tcp_probe(&mut buf[..TCP_HEADER_LEN], probe, src_ipaddr, src_port, dst_ipaddr, dst_port).packet().len()
+        },
+        HostProbe::Udp(dst_port) => {
+            build_udp_probe(&mut buf[..UDP_HEADER_LEN], src_ipaddr, src_port, dst_ipaddr, dst_port).packet().len()
+        },
+    };
+    let mut ip_buf = [0u8; tcp::IPV4_HEADER_LEN + MAX_PROBE_LEN];
+    let packet = tcp::build_ipv4_packet(&mut ip_buf, src, dst, probe.get_protocol(), &buf[..len]);
+    match tx.send_to(packet, dst_ipaddr) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+fn build_icmp_request(buf: &mut [u8], probe: HostProbe, sequence: u16) -> MutableIcmpPacket<'_> {
+    let len = if probe == HostProbe::IcmpTimestamp { ICMP_TIMESTAMP_LEN } else { ICMP_ECHO_LEN };
+    let buf = &mut buf[..len];
+    buf[4..6].copy_from_slice(&ICMP_IDENTIFIER.to_be_bytes());
+    buf[6..8].copy_from_slice(&sequence.to_be_bytes());
+    if probe == HostProbe::IcmpTimestamp {
+        // Originate timestamp in milliseconds since midnight UTC
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_millis(0));
+        let originate = (now.as_millis() % 86_400_000) as u32;
+        buf[8..12].copy_from_slice(&originate.to_be_bytes());
+    }
+    let mut icmp_packet = MutableIcmpPacket::new(buf).unwrap();
+    icmp_packet.set_icmp_type(if probe == HostProbe::IcmpTimestamp { IcmpTypes::Timestamp } else { IcmpTypes::EchoRequest });
+    let checksum = pnet::util::checksum(icmp_packet.packet(), 1);
+    icmp_packet.set_checksum(checksum);
+    icmp_packet
+}
+
+fn build_tcp_probe(buf: &mut [u8], probe: HostProbe, src_ipaddr: IpAddr, src_port: u16, dst_ipaddr: IpAddr, dst_port: u16) -> MutableTcpPacket<'_> {
+    let mut tcp_packet = MutableTcpPacket::new(buf).unwrap();
+    tcp_packet.set_source(src_port);
+    tcp_packet.set_destination(dst_port);
+    tcp_packet.set_window(1024);
+    tcp_packet.set_data_offset(5);
+    match probe {
+        HostProbe::TcpAck(_) => {
+            tcp_packet.set_acknowledgement(PROBE_SEQUENCE);
+            tcp_packet.set_flags(TcpFlags::ACK);
+        },
+        _ => {
+            tcp_packet.set_sequence(PROBE_SEQUENCE);
+            tcp_packet.set_flags(TcpFlags::SYN);
+        },
+    }
+    let checksum = match (src_ipaddr, dst_ipaddr) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => pnet::packet::tcp::ipv4_checksum(&tcp_packet.to_immutable(), &src, &dst),
+        _ => 0,
+    };
+    tcp_packet.set_checksum(checksum);
+    tcp_packet
+}
+
+fn build_udp_probe(buf: &mut [u8], src_ipaddr: IpAddr, src_port: u16, dst_ipaddr: IpAddr, dst_port: u16) -> MutableUdpPacket<'_> {
+    let mut udp_packet = MutableUdpPacket::new(buf).unwrap();
+    udp_packet.set_source(src_port);
+    udp_packet.set_destination(dst_port);
+    udp_packet.set_length(UDP_HEADER_LEN as u16);
+    let checksum = match (src_ipaddr, dst_ipaddr) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => pnet::packet::udp::ipv4_checksum(&udp_packet.to_immutable(), &src, &dst),
+        _ => 0,
+    };
+    udp_packet.set_checksum(checksum);
+    udp_packet
+}
+
+// Index of the TCP or UDP probe quoted in an ICMP unreachable message
+fn get_quoted_probe(icmp_packet: &IcmpPacket, matcher: &ReplyMatcher) -> Option<usize> {
+    // Unused field before the original IPv4 header
+    let quoted = Ipv4Packet::new(icmp_packet.payload().get(4..)?)?;
+    let protocol = quoted.get_next_level_protocol();
+    let header_len = quoted.get_header_length() as usize * 4;
+    let ports = quoted.packet().get(header_len..header_len + 4)?;
+    let src_port = u16::from_be_bytes([ports[0], ports[1]]);
+    let dst_port = u16::from_be_bytes([ports[2], ports[3]]);
+    matcher.find_port_probe(protocol, src_port, dst_port)
+}
+
+fn handle_reply(packet: &Ipv4Packet, host: IpAddr, matcher: &ReplyMatcher, replies: &Arc<Mutex<Replies>>) {
+    let ttl = packet.get_ttl();
+    let mut fingerprint = OsFingerprint::from_ttl(ttl);
+    let index = match packet.get_next_level_protocol() {
+        IpNextHeaderProtocols::Icmp => {
+            let icmp_packet = match IcmpPacket::new(packet.payload()) {
+                Some(icmp_packet) => icmp_packet,
+                None => return,
+            };
+            let payload = icmp_packet.payload();
+            let icmp_type = icmp_packet.get_icmp_type();
+            if icmp_type == IcmpTypes::DestinationUnreachable {
+                get_quoted_probe(&icmp_packet, matcher)
+            }else if payload.len() < 2 || u16::from_be_bytes([payload[0], payload[1]]) != ICMP_IDENTIFIER {
+                None
+            }else if icmp_type == IcmpTypes::EchoReply {
+                matcher.find_icmp_probe(HostProbe::IcmpEcho)
+            }else if icmp_type == IcmpTypes::TimestampReply {
+                matcher.find_icmp_probe(HostProbe::IcmpTimestamp)
+            }else{
+                None
+            }
+        },
+        IpNextHeaderProtocols::Tcp => {
+            let tcp_packet = match TcpPacket::new(packet.payload()) {
+                Some(tcp_packet) => tcp_packet,
+                None => return,
+            };
+            if tcp_packet.get_flags() & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+                fingerprint = OsFingerprint::from_syn_ack(Some(ttl), &tcp_packet);
+            }
+            matcher.find_port_probe(IpNextHeaderProtocols::Tcp, tcp_packet.get_destination(), tcp_packet.get_source())
+        },
+        IpNextHeaderProtocols::Udp => {
+            let udp_packet = match UdpPacket::new(packet.payload()) {
+                Some(udp_packet) => udp_packet,
+                None => return,
+            };
+            matcher.find_port_probe(IpNextHeaderProtocols::Udp, udp_packet.get_destination(), udp_packet.get_source())
+        },
+        _ => None,
+    };
+    if let Some(index) = index {
+        replies.lock().unwrap().add_up_host(host, index, matcher.probes[index], fingerprint);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn receive_packets(
+    mut rx: TransportReceiver,
+    matcher: ReplyMatcher,
+    stop: &Arc<Mutex<bool>>,
+    replies: &Arc<Mutex<Replies>>) {
+    let mut iter = ipv4_packet_iter(&mut rx);
+    loop {
+        if let Ok(Some((packet, addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+            if matcher.target_hosts.contains(&addr) {
+                handle_reply(&packet, addr, &matcher, replies);
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn receive_packets(
+    mut rx: TransportReceiver,
+    matcher: ReplyMatcher,
+    stop: &Arc<Mutex<bool>>,
+    replies: &Arc<Mutex<Replies>>) {
+    let mut iter = ipv4_packet_iter(&mut rx);
+    loop {
+        if let Ok((packet, addr)) = iter.next() {
+            if matcher.target_hosts.contains(&addr) {
+                handle_reply(&packet, addr, &matcher, replies);
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
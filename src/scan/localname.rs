@@ -0,0 +1,611 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use super::rdns::{self, DNS_HEADER_LEN, TYPE_PTR, TYPE_TXT};
+
+/// Default time to wait for each mDNS, NetBIOS and SSDP reply.
+pub const DEFAULT_NAME_TIMEOUT: Duration = Duration::from_millis(1000);
+
+const MDNS_PORT: u16 = 5353;
+const NBNS_PORT: u16 = 137;
+const SSDP_PORT: u16 = 1900;
+const DNS_SD_SERVICES: &str = "_services._dns-sd._udp.local";
+// Service types asked for their instances, to find a model string
+const MAX_SERVICE_TYPES: usize = 8;
+// TXT keys holding the model. md: Google Cast, ty: printers, am: AirPlay
+const MODEL_TXT_KEYS: [&str; 5] = ["md", "model", "ty", "usb_MDL", "am"];
+const TYPE_NBSTAT: u16 = 0x21;
+const CLASS_IN: u16 = 1;
+const NBNS_NAME_LEN: usize = 18;
+const MAX_DESCRIPTION_LEN: u64 = 65536;
+
+/// Names, model and services a host announces on the local network.
+#[derive(Clone)]
+pub struct LocalNameInfo {
+    /// mDNS host name, NetBIOS computer name and UPnP friendly name, in this order.
+    pub names: Vec<String>,
+    /// NetBIOS workgroup or domain.
+    pub workgroup: Option<String>,
+    /// Model from DNS-SD TXT records, the UPnP device description or the SSDP SERVER header.
+    pub model: Option<String>,
+    /// DNS-SD service types and UPnP device types. Ex: _ipp._tcp, upnp:MediaRenderer
+    pub services: Vec<String>,
+}
+
+impl LocalNameInfo {
+    pub fn new() -> LocalNameInfo {
+        LocalNameInfo {
+            names: vec![],
+            workgroup: None,
+            model: None,
+            services: vec![],
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.workgroup.is_none() && self.model.is_none() && self.services.is_empty()
+    }
+    /// Ex: "printer.local, PRINTER01, model: HP LaserJet 400, services: _ipp._tcp _http._tcp"
+    pub fn get_detail(&self) -> String {
+        let mut parts: Vec<String> = self.names.clone();
+        if let Some(workgroup) = &self.workgroup {
+            parts.push(format!("workgroup: {}", workgroup));
+        }
+        if let Some(model) = &self.model {
+            parts.push(format!("model: {}", model));
+        }
+        if !self.services.is_empty() {
+            parts.push(format!("services: {}", self.services.join(" ")));
+        }
+        parts.join(", ")
+    }
+    fn add_name(&mut self, name: &str) {
+        let name = name.trim();
+        if !name.is_empty() && !self.names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            self.names.push(name.to_string());
+        }
+    }
+    fn add_service(&mut self, service: &str) {
+        if !service.is_empty() && !self.services.iter().any(|s| s == service) {
+            self.services.push(service.to_string());
+        }
+    }
+    fn set_model(&mut self, model: &str) {
+        let model = model.trim();
+        if self.model.is_none() && !model.is_empty() {
+            self.model = Some(model.to_string());
+        }
+    }
+}
+
+// Resource record of a DNS message. Values are the name of PTR records and the strings of TXT records.
+struct DnsRecord {
+    name: String,
+    rtype: u16,
+    values: Vec<String>,
+}
+
+/// Structure for name discovery with mDNS/DNS-SD, NetBIOS node status and SSDP.
+///
+/// Queries are sent to the host itself, so hosts on other subnets can answer as well
+/// if they reply to unicast queries.
+pub struct LocalNameResolver {
+    timeout: Duration,
+}
+
+impl LocalNameResolver {
+    pub fn new() -> LocalNameResolver {
+        LocalNameResolver {
+            timeout: DEFAULT_NAME_TIMEOUT,
+        }
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        if timeout < self.timeout {
+            self.timeout = timeout;
+        }
+    }
+    /// Ask the host with each protocol. Hosts that do not speak a protocol simply do not answer it.
+    pub fn lookup(&self, ip_addr: IpAddr) -> LocalNameInfo {
+        let mut info = LocalNameInfo::new();
+        let _ = self.query_mdns(ip_addr, &mut info);
+        if ip_addr.is_ipv4() {
+            let _ = self.query_nbns(ip_addr, &mut info);
+        }
+        let _ = self.query_ssdp(ip_addr, &mut info);
+        info
+    }
+    fn query_mdns(&self, ip_addr: IpAddr, info: &mut LocalNameInfo) -> Result<(), String> {
+        let socket = bind_udp_socket(ip_addr, self.timeout)?;
+        let dst = SocketAddr::new(ip_addr, MDNS_PORT);
+        // Queries from a port other than 5353 are answered directly to the sender.
+        // Responders without a reverse record may still list their services.
+        if let Ok(records) = self.query_dns(&socket, dst, &rdns::get_ptr_name(ip_addr), TYPE_PTR) {
+            if let Some(name) = records.iter().filter(|r| r.rtype == TYPE_PTR).flat_map(|r| r.values.iter()).next() {
+                info.add_name(name);
+            }
+        }
+        let records = self.query_dns(&socket, dst, DNS_SD_SERVICES, TYPE_PTR)?;
+        let service_types: Vec<String> = records.iter()
+            .filter(|r| r.rtype == TYPE_PTR && r.name.eq_ignore_ascii_case(DNS_SD_SERVICES))
+            .flat_map(|r| r.values.clone())
+            .collect();
+        for service_type in service_types.iter().take(MAX_SERVICE_TYPES) {
+            info.add_service(trim_local_domain(service_type));
+            if info.model.is_some() {
+                continue;
+            }
+            // Responders usually add the TXT records of the instances to the answer
+            let records = match self.query_dns(&socket, dst, service_type, TYPE_PTR) {
+                Ok(records) => records,
+                Err(_) => continue,
+            };
+            if let Some(model) = get_txt_model(&records) {
+                info.set_model(&model);
+                continue;
+            }
+            let instance = match records.iter().filter(|r| r.rtype == TYPE_PTR).flat_map(|r| r.values.iter()).next() {
+                Some(instance) => instance.to_string(),
+                None => continue,
+            };
+            if let Ok(records) = self.query_dns(&socket, dst, &instance, TYPE_TXT) {
+                if let Some(model) = get_txt_model(&records) {
+                    info.set_model(&model);
+                }
+            }
+        }
+        for service_type in service_types.iter().skip(MAX_SERVICE_TYPES) {
+            info.add_service(trim_local_domain(service_type));
+        }
+        Ok(())
+    }
+    // Send a query and return the records of the matching reply
+    fn query_dns(&self, socket: &UdpSocket, dst: SocketAddr, name: &str, qtype: u16) -> Result<Vec<DnsRecord>, String> {
+        let id: u16 = rand::thread_rng().gen();
+        let query = rdns::build_query(id, name, qtype, false)?;
+        if let Err(e) = socket.send_to(&query, dst) {
+            return Err(format!("{}", e));
+        }
+        let start_time = Instant::now();
+        let mut buf = [0u8; 9000];
+        loop {
+            if Instant::now().duration_since(start_time) > self.timeout {
+                return Err(format!("No mDNS reply from {}", dst.ip()));
+            }
+            let (len, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => return Err(format!("{}", e)),
+            };
+            if addr.ip() != dst.ip() || len < DNS_HEADER_LEN || u16::from_be_bytes([buf[0], buf[1]]) != id {
+                continue;
+            }
+            return Ok(parse_records(&buf[..len]).unwrap_or(vec![]));
+        }
+    }
+    fn query_nbns(&self, ip_addr: IpAddr, info: &mut LocalNameInfo) -> Result<(), String> {
+        let socket = bind_udp_socket(ip_addr, self.timeout)?;
+        let id: u16 = rand::thread_rng().gen();
+        let mut query: Vec<u8> = vec![];
+        query.extend_from_slice(&id.to_be_bytes());
+        // No flags, one question
+        query.extend_from_slice(&[0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        // Wildcard name "*" padded with zeros, in first-level encoding
+        let mut name = [0u8; 16];
+        name[0] = b'*';
+        query.push(32);
+        for b in name.iter() {
+            query.push(b'A' + (b >> 4));
+            query.push(b'A' + (b & 0x0f));
+        }
+        query.push(0);
+        query.extend_from_slice(&TYPE_NBSTAT.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        if let Err(e) = socket.send_to(&query, SocketAddr::new(ip_addr, NBNS_PORT)) {
+            return Err(format!("{}", e));
+        }
+        let start_time = Instant::now();
+        let mut buf = [0u8; 1500];
+        let response = loop {
+            if Instant::now().duration_since(start_time) > self.timeout {
+                return Err(format!("No NetBIOS reply from {}", ip_addr));
+            }
+            let (len, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => return Err(format!("{}", e)),
+            };
+            if addr.ip() == ip_addr && len >= DNS_HEADER_LEN && u16::from_be_bytes([buf[0], buf[1]]) == id {
+                break &buf[..len];
+            }
+        };
+        for (name, suffix, group) in parse_node_status(response).unwrap_or(vec![]) {
+            // Suffix 0x00 is the workstation service of the computer, or the workgroup for group names
+            if suffix != 0x00 {
+                continue;
+            }
+            if group {
+                if info.workgroup.is_none() {
+                    info.workgroup = Some(name);
+                }
+            }else{
+                info.add_name(&name);
+            }
+        }
+        Ok(())
+    }
+    fn query_ssdp(&self, ip_addr: IpAddr, info: &mut LocalNameInfo) -> Result<(), String> {
+        let socket = bind_udp_socket(ip_addr, self.timeout)?;
+        let dst = SocketAddr::new(ip_addr, SSDP_PORT);
+        // Unicast M-SEARCH has the host address in HOST and no MX
+        let request = format!("M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nST: ssdp:all\r\n\r\n", dst);
+        if let Err(e) = socket.send_to(request.as_bytes(), dst) {
+            return Err(format!("{}", e));
+        }
+        let start_time = Instant::now();
+        let mut buf = [0u8; 2048];
+        let mut location: Option<String> = None;
+        let mut server: Option<String> = None;
+        // A device sends one response for each of its device and service types
+        while Instant::now().duration_since(start_time) < self.timeout {
+            let (len, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(_) => break,
+            };
+            if addr.ip() != ip_addr {
+                continue;
+            }
+            let response = String::from_utf8_lossy(&buf[..len]).to_string();
+            if !response.starts_with("HTTP/1.1 200") {
+                continue;
+            }
+            if location.is_none() {
+                location = get_header_value(&response, "LOCATION");
+            }
+            if server.is_none() {
+                server = get_header_value(&response, "SERVER");
+            }
+            if let Some(st) = get_header_value(&response, "ST") {
+                // Ex: urn:schemas-upnp-org:device:MediaRenderer:1
+                let fields: Vec<&str> = st.split(":").collect();
+                if fields.len() >= 4 && fields[0] == "urn" && fields[2] == "device" {
+                    info.add_service(&format!("upnp:{}", fields[3]));
+                }
+            }
+        }
+        if location.is_none() && server.is_none() {
+            return Err(format!("No SSDP reply from {}", ip_addr));
+        }
+        if let Some(location) = location {
+            if let Ok(description) = self.get_upnp_description(ip_addr, &location) {
+                if let Some(name) = get_xml_value(&description, "friendlyName") {
+                    info.add_name(&name);
+                }
+                let manufacturer = get_xml_value(&description, "manufacturer").unwrap_or(String::new());
+                if let Some(model_name) = get_xml_value(&description, "modelName") {
+                    if model_name.starts_with(&manufacturer) {
+                        info.set_model(&model_name);
+                    }else{
+                        info.set_model(&format!("{} {}", manufacturer, model_name));
+                    }
+                }
+            }
+        }
+        if let Some(server) = server {
+            info.set_model(&server);
+        }
+        Ok(())
+    }
+    // Device description XML at the LOCATION of an SSDP response. Only fetched from the host itself.
+    fn get_upnp_description(&self, ip_addr: IpAddr, location: &str) -> Result<String, String> {
+        if !location.starts_with("http://") {
+            return Err(format!("Unsupported location: {}", location));
+        }
+        let rest = &location["http://".len()..];
+        let (authority, path) = match rest.find("/") {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let addr: SocketAddr = match authority.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => match authority.trim_start_matches("[").trim_end_matches("]").parse::<IpAddr>() {
+                Ok(ip) => SocketAddr::new(ip, 80),
+                Err(_) => return Err(format!("Invalid location: {}", location)),
+            },
+        };
+        if addr.ip() != ip_addr {
+            return Err(format!("Description of {} is on another host: {}", ip_addr, location));
+        }
+        let mut stream = match TcpStream::connect_timeout(&addr, self.timeout) {
+            Ok(stream) => stream,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        if let Err(e) = stream.set_read_timeout(Some(self.timeout)) {
+            return Err(format!("{}", e));
+        }
+        let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, authority);
+        if let Err(e) = stream.write_all(request.as_bytes()) {
+            return Err(format!("{}", e));
+        }
+        let mut response: Vec<u8> = vec![];
+        if let Err(e) = stream.take(MAX_DESCRIPTION_LEN).read_to_end(&mut response) {
+            // Keep what arrived before the timeout
+            if response.is_empty() {
+                return Err(format!("{}", e));
+            }
+        }
+        let response = String::from_utf8_lossy(&response).to_string();
+        match response.find("\r\n\r\n") {
+            Some(i) => Ok(response[i + 4..].to_string()),
+            None => Err(format!("Invalid HTTP response from {}", addr)),
+        }
+    }
+}
+
+fn bind_udp_socket(ip_addr: IpAddr, timeout: Duration) -> Result<UdpSocket, String> {
+    let bind_addr = match ip_addr {
+        IpAddr::V4(_) => "0.0.0.0:0",
+        IpAddr::V6(_) => "[::]:0",
+    };
+    let socket = match UdpSocket::bind(bind_addr) {
+        Ok(socket) => socket,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    if let Err(e) = socket.set_read_timeout(Some(timeout)) {
+        return Err(format!("{}", e));
+    }
+    Ok(socket)
+}
+
+// Ex: _ipp._tcp.local -> _ipp._tcp
+fn trim_local_domain(name: &str) -> &str {
+    let pos = name.len().saturating_sub(".local".len());
+    if name.is_char_boundary(pos) && name[pos..].eq_ignore_ascii_case(".local") {
+        &name[..pos]
+    }else{
+        name
+    }
+}
+
+// Records in the answer, authority and additional sections
+fn parse_records(message: &[u8]) -> Option<Vec<DnsRecord>> {
+    let question_count = u16::from_be_bytes([message[4], message[5]]);
+    let record_count = u16::from_be_bytes([message[6], message[7]]) as usize
+        + u16::from_be_bytes([message[8], message[9]]) as usize
+        + u16::from_be_bytes([message[10], message[11]]) as usize;
+    let mut pos = DNS_HEADER_LEN;
+    for _ in 0..question_count {
+        pos = rdns::skip_name(message, pos)? + 4;
+    }
+    let mut records: Vec<DnsRecord> = vec![];
+    for _ in 0..record_count {
+        let name = rdns::read_name(message, pos).unwrap_or(String::new());
+        pos = rdns::skip_name(message, pos)?;
+        let fields = message.get(pos..pos + 10)?;
+        let rtype = u16::from_be_bytes([fields[0], fields[1]]);
+        let rdlength = u16::from_be_bytes([fields[8], fields[9]]) as usize;
+        pos += 10;
+        let rdata = message.get(pos..pos + rdlength)?;
+        let values = match rtype {
+            TYPE_PTR => rdns::read_name(message, pos).into_iter().collect(),
+            TYPE_TXT => read_txt_strings(rdata),
+            _ => vec![],
+        };
+        records.push(DnsRecord {
+            name: name,
+            rtype: rtype,
+            values: values,
+        });
+        pos += rdlength;
+    }
+    Some(records)
+}
+
+// TXT data is a sequence of length-prefixed strings. Ex: "md=Chromecast"
+fn read_txt_strings(rdata: &[u8]) -> Vec<String> {
+    let mut strings: Vec<String> = vec![];
+    let mut pos = 0;
+    while pos < rdata.len() {
+        let len = rdata[pos] as usize;
+        match rdata.get(pos + 1..pos + 1 + len) {
+            Some(s) => strings.push(String::from_utf8_lossy(s).to_string()),
+            None => break,
+        }
+        pos += len + 1;
+    }
+    strings
+}
+
+fn get_txt_model(records: &[DnsRecord]) -> Option<String> {
+    let values: Vec<&String> = records.iter().filter(|r| r.rtype == TYPE_TXT).flat_map(|r| r.values.iter()).collect();
+    for key in MODEL_TXT_KEYS.iter() {
+        let prefix = format!("{}=", key);
+        if let Some(value) = values.iter().find(|v| v.starts_with(&prefix)) {
+            let model = value[prefix.len()..].trim();
+            if !model.is_empty() {
+                return Some(model.to_string());
+            }
+        }
+    }
+    None
+}
+
+// Names in a NetBIOS node status response, with their suffix and whether they are group names
+fn parse_node_status(response: &[u8]) -> Option<Vec<(String, u8, bool)>> {
+    // Response code other than success
+    if response[3] & 0x0f != 0 || u16::from_be_bytes([response[6], response[7]]) == 0 {
+        return None;
+    }
+    let mut pos = rdns::skip_name(response, DNS_HEADER_LEN)?;
+    let fields = response.get(pos..pos + 10)?;
+    if u16::from_be_bytes([fields[0], fields[1]]) != TYPE_NBSTAT {
+        return None;
+    }
+    pos += 10;
+    let name_count = *response.get(pos)? as usize;
+    pos += 1;
+    let mut names: Vec<(String, u8, bool)> = vec![];
+    for _ in 0..name_count {
+        let entry = response.get(pos..pos + NBNS_NAME_LEN)?;
+        let name = String::from_utf8_lossy(&entry[..15]).trim_end().to_string();
+        let flags = u16::from_be_bytes([entry[16], entry[17]]);
+        names.push((name, entry[15], flags & 0x8000 != 0));
+        pos += NBNS_NAME_LEN;
+    }
+    Some(names)
+}
+
+// Value of a header in an SSDP response. Header names are case-insensitive.
+fn get_header_value(response: &str, name: &str) -> Option<String> {
+    for line in response.lines().skip(1) {
+        if let Some(i) = line.find(":") {
+            if line[..i].trim().eq_ignore_ascii_case(name) {
+                return Some(line[i + 1..].trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+// Text of the first element with the tag. Ex: <modelName>Sonos Play:1</modelName>
+fn get_xml_value(xml: &str, tag: &str) -> Option<String> {
+    let start_tag = format!("<{}>", tag);
+    let end_tag = format!("</{}>", tag);
+    let start = xml.find(&start_tag)? + start_tag.len();
+    let end = start + xml[start..].find(&end_tag)?;
+    let value = xml[start..end].trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    if value.is_empty() {
+        None
+    }else{
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reply to a DNS-SD service type query, with the TXT record of one instance
+    fn mdns_response() -> Vec<u8> {
+        let mut message: Vec<u8> = vec![0x00, 0x00, 0x84, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01];
+        // Question at 12. "local" is at 35 (0x23)
+        message.extend_from_slice(b"\x09_services\x07_dns-sd\x04_udp\x05local\x00\x00\x0c\x00\x01");
+        // PTR records named by a pointer to the question, rdata at 58 (0x3a) and 82
+        message.extend_from_slice(b"\xc0\x0c\x00\x0c\x00\x01\x00\x00\x11\x94\x00\x0c\x04_ipp\x04_tcp\xc0\x23");
+        message.extend_from_slice(b"\xc0\x0c\x00\x0c\x00\x01\x00\x00\x11\x94\x00\x0d\x05_http\x04_tcp\xc0\x23");
+        // TXT record named by a pointer into the rdata of the first PTR record
+        message.extend_from_slice(b"\xc0\x3a\x00\x10\x00\x01\x00\x00\x11\x94\x00\x15\x0dmd=Chromecast\x06ty=foo");
+        message
+    }
+
+    // Node status reply with a computer name, its workgroup and the file server name
+    fn node_status_response() -> Vec<u8> {
+        let mut response: Vec<u8> = vec![0x12, 0x34, 0x84, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00];
+        response.push(32);
+        response.extend_from_slice(b"CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\x00");
+        response.extend_from_slice(&[0x00, 0x21, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x41]);
+        response.push(3);
+        for (name, suffix, flags) in [("DESKTOP-1", 0x00u8, 0x0400u16), ("WORKGROUP", 0x00, 0x8400), ("DESKTOP-1", 0x20, 0x0400)].iter() {
+            response.extend_from_slice(format!("{:<15}", name).as_bytes());
+            response.push(*suffix);
+            response.extend_from_slice(&flags.to_be_bytes());
+        }
+        // Statistics, not read
+        response.extend_from_slice(&[0u8; 46]);
+        response
+    }
+
+    #[test]
+    fn dns_sd_records() {
+        let records = parse_records(&mdns_response()).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].name, "_services._dns-sd._udp.local");
+        assert_eq!(records[0].rtype, TYPE_PTR);
+        assert_eq!(records[0].values, vec!["_ipp._tcp.local"]);
+        assert_eq!(records[1].values, vec!["_http._tcp.local"]);
+        assert_eq!(records[2].name, "_ipp._tcp.local");
+        assert_eq!(records[2].rtype, TYPE_TXT);
+        assert_eq!(records[2].values, vec!["md=Chromecast", "ty=foo"]);
+        assert_eq!(get_txt_model(&records), Some(String::from("Chromecast")));
+    }
+
+    #[test]
+    fn truncated_records() {
+        let message = mdns_response();
+        // Inside the question, the fixed fields of a record and the rdata
+        assert!(parse_records(&message[..30]).is_none());
+        assert!(parse_records(&message[..100]).is_none());
+        assert!(parse_records(&message[..message.len() - 1]).is_none());
+        // Fewer records than the counts say
+        assert!(parse_records(&message[..95]).is_none());
+        // A TXT string longer than the rdata keeps the strings before it
+        assert_eq!(read_txt_strings(b"\x05md=TV\x09ty"), vec!["md=TV"]);
+    }
+
+    #[test]
+    fn pointer_loop() {
+        let mut message = mdns_response();
+        // Name of the first PTR record points to itself
+        message[46] = 0xc0;
+        message[47] = 0x2e;
+        let records = parse_records(&message).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].name, "");
+        assert_eq!(records[0].values, vec!["_ipp._tcp.local"]);
+        // So does the rdata of the second
+        message[82] = 0xc0;
+        message[83] = 0x52;
+        let records = parse_records(&message).unwrap();
+        assert!(records[1].values.is_empty());
+    }
+
+    #[test]
+    fn node_status_names() {
+        let names = parse_node_status(&node_status_response()).unwrap();
+        assert_eq!(names, vec![
+            (String::from("DESKTOP-1"), 0x00, false),
+            (String::from("WORKGROUP"), 0x00, true),
+            (String::from("DESKTOP-1"), 0x20, false),
+        ]);
+    }
+
+    #[test]
+    fn invalid_node_status() {
+        let response = node_status_response();
+        // Entries cut short
+        assert!(parse_node_status(&response[..57 + 18 * 2 + 5]).is_none());
+        assert!(parse_node_status(&response[..50]).is_none());
+        // Error code, no answer and another record type
+        let mut error = response.clone();
+        error[3] = 0x03;
+        assert!(parse_node_status(&error).is_none());
+        let mut no_answer = response.clone();
+        no_answer[7] = 0;
+        assert!(parse_node_status(&no_answer).is_none());
+        let mut other_type = response.clone();
+        other_type[47] = 0x20;
+        assert!(parse_node_status(&other_type).is_none());
+    }
+
+    #[test]
+    fn xml_values() {
+        let xml = "<root><device><friendlyName>Living Room</friendlyName><modelName> Sonos &amp; Co &lt;1&gt; </modelName><manufacturer>&amp;lt;</manufacturer><empty></empty><open>x</device></root>";
+        assert_eq!(get_xml_value(xml, "friendlyName"), Some(String::from("Living Room")));
+        assert_eq!(get_xml_value(xml, "modelName"), Some(String::from("Sonos & Co <1>")));
+        assert_eq!(get_xml_value(xml, "manufacturer"), Some(String::from("&lt;")));
+        assert_eq!(get_xml_value(xml, "empty"), None);
+        assert_eq!(get_xml_value(xml, "open"), None);
+        assert_eq!(get_xml_value(xml, "serialNumber"), None);
+    }
+
+    #[test]
+    fn local_domain() {
+        assert_eq!(trim_local_domain("_ipp._tcp.local"), "_ipp._tcp");
+        assert_eq!(trim_local_domain("printer.LOCAL"), "printer");
+        assert_eq!(trim_local_domain("local"), "local");
+        assert_eq!(trim_local_domain("printer.example.com"), "printer.example.com");
+        assert_eq!(trim_local_domain("é.loca"), "é.loca");
+    }
+}
@@ -0,0 +1,49 @@
+pub mod connect;
+pub mod arp;
+pub mod host;
+pub mod ndp;
+pub mod tcp;
+pub mod udp;
+pub mod os;
+pub mod rdns;
+pub mod localname;
+pub mod service;
+pub mod tls;
+pub mod timing;
+
+use std::time::Duration;
+use nerve_base::ScanStatus;
+use os::OsFingerprint;
+
+/// State of a scanned port.  
+#[derive(Clone, Copy, PartialEq)]
+pub enum PortState {
+    Open,
+    Closed,
+    Filtered,
+    OpenFiltered,
+}
+
+impl PortState {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::OpenFiltered => "open|filtered",
+        }
+    }
+}
+
+/// Result of a port scan with the state of each probed port.  
+#[derive(Clone)]
+pub struct PortStateResult {
+    /// List of port number and its state.  
+    pub ports: Vec<(u16, PortState)>,
+    /// Smoothed round-trip time to the host, if any probe was answered.  
+    pub rtt: Option<Duration>,
+    /// Header values of the first SYN/ACK, for OS detection.  
+    pub fingerprint: Option<OsFingerprint>,
+    /// Scan job status
+    pub scan_status: ScanStatus,
+}
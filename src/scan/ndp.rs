@@ -0,0 +1,375 @@
+use std::thread;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use ipnet::Ipv6Net;
+use pnet::datalink::{self, Channel, DataLinkReceiver, MacAddr, NetworkInterface};
+use pnet::packet::Packet;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmpv6::{self, Icmpv6Packet, Icmpv6Types, MutableIcmpv6Packet};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+use nerve_base::ScanStatus;
+use super::host::HostStateResult;
+use super::os::OsFingerprint;
+use super::timing::{self, RttEstimator, Timing};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV6_HEADER_LEN: usize = 40;
+const ICMPV6_ECHO_LEN: usize = 8;
+// Type, code, checksum, reserved, target address and source link-layer address option
+const NEIGHBOR_SOLICIT_LEN: usize = 32;
+const ICMPV6_IDENTIFIER: u16 = 0x6e73;
+const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+const NDP_OPTION_SOURCE_LL: u8 = 1;
+const NDP_OPTION_TARGET_LL: u8 = 2;
+
+/// Structure for IPv6 host discovery on the local link.
+///
+/// Hosts are found with ICMPv6 echo requests to the all-nodes multicast address,
+/// then resolved to MAC addresses with Neighbor Solicitation.
+/// Hosts that ignore multicast echo requests (e.g. Windows by default) are not found.
+pub struct NdpScanner {
+    /// Interface to send from.
+    interface: NetworkInterface,
+    /// Source address. Hosts reply from an address of the same scope.
+    src_ipaddr: Ipv6Addr,
+    /// Hosts outside this network are ignored.
+    target_network: Ipv6Net,
+    /// Probe pacing and retransmission.
+    timing: Timing,
+    /// Timeout setting of host scan.
+    timeout: Duration,
+}
+
+impl NdpScanner {
+    /// Create a scanner on the interface with an address in the target network,
+    /// or on the given fallback interface.
+    /// Link-local networks are on every link, so those use the fallback interface first.
+    pub fn new(target_network: Ipv6Net, fallback_if_index: u32) -> Result<NdpScanner, String> {
+        let interfaces = datalink::interfaces();
+        let fallback = interfaces.iter().find(|iface| iface.index == fallback_if_index);
+        let in_network = interfaces.iter().find(|iface| iface.ips.iter().any(|ip| match ip.ip() {
+            IpAddr::V6(ipv6_addr) => target_network.contains(&ipv6_addr),
+            IpAddr::V4(_) => false,
+        }));
+        let found = if is_link_local(&target_network.network()) {
+            fallback.or(in_network)
+        }else{
+            in_network.or(fallback)
+        };
+        let interface = match found {
+            Some(interface) => interface.clone(),
+            None => return Err(String::from("No interface on the target network")),
+        };
+        let mut scanner = NdpScanner {
+            interface: interface,
+            src_ipaddr: Ipv6Addr::UNSPECIFIED,
+            target_network: target_network,
+            timing: Timing::new(),
+            timeout: Duration::from_millis(30000),
+        };
+        scanner.src_ipaddr = match scanner.get_src_ipaddr() {
+            Some(src_ipaddr) => src_ipaddr,
+            None => return Err(format!("No IPv6 address on interface {}", scanner.interface.name)),
+        };
+        Ok(scanner)
+    }
+    /// Send from the given interface instead.
+    pub fn set_interface(&mut self, interface: NetworkInterface) -> Result<(), String> {
+        self.interface = interface;
+        self.src_ipaddr = match self.get_src_ipaddr() {
+            Some(src_ipaddr) => src_ipaddr,
+            None => return Err(format!("No IPv6 address on interface {}", self.interface.name)),
+        };
+        Ok(())
+    }
+    pub fn set_timing(&mut self, timing: Timing){
+        self.timing = timing;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        self.timeout = timeout;
+    }
+    // Prefer an address in the target network, then a link-local address
+    fn get_src_ipaddr(&self) -> Option<Ipv6Addr> {
+        let ips: Vec<Ipv6Addr> = self.interface.ips.iter().filter_map(|ip| match ip.ip() {
+            IpAddr::V6(ipv6_addr) => Some(ipv6_addr),
+            IpAddr::V4(_) => None,
+        }).collect();
+        ips.iter().cloned().find(|ip| self.target_network.contains(ip))
+            .or(ips.iter().cloned().find(|ip| is_link_local(ip)))
+    }
+    pub fn run_scan(&self) -> Result<HostStateResult, String> {
+        let start_time = Instant::now();
+        let src_mac = match self.interface.mac {
+            Some(mac) => mac,
+            None => return Err(format!("No MAC address on interface {}", self.interface.name)),
+        };
+        let config = datalink::Config {
+            read_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let (mut tx, rx) = match datalink::channel(&self.interface, config) {
+            Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
+            Ok(_) => return Err(String::from("Unsupported channel type")),
+            Err(e) => return Err(format!("Failed to open datalink channel: {}", e)),
+        };
+        let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+        let replies: Arc<Mutex<Replies>> = Arc::new(Mutex::new(Replies::new(&self.timing)));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            let replies = Arc::clone(&replies);
+            let target_network = self.target_network;
+            thread::spawn(move || receive_packets(rx, target_network, &stop, &replies))
+        };
+        let mut scan_status = ScanStatus::Done;
+        // Multicast probes are answered by an unknown number of hosts,
+        // so every round waits the initial probe timeout.
+        let mut sequence: u16 = 0;
+        for _ in 0..self.timing.max_retries as usize + 1 {
+            let elapsed = Instant::now().duration_since(start_time);
+            if elapsed >= self.timeout {
+                scan_status = ScanStatus::Timeout;
+                break;
+            }
+            sequence = sequence.wrapping_add(1);
+            let packet = build_echo_request(src_mac, self.src_ipaddr, sequence);
+            replies.lock().unwrap().sent.insert(sequence, Instant::now());
+            if let Some(Err(e)) = tx.send_to(&packet, None) {
+                warn!("Failed to send echo request on {}: {}", self.interface.name, e);
+            }
+            let remaining = self.timeout - elapsed;
+            let wait = self.timing.initial_rtt_timeout;
+            timing::wait_for_replies(if remaining < wait { remaining } else { wait }, || false);
+        }
+        // Resolve MAC addresses of the hosts found
+        let mut send_failed = false;
+        for _ in 0..self.timing.max_retries as usize + 1 {
+            let unresolved: Vec<Ipv6Addr> = {
+                let replies = replies.lock().unwrap();
+                replies.up_hosts.iter().map(|(host, _)| *host).filter(|host| !replies.mac_addrs.contains_key(host)).collect()
+            };
+            if unresolved.is_empty() {
+                break;
+            }
+            for host in &unresolved {
+                if Instant::now().duration_since(start_time) > self.timeout {
+                    break;
+                }
+                thread::sleep(self.timing.get_send_delay());
+                let packet = build_neighbor_solicit(src_mac, self.src_ipaddr, *host);
+                if let Some(Err(e)) = tx.send_to(&packet, None) {
+                    // Logged once, the same error usually repeats for every host
+                    if !send_failed {
+                        warn!("Failed to send Neighbor Solicitation for {}: {}", host, e);
+                        send_failed = true;
+                    }
+                }
+            }
+            let elapsed = Instant::now().duration_since(start_time);
+            if elapsed >= self.timeout {
+                scan_status = ScanStatus::Timeout;
+                break;
+            }
+            let remaining = self.timeout - elapsed;
+            let probe_timeout = replies.lock().unwrap().rtt.get_timeout();
+            timing::wait_for_replies(if remaining < probe_timeout { remaining } else { probe_timeout }, || {
+                let replies = replies.lock().unwrap();
+                unresolved.iter().all(|host| replies.mac_addrs.contains_key(host))
+            });
+            // Probes sent again wait longer, until a new reply is measured
+            replies.lock().unwrap().rtt.back_off();
+        }
+        *stop.lock().unwrap() = true;
+        let _ = handle.join();
+        let replies = replies.lock().unwrap();
+        let mut up_hosts: Vec<(IpAddr, Duration)> = replies.up_hosts.iter().map(|(host, rtt)| (IpAddr::V6(*host), *rtt)).collect();
+        up_hosts.sort_by_key(|(host, _)| *host);
+        let mut mac_addrs: Vec<(IpAddr, MacAddr)> = vec![];
+        for (host, _) in &replies.up_hosts {
+            if let Some(mac) = replies.mac_addrs.get(host).or(replies.frame_macs.get(host)) {
+                mac_addrs.push((IpAddr::V6(*host), *mac));
+            }
+        }
+        let probe_names: Vec<(IpAddr, String)> = up_hosts.iter().map(|(host, _)| (*host, String::from("icmp6 echo"))).collect();
+        Ok(HostStateResult {
+            up_hosts: up_hosts,
+            probe_names: probe_names,
+            fingerprints: replies.fingerprints.iter().map(|(host, fingerprint)| (IpAddr::V6(*host), fingerprint.clone())).collect(),
+            mac_addrs: mac_addrs,
+            scan_time: Instant::now().duration_since(start_time),
+            scan_status: scan_status,
+        })
+    }
+}
+
+// State shared with the receiver thread
+struct Replies {
+    sent: HashMap<u16, Instant>,
+    rtt: RttEstimator,
+    up_hosts: Vec<(Ipv6Addr, Duration)>,
+    fingerprints: Vec<(Ipv6Addr, OsFingerprint)>,
+    /// Link-layer addresses from Neighbor Advertisements.
+    mac_addrs: HashMap<Ipv6Addr, MacAddr>,
+    /// Ethernet source of echo replies, used for hosts that did not advertise.
+    frame_macs: HashMap<Ipv6Addr, MacAddr>,
+}
+
+impl Replies {
+    fn new(timing: &Timing) -> Replies {
+        Replies {
+            sent: HashMap::new(),
+            rtt: RttEstimator::new(timing),
+            up_hosts: vec![],
+            fingerprints: vec![],
+            mac_addrs: HashMap::new(),
+            frame_macs: HashMap::new(),
+        }
+    }
+    fn add_up_host(&mut self, host: Ipv6Addr, sequence: u16, hop_limit: u8) {
+        if self.up_hosts.iter().any(|(h, _)| *h == host) {
+            return;
+        }
+        let host_rtt = match self.sent.get(&sequence) {
+            Some(sent_time) => Instant::now().duration_since(*sent_time),
+            None => Duration::from_millis(0),
+        };
+        self.rtt.add_sample(host_rtt);
+        self.up_hosts.push((host, host_rtt));
+        self.fingerprints.push((host, OsFingerprint::from_ttl(hop_limit)));
+    }
+}
+
+fn is_link_local(ipv6_addr: &Ipv6Addr) -> bool {
+    ipv6_addr.segments()[0] & 0xffc0 == 0xfe80
+}
+
+// Ethernet address of an IPv6 multicast group (33:33 followed by the last 4 bytes)
+fn get_multicast_mac(group: Ipv6Addr) -> MacAddr {
+    let octets = group.octets();
+    MacAddr::new(0x33, 0x33, octets[12], octets[13], octets[14], octets[15])
+}
+
+// Solicited-node multicast address (ff02::1:ffXX:XXXX) of a host
+fn get_solicited_node(ipv6_addr: Ipv6Addr) -> Ipv6Addr {
+    let octets = ipv6_addr.octets();
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00 | octets[13] as u16, u16::from_be_bytes([octets[14], octets[15]]))
+}
+
+fn build_packet(src_mac: MacAddr, src_ipaddr: Ipv6Addr, dst_ipaddr: Ipv6Addr, icmp_packet: &mut MutableIcmpv6Packet) -> Vec<u8> {
+    let checksum = icmpv6::checksum(&icmp_packet.to_immutable(), &src_ipaddr, &dst_ipaddr);
+    icmp_packet.set_checksum(checksum);
+    let icmp_len = icmp_packet.packet().len();
+    let mut buf = vec![0u8; ETHERNET_HEADER_LEN + IPV6_HEADER_LEN + icmp_len];
+    {
+        let mut ipv6_packet = MutableIpv6Packet::new(&mut buf[ETHERNET_HEADER_LEN..]).unwrap();
+        ipv6_packet.set_version(6);
+        ipv6_packet.set_payload_length(icmp_len as u16);
+        ipv6_packet.set_next_header(IpNextHeaderProtocols::Icmpv6);
+        // Neighbor Discovery messages must have a hop limit of 255
+        ipv6_packet.set_hop_limit(255);
+        ipv6_packet.set_source(src_ipaddr);
+        ipv6_packet.set_destination(dst_ipaddr);
+        ipv6_packet.set_payload(icmp_packet.packet());
+    }
+    let mut ethernet_packet = MutableEthernetPacket::new(&mut buf).unwrap();
+    ethernet_packet.set_destination(get_multicast_mac(dst_ipaddr));
+    ethernet_packet.set_source(src_mac);
+    ethernet_packet.set_ethertype(EtherTypes::Ipv6);
+    buf
+}
+
+fn build_echo_request(src_mac: MacAddr, src_ipaddr: Ipv6Addr, sequence: u16) -> Vec<u8> {
+    let mut buf = [0u8; ICMPV6_ECHO_LEN];
+    buf[4..6].copy_from_slice(&ICMPV6_IDENTIFIER.to_be_bytes());
+    buf[6..8].copy_from_slice(&sequence.to_be_bytes());
+    let mut icmp_packet = MutableIcmpv6Packet::new(&mut buf).unwrap();
+    icmp_packet.set_icmpv6_type(Icmpv6Types::EchoRequest);
+    build_packet(src_mac, src_ipaddr, ALL_NODES, &mut icmp_packet)
+}
+
+fn build_neighbor_solicit(src_mac: MacAddr, src_ipaddr: Ipv6Addr, target: Ipv6Addr) -> Vec<u8> {
+    let mut buf = [0u8; NEIGHBOR_SOLICIT_LEN];
+    buf[8..24].copy_from_slice(&target.octets());
+    buf[24] = NDP_OPTION_SOURCE_LL;
+    buf[25] = 1;
+    buf[26..32].copy_from_slice(&src_mac.octets());
+    let mut icmp_packet = MutableIcmpv6Packet::new(&mut buf).unwrap();
+    icmp_packet.set_icmpv6_type(Icmpv6Types::NeighborSolicit);
+    build_packet(src_mac, src_ipaddr, get_solicited_node(target), &mut icmp_packet)
+}
+
+// Return the target address and its link-layer address from a Neighbor Advertisement
+fn get_neighbor_advert(icmp_packet: &Icmpv6Packet, src_mac: MacAddr) -> Option<(Ipv6Addr, MacAddr)> {
+    let packet = icmp_packet.packet();
+    if packet.len() < 24 {
+        return None;
+    }
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&packet[8..24]);
+    let mut mac = src_mac;
+    let mut options = &packet[24..];
+    while options.len() >= 8 {
+        let option_len = options[1] as usize * 8;
+        if option_len == 0 || option_len > options.len() {
+            break;
+        }
+        if options[0] == NDP_OPTION_TARGET_LL {
+            mac = MacAddr::new(options[2], options[3], options[4], options[5], options[6], options[7]);
+        }
+        options = &options[option_len..];
+    }
+    Some((Ipv6Addr::from(octets), mac))
+}
+
+fn handle_packet(frame: &[u8], target_network: Ipv6Net, replies: &Arc<Mutex<Replies>>) {
+    let ethernet_packet = match EthernetPacket::new(frame) {
+        Some(packet) if packet.get_ethertype() == EtherTypes::Ipv6 => packet,
+        _ => return,
+    };
+    let ipv6_packet = match Ipv6Packet::new(ethernet_packet.payload()) {
+        Some(packet) if packet.get_next_header() == IpNextHeaderProtocols::Icmpv6 => packet,
+        _ => return,
+    };
+    let icmp_packet = match Icmpv6Packet::new(ipv6_packet.payload()) {
+        Some(packet) => packet,
+        None => return,
+    };
+    let src_ipaddr = ipv6_packet.get_source();
+    match icmp_packet.get_icmpv6_type() {
+        Icmpv6Types::EchoReply => {
+            let payload = icmp_packet.payload();
+            if payload.len() < 4 || u16::from_be_bytes([payload[0], payload[1]]) != ICMPV6_IDENTIFIER {
+                return;
+            }
+            if !target_network.contains(&src_ipaddr) {
+                return;
+            }
+            let sequence = u16::from_be_bytes([payload[2], payload[3]]);
+            let mut replies = replies.lock().unwrap();
+            replies.add_up_host(src_ipaddr, sequence, ipv6_packet.get_hop_limit());
+            replies.frame_macs.entry(src_ipaddr).or_insert(ethernet_packet.get_source());
+        },
+        Icmpv6Types::NeighborAdvert => {
+            if let Some((target, mac)) = get_neighbor_advert(&icmp_packet, ethernet_packet.get_source()) {
+                let mut replies = replies.lock().unwrap();
+                if replies.up_hosts.iter().any(|(h, _)| *h == target) {
+                    replies.mac_addrs.insert(target, mac);
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+fn receive_packets(mut rx: Box<dyn DataLinkReceiver>, target_network: Ipv6Net, stop: &Arc<Mutex<bool>>, replies: &Arc<Mutex<Replies>>) {
+    loop {
+        if let Ok(frame) = rx.next() {
+            handle_packet(frame, target_network, replies);
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
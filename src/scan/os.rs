@@ -0,0 +1,270 @@
+use pnet::packet::Packet;
+use pnet::packet::tcp::{TcpOptionNumbers, TcpPacket};
+use crate::util::db::OsSignature;
+
+/// Initial TTL values used by common TCP/IP stacks.
+const INITIAL_TTLS: [u8; 4] = [32, 64, 128, 255];
+
+/// Header values observed in a reply from a host.
+#[derive(Clone)]
+pub struct OsFingerprint {
+    /// TTL of the reply. Not available for IPv6.
+    pub ttl: Option<u8>,
+    pub window_size: Option<u16>,
+    pub mss: Option<u16>,
+    /// Order of TCP options. Ex: M,S,T,N,W
+    pub tcp_options: Option<String>,
+}
+
+impl OsFingerprint {
+    /// Fingerprint of an ICMP echo reply. Only the TTL is meaningful.
+    pub fn from_ttl(ttl: u8) -> OsFingerprint {
+        OsFingerprint {
+            ttl: Some(ttl),
+            window_size: None,
+            mss: None,
+            tcp_options: None,
+        }
+    }
+    /// Fingerprint of a SYN/ACK.
+    pub fn from_syn_ack(ttl: Option<u8>, packet: &TcpPacket) -> OsFingerprint {
+        let mut mss: Option<u16> = None;
+        let mut options: Vec<&str> = vec![];
+        for option in packet.get_options_iter() {
+            let number = option.get_number();
+            let name = if number == TcpOptionNumbers::MSS {
+                let data = option.payload();
+                if data.len() >= 2 {
+                    mss = Some(u16::from_be_bytes([data[0], data[1]]));
+                }
+                "M"
+            }else if number == TcpOptionNumbers::NOP {
+                "N"
+            }else if number == TcpOptionNumbers::WSCALE {
+                "W"
+            }else if number == TcpOptionNumbers::SACK_PERMITTED {
+                "S"
+            }else if number == TcpOptionNumbers::TIMESTAMPS {
+                "T"
+            }else if number == TcpOptionNumbers::EOL {
+                "E"
+            }else{
+                "?"
+            };
+            options.push(name);
+        }
+        OsFingerprint {
+            ttl: ttl,
+            window_size: Some(packet.get_window()),
+            mss: mss,
+            tcp_options: Some(options.join(",")),
+        }
+    }
+    /// Smallest common initial TTL not below the observed TTL.
+    pub fn get_initial_ttl(&self) -> Option<u8> {
+        let ttl = self.ttl?;
+        INITIAL_TTLS.iter().cloned().find(|initial| *initial >= ttl)
+    }
+    /// Number of routers between us and the host, inferred from the TTL.
+    pub fn get_hop_distance(&self) -> Option<u8> {
+        Some(self.get_initial_ttl()? - self.ttl?)
+    }
+}
+
+/// Best-guess OS family of a host.
+#[derive(Clone)]
+pub struct OsGuess {
+    pub os_family: String,
+    pub hop_distance: Option<u8>,
+}
+
+impl OsGuess {
+    /// Ex: "Linux (2 hops)"
+    pub fn get_detail(&self) -> String {
+        match self.hop_distance {
+            Some(1) => format!("{} (1 hop)", self.os_family),
+            Some(hops) => format!("{} ({} hops)", self.os_family, hops),
+            None => self.os_family.to_string(),
+        }
+    }
+}
+
+/// Structure for passive OS detection.
+///
+/// Fingerprints are matched against signatures from the OS_SIGNATURE table.
+/// Empty signature fields match any value, and the signature
+/// matching the most fields wins.
+pub struct OsDetector {
+    signatures: Vec<OsSignature>,
+}
+
+impl OsDetector {
+    pub fn new(signatures: Vec<OsSignature>) -> OsDetector {
+        OsDetector {
+            signatures: signatures,
+        }
+    }
+    /// Return the best matching OS family, or "Unknown" with the hop distance only.
+    pub fn guess(&self, fingerprint: &OsFingerprint) -> OsGuess {
+        let initial_ttl = fingerprint.get_initial_ttl();
+        let mut best: Option<(&OsSignature, usize)> = None;
+        for signature in &self.signatures {
+            let mut score = 0;
+            if let Some(ttl) = initial_ttl {
+                if signature.initial_ttl != 0 {
+                    if signature.initial_ttl != ttl {
+                        continue;
+                    }
+                    score += 1;
+                }
+            }
+            match match_values(&signature.window_size, fingerprint.window_size) {
+                Some(n) => score += n,
+                None => continue,
+            }
+            match match_values(&signature.mss, fingerprint.mss) {
+                Some(n) => score += n,
+                None => continue,
+            }
+            if !signature.tcp_options.is_empty() {
+                match &fingerprint.tcp_options {
+                    Some(options) if *options == signature.tcp_options => score += 2,
+                    _ => continue,
+                }
+            }
+            let is_better = match best {
+                Some((_, best_score)) => score > best_score,
+                None => score > 0,
+            };
+            if is_better {
+                best = Some((signature, score));
+            }
+        }
+        let os_family = match best {
+            Some((signature, _)) => signature.os_family.to_string(),
+            None => String::from("Unknown"),
+        };
+        OsGuess {
+            os_family: os_family,
+            hop_distance: fingerprint.get_hop_distance(),
+        }
+    }
+}
+
+// Match a comma-separated list of values. None if the value is required but absent or different.
+fn match_values(values: &str, value: Option<u16>) -> Option<usize> {
+    if values.is_empty() {
+        return Some(0);
+    }
+    let value = value?;
+    if values.split(",").any(|v| v.trim().parse::<u16>().ok() == Some(value)) {
+        Some(1)
+    }else{
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pnet::packet::tcp::{MutableTcpPacket, TcpOption};
+
+    fn signature(os_family: &str, initial_ttl: u8, window_size: &str, mss: &str, tcp_options: &str) -> OsSignature {
+        OsSignature {
+            os_family: os_family.to_string(),
+            initial_ttl: initial_ttl,
+            window_size: window_size.to_string(),
+            mss: mss.to_string(),
+            tcp_options: tcp_options.to_string(),
+        }
+    }
+
+    fn fingerprint(ttl: u8, window_size: u16, mss: u16, tcp_options: &str) -> OsFingerprint {
+        OsFingerprint {
+            ttl: Some(ttl),
+            window_size: Some(window_size),
+            mss: Some(mss),
+            tcp_options: Some(tcp_options.to_string()),
+        }
+    }
+
+    fn detector() -> OsDetector {
+        OsDetector::new(vec![
+            signature("Linux", 64, "", "", ""),
+            signature("Linux 2.6+", 64, "5840,29200,64240", "1460", "M,S,T,N,W"),
+            signature("FreeBSD", 64, "65535", "", "M,N,W,S,T"),
+            signature("Windows", 128, "", "", ""),
+            signature("Cisco IOS", 255, "4128", "", ""),
+        ])
+    }
+
+    #[test]
+    fn initial_ttl_rounding() {
+        let cases: [(u8, Option<u8>, Option<u8>); 7] = [
+            (1, Some(32), Some(31)),
+            (32, Some(32), Some(0)),
+            (33, Some(64), Some(31)),
+            (57, Some(64), Some(7)),
+            (64, Some(64), Some(0)),
+            (120, Some(128), Some(8)),
+            (250, Some(255), Some(5)),
+        ];
+        for (ttl, initial_ttl, hops) in cases.iter() {
+            let fp = OsFingerprint::from_ttl(*ttl);
+            assert_eq!(fp.get_initial_ttl(), *initial_ttl, "ttl {}", ttl);
+            assert_eq!(fp.get_hop_distance(), *hops, "ttl {}", ttl);
+        }
+        let fp = OsFingerprint { ttl: None, window_size: None, mss: None, tcp_options: None };
+        assert_eq!(fp.get_initial_ttl(), None);
+        assert_eq!(fp.get_hop_distance(), None);
+    }
+
+    #[test]
+    fn guess_by_ttl_only() {
+        let guess = detector().guess(&OsFingerprint::from_ttl(118));
+        assert_eq!(guess.os_family, "Windows");
+        assert_eq!(guess.get_detail(), "Windows (10 hops)");
+        assert_eq!(detector().guess(&OsFingerprint::from_ttl(63)).get_detail(), "Linux (1 hop)");
+    }
+
+    #[test]
+    fn guess_by_window_mss_and_options() {
+        let guess = detector().guess(&fingerprint(61, 29200, 1460, "M,S,T,N,W"));
+        assert_eq!(guess.os_family, "Linux 2.6+");
+        assert_eq!(guess.hop_distance, Some(3));
+        // Different option order falls back to the TTL-only signature
+        assert_eq!(detector().guess(&fingerprint(64, 29200, 1460, "M,N,W,S,T")).os_family, "Linux");
+        // MSS outside the list
+        assert_eq!(detector().guess(&fingerprint(64, 29200, 1380, "M,S,T,N,W")).os_family, "Linux");
+        assert_eq!(detector().guess(&fingerprint(64, 65535, 1460, "M,N,W,S,T")).os_family, "FreeBSD");
+        assert_eq!(detector().guess(&fingerprint(255, 4128, 536, "M")).os_family, "Cisco IOS");
+    }
+
+    #[test]
+    fn guess_without_match() {
+        let guess = detector().guess(&fingerprint(255, 8192, 1460, "M,N,W"));
+        assert_eq!(guess.os_family, "Unknown");
+        assert_eq!(guess.get_detail(), "Unknown (0 hops)");
+        let guess = OsDetector::new(vec![]).guess(&OsFingerprint::from_ttl(64));
+        assert_eq!(guess.os_family, "Unknown");
+        // IPv6 replies have no TTL, so only TCP values can match
+        let fp = OsFingerprint { ttl: None, window_size: Some(65535), mss: Some(1440), tcp_options: Some(String::from("M,N,W,S,T")) };
+        let guess = detector().guess(&fp);
+        assert_eq!(guess.os_family, "FreeBSD");
+        assert_eq!(guess.get_detail(), "FreeBSD");
+    }
+
+    #[test]
+    fn fingerprint_from_syn_ack() {
+        let mut buf = [0u8; 40];
+        let mut packet = MutableTcpPacket::new(&mut buf).unwrap();
+        packet.set_data_offset(10);
+        packet.set_window(29200);
+        packet.set_options(&[TcpOption::mss(1460), TcpOption::sack_perm(), TcpOption::timestamp(1, 0), TcpOption::nop(), TcpOption::wscale(7)]);
+        let fp = OsFingerprint::from_syn_ack(Some(64), &packet.to_immutable());
+        assert_eq!(fp.window_size, Some(29200));
+        assert_eq!(fp.mss, Some(1460));
+        assert_eq!(fp.tcp_options.as_deref(), Some("M,S,T,N,W"));
+        assert_eq!(detector().guess(&fp).os_family, "Linux 2.6+");
+    }
+}
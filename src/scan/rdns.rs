@@ -0,0 +1,304 @@
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use dns_lookup::lookup_addr;
+use rand::Rng;
+
+/// Default time to wait for a DNS response.
+pub const DEFAULT_DNS_TIMEOUT: Duration = Duration::from_millis(2000);
+/// Default number of lookups run in parallel.
+pub const DEFAULT_DNS_PARALLELISM: usize = 16;
+
+pub const DNS_HEADER_LEN: usize = 12;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+const CLASS_IN: u16 = 1;
+// Compression pointers allowed in one name, to stop loops in malformed responses
+const MAX_POINTERS: usize = 16;
+const MAX_LABEL_LEN: usize = 63;
+// Encoded length including the length octets and the root label
+const MAX_NAME_LEN: usize = 255;
+
+/// Structure for reverse DNS (PTR) lookups.
+///
+/// Uses the system resolver, or queries the given DNS server directly over UDP.
+pub struct ReverseResolver {
+    dns_server: Option<SocketAddr>,
+    timeout: Duration,
+}
+
+impl ReverseResolver {
+    pub fn new() -> ReverseResolver {
+        ReverseResolver {
+            dns_server: None,
+            timeout: DEFAULT_DNS_TIMEOUT,
+        }
+    }
+    pub fn set_dns_server(&mut self, dns_server: SocketAddr){
+        self.dns_server = Some(dns_server);
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        if timeout < self.timeout {
+            self.timeout = timeout;
+        }
+    }
+    /// Return the host name of the address, without the trailing dot.
+    pub fn lookup(&self, ip_addr: IpAddr) -> Result<String, String> {
+        match self.dns_server {
+            Some(dns_server) => self.query_ptr(dns_server, ip_addr),
+            None => {
+                match lookup_addr(&ip_addr) {
+                    // getnameinfo returns the address itself when there is no PTR record
+                    Ok(name) if name != ip_addr.to_string() => Ok(name),
+                    Ok(_) => Err(format!("No PTR record for {}", ip_addr)),
+                    Err(e) => Err(format!("{}", e)),
+                }
+            },
+        }
+    }
+    fn query_ptr(&self, dns_server: SocketAddr, ip_addr: IpAddr) -> Result<String, String> {
+        let bind_addr = match dns_server {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = match UdpSocket::bind(bind_addr) {
+            Ok(socket) => socket,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        if let Err(e) = socket.set_read_timeout(Some(self.timeout)) {
+            return Err(format!("{}", e));
+        }
+        let id: u16 = rand::thread_rng().gen();
+        let query = build_query(id, &get_ptr_name(ip_addr), TYPE_PTR, true)?;
+        if let Err(e) = socket.send_to(&query, dns_server) {
+            return Err(format!("{}", e));
+        }
+        let mut buf = [0u8; 1500];
+        loop {
+            let (len, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => return Err(format!("{}", e)),
+            };
+            if addr != dns_server || len < DNS_HEADER_LEN || u16::from_be_bytes([buf[0], buf[1]]) != id {
+                continue;
+            }
+            return match parse_ptr_response(&buf[..len]) {
+                Some(name) => Ok(name),
+                None => Err(format!("No PTR record for {}", ip_addr)),
+            };
+        }
+    }
+}
+
+/// Ex: 1.2.0.192.in-addr.arpa
+pub fn get_ptr_name(ip_addr: IpAddr) -> String {
+    match ip_addr {
+        IpAddr::V4(ipv4_addr) => {
+            let o = ipv4_addr.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0])
+        },
+        IpAddr::V6(ipv6_addr) => {
+            let mut labels: Vec<String> = vec![];
+            for byte in ipv6_addr.octets().iter().rev() {
+                labels.push(format!("{:x}", byte & 0x0f));
+                labels.push(format!("{:x}", byte >> 4));
+            }
+            format!("{}.ip6.arpa", labels.join("."))
+        },
+    }
+}
+
+/// Query with one question. mDNS queries are sent without the recursion desired flag.
+///
+/// Dots and backslashes inside a label are escaped with a backslash, as `read_name` returns them.
+pub fn build_query(id: u16, name: &str, qtype: u16, recursion_desired: bool) -> Result<Vec<u8>, String> {
+    let labels = split_labels(name)?;
+    let mut query: Vec<u8> = vec![];
+    query.extend_from_slice(&id.to_be_bytes());
+    query.push(if recursion_desired { 0x01 } else { 0x00 });
+    // One question
+    query.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in labels {
+        query.push(label.len() as u8);
+        query.extend_from_slice(&label);
+    }
+    query.push(0);
+    query.extend_from_slice(&qtype.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+// Split a name into unescaped labels. A trailing dot for the root is allowed.
+fn split_labels(name: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut labels: Vec<Vec<u8>> = vec![];
+    let mut label: Vec<u8> = vec![];
+    let mut bytes = name.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next() {
+                Some(escaped) if escaped == b'.' || escaped == b'\\' => label.push(escaped),
+                _ => return Err(format!("Invalid escape in DNS name: {}", name)),
+            },
+            b'.' => labels.push(std::mem::take(&mut label)),
+            _ => label.push(b),
+        }
+    }
+    if !label.is_empty() || labels.is_empty() {
+        labels.push(label);
+    }
+    if labels.iter().any(|label| label.is_empty()) {
+        return Err(format!("Empty label in DNS name: {}", name));
+    }
+    if labels.iter().any(|label| label.len() > MAX_LABEL_LEN) {
+        return Err(format!("DNS label longer than {} bytes: {}", MAX_LABEL_LEN, name));
+    }
+    if labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1 > MAX_NAME_LEN {
+        return Err(format!("DNS name longer than {} bytes: {}", MAX_NAME_LEN, name));
+    }
+    Ok(labels)
+}
+
+// Return the first PTR answer
+fn parse_ptr_response(response: &[u8]) -> Option<String> {
+    // Response code other than NOERROR
+    if response[3] & 0x0f != 0 {
+        return None;
+    }
+    let question_count = u16::from_be_bytes([response[4], response[5]]);
+    let answer_count = u16::from_be_bytes([response[6], response[7]]);
+    let mut pos = DNS_HEADER_LEN;
+    for _ in 0..question_count {
+        pos = skip_name(response, pos)? + 4;
+    }
+    for _ in 0..answer_count {
+        pos = skip_name(response, pos)?;
+        let fields = response.get(pos..pos + 10)?;
+        let rtype = u16::from_be_bytes([fields[0], fields[1]]);
+        let rdlength = u16::from_be_bytes([fields[8], fields[9]]) as usize;
+        pos += 10;
+        if rtype == TYPE_PTR {
+            return read_name(response, pos);
+        }
+        pos += rdlength;
+    }
+    None
+}
+
+/// Position after the name at `pos`
+pub fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += len + 1;
+    }
+}
+
+/// Read a name at `pos`, following compression pointers.
+///
+/// Dots and backslashes inside a label are escaped with a backslash.
+pub fn read_name(message: &[u8], mut pos: usize) -> Option<String> {
+    let mut labels: Vec<String> = vec![];
+    let mut pointers = 0;
+    loop {
+        let len = *message.get(pos)? as usize;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 == 0xc0 {
+            pointers += 1;
+            if pointers > MAX_POINTERS {
+                return None;
+            }
+            pos = ((len & 0x3f) << 8) | *message.get(pos + 1)? as usize;
+            continue;
+        }
+        let label = message.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).replace("\\", "\\\\").replace(".", "\\."));
+        pos += len + 1;
+    }
+    if labels.is_empty() {
+        None
+    }else{
+        Some(labels.join("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_wire_format() {
+        let query = build_query(0x1234, "_http._tcp.local", TYPE_PTR, false).unwrap();
+        let mut expected: Vec<u8> = vec![0x12, 0x34, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        expected.extend_from_slice(b"\x05_http\x04_tcp\x05local\x00\x00\x0c\x00\x01");
+        assert_eq!(query, expected);
+        assert_eq!(build_query(0x1234, "_http._tcp.local.", TYPE_PTR, false).unwrap(), expected);
+    }
+
+    #[test]
+    fn escaped_labels_round_trip() {
+        let name = "Kitchen\\.TV \\\\ 2._googlecast._tcp.local";
+        let query = build_query(1, name, TYPE_TXT, false).unwrap();
+        assert_eq!(&query[DNS_HEADER_LEN..DNS_HEADER_LEN + 15], b"\x0eKitchen.TV \\ 2");
+        assert_eq!(read_name(&query, DNS_HEADER_LEN).unwrap(), name);
+    }
+
+    #[test]
+    fn invalid_names() {
+        let long_label = "a".repeat(MAX_LABEL_LEN + 1);
+        assert!(build_query(1, &"a".repeat(MAX_LABEL_LEN), TYPE_PTR, true).is_ok());
+        assert!(build_query(1, &format!("{}.local", long_label), TYPE_PTR, true).unwrap_err().contains("longer than 63"));
+        let long_name = vec!["a".repeat(MAX_LABEL_LEN); 4].join(".");
+        assert!(build_query(1, &long_name, TYPE_PTR, true).unwrap_err().contains("longer than 255"));
+        for name in &["", ".", "a..local", ".local", "a.local..", "a\\", "a\\x.local"] {
+            assert!(build_query(1, name, TYPE_PTR, true).is_err(), "{}", name);
+        }
+    }
+    // Reply for 1.2.0.192.in-addr.arpa with a CNAME before the PTR answer
+    fn ptr_response() -> Vec<u8> {
+        let mut response: Vec<u8> = vec![0xab, 0xcd, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00];
+        response.extend_from_slice(b"\x011\x012\x010\x03192\x07in-addr\x04arpa\x00\x00\x0c\x00\x01");
+        response.extend_from_slice(b"\xc0\x0c\x00\x05\x00\x01\x00\x00\x0e\x10\x00\x04\x01x\xc0\x0c");
+        response.extend_from_slice(b"\xc0\x0c\x00\x0c\x00\x01\x00\x00\x0e\x10\x00\x0e\x04host\x07example\x00");
+        response
+    }
+
+    #[test]
+    fn ptr_answer() {
+        assert_eq!(parse_ptr_response(&ptr_response()), Some(String::from("host.example")));
+    }
+
+    #[test]
+    fn ptr_answer_count() {
+        let mut response = ptr_response();
+        // Only the CNAME is counted
+        response[7] = 1;
+        assert_eq!(parse_ptr_response(&response), None);
+        response[7] = 0;
+        assert_eq!(parse_ptr_response(&response), None);
+    }
+
+    #[test]
+    fn ptr_error_code() {
+        let mut response = ptr_response();
+        // NXDOMAIN and SERVFAIL, even with an answer
+        response[3] = 0x83;
+        assert_eq!(parse_ptr_response(&response), None);
+        response[3] = 0x82;
+        assert_eq!(parse_ptr_response(&response), None);
+    }
+
+    #[test]
+    fn truncated_ptr_response() {
+        let response = ptr_response();
+        // Inside the question, the CNAME, the fixed fields of the PTR record and its name
+        for len in &[DNS_HEADER_LEN, 20, 38, 50, 60, 75, response.len() - 1] {
+            assert_eq!(parse_ptr_response(&response[..*len]), None, "{}", len);
+        }
+    }
+}
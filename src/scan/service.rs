@@ -0,0 +1,321 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use futures::stream::{self, StreamExt};
+use regex::bytes::Regex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use crate::util::{db, sys};
+
+/// Maximum number of ports probed at once.
+pub const DEFAULT_CONCURRENCY: usize = 16;
+/// Time to wait for the first byte of a response.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(3000);
+/// Probe that only reads the banner sent by the server after connecting.
+pub const NULL_PROBE: &str = "NULL";
+
+// Once a response has started, stop reading after this much silence
+const READ_IDLE_TIME: Duration = Duration::from_millis(300);
+const MAX_RESPONSE_SIZE: usize = 8192;
+const MAX_BANNER_LEN: usize = 48;
+
+/// Service detected on an open port.
+#[derive(Clone)]
+pub struct ServiceInfo {
+    pub service_name: String,
+    pub product: String,
+    pub version: String,
+    /// First line of the response when no rule matched.
+    pub banner: String,
+}
+
+impl ServiceInfo {
+    /// Return product and version, or the raw banner when unidentified.
+    pub fn get_detail(&self) -> String {
+        if self.product.is_empty() && self.version.is_empty() {
+            if self.banner.is_empty() {
+                return self.service_name.to_string();
+            }
+            return format!("\"{}\"", self.banner);
+        }
+        format!("{} {}", self.product, self.version).trim().to_string()
+    }
+}
+
+struct Probe {
+    name: String,
+    ports: Vec<u16>,
+    payload: Vec<u8>,
+}
+
+struct MatchRule {
+    probe_name: String,
+    service_name: String,
+    pattern: Regex,
+    product: String,
+    version: String,
+}
+
+/// Structure for service and version detection.
+///
+/// Probes and match rules are loaded from the SERVICE_PROBE table.
+pub struct ServiceDetector {
+    probes: Vec<Probe>,
+    rules: Vec<MatchRule>,
+    concurrency: usize,
+    read_timeout: Duration,
+}
+
+impl ServiceDetector {
+    pub fn new(service_probes: Vec<db::ServiceProbe>) -> ServiceDetector {
+        let mut probes: Vec<Probe> = vec![];
+        let mut rules: Vec<MatchRule> = vec![];
+        for service_probe in service_probes {
+            let pattern = match Regex::new(&service_probe.match_pattern) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    warn!("Invalid match pattern for probe {}: {}", service_probe.probe_name, e);
+                    continue;
+                },
+            };
+            if service_probe.probe_name != NULL_PROBE && !probes.iter().any(|p| p.name == service_probe.probe_name) {
+                probes.push(Probe {
+                    name: service_probe.probe_name.to_string(),
+                    ports: sys::parse_port_list(&service_probe.probe_ports).unwrap_or(vec![]),
+                    payload: unescape(&service_probe.probe_string),
+                });
+            }
+            rules.push(MatchRule {
+                probe_name: service_probe.probe_name,
+                service_name: service_probe.service_name,
+                pattern: pattern,
+                product: service_probe.product,
+                version: service_probe.version,
+            });
+        }
+        ServiceDetector {
+            probes: probes,
+            rules: rules,
+            concurrency: DEFAULT_CONCURRENCY,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+        }
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        if timeout < self.read_timeout {
+            self.read_timeout = timeout;
+        }
+    }
+    /// Probe each port and return the services that could be identified.
+    pub async fn run_detection(&self, ip_addr: IpAddr, ports: Vec<u16>) -> Vec<(u16, ServiceInfo)> {
+        let detector = self;
+        let mut results: Vec<(u16, ServiceInfo)> = stream::iter(ports)
+            .map(|port| async move {
+                (port, detector.detect_service(SocketAddr::new(ip_addr, port)).await)
+            })
+            .buffer_unordered(self.concurrency)
+            .filter_map(|(port, info)| async move { info.map(|info| (port, info)) })
+            .collect().await;
+        results.sort_by_key(|(port, _)| *port);
+        results
+    }
+    async fn detect_service(&self, addr: SocketAddr) -> Option<ServiceInfo> {
+        let mut banner: Vec<u8> = vec![];
+        // Many services greet first, so start by just listening
+        match self.send_probe(addr, &[]).await {
+            Some(response) => {
+                if let Some(info) = self.match_response(NULL_PROBE, &response) {
+                    return Some(info);
+                }
+                banner = response;
+            },
+            None => {},
+        }
+        for probe in &self.probes {
+            if !probe.ports.is_empty() && !probe.ports.contains(&addr.port()) {
+                continue;
+            }
+            if let Some(response) = self.send_probe(addr, &probe.payload).await {
+                if let Some(info) = self.match_response(&probe.name, &response) {
+                    return Some(info);
+                }
+            }
+        }
+        if banner.is_empty() {
+            return None;
+        }
+        Some(ServiceInfo {
+            service_name: String::new(),
+            product: String::new(),
+            version: String::new(),
+            banner: get_first_line(&banner),
+        })
+    }
+    async fn send_probe(&self, addr: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+        let mut stream = match timeout(self.read_timeout, TcpStream::connect(&addr)).await {
+            Ok(Ok(stream)) => stream,
+            _ => return None,
+        };
+        if !payload.is_empty() {
+            if let Err(_) = stream.write_all(payload).await {
+                return None;
+            }
+        }
+        let mut response: Vec<u8> = vec![];
+        let mut buf = [0u8; 2048];
+        let start_time = Instant::now();
+        loop {
+            let wait_time = if response.is_empty() {
+                match self.read_timeout.checked_sub(Instant::now().duration_since(start_time)) {
+                    Some(remaining) => remaining,
+                    None => break,
+                }
+            }else{
+                READ_IDLE_TIME
+            };
+            match timeout(wait_time, stream.read(&mut buf)).await {
+                Ok(Ok(0)) => break,
+                Ok(Ok(n)) => response.extend_from_slice(&buf[..n]),
+                _ => break,
+            }
+            if response.len() >= MAX_RESPONSE_SIZE {
+                break;
+            }
+        }
+        if response.is_empty() {
+            None
+        }else{
+            Some(response)
+        }
+    }
+    fn match_response(&self, probe_name: &str, response: &[u8]) -> Option<ServiceInfo> {
+        for rule in self.rules.iter().filter(|r| r.probe_name == probe_name) {
+            if let Some(caps) = rule.pattern.captures(response) {
+                let mut product: Vec<u8> = vec![];
+                let mut version: Vec<u8> = vec![];
+                caps.expand(rule.product.as_bytes(), &mut product);
+                caps.expand(rule.version.as_bytes(), &mut version);
+                return Some(ServiceInfo {
+                    service_name: rule.service_name.to_string(),
+                    product: sanitize(&product),
+                    version: sanitize(&version),
+                    banner: String::new(),
+                });
+            }
+        }
+        None
+    }
+}
+
+fn get_first_line(response: &[u8]) -> String {
+    let line = response.split(|b| *b == b'\n').next().unwrap_or(&[]);
+    let mut banner = sanitize(line);
+    if banner.len() > MAX_BANNER_LEN {
+        banner.truncate(MAX_BANNER_LEN);
+        banner.push_str("...");
+    }
+    banner
+}
+
+// Keep printable ASCII only so binary responses do not garble the report
+fn sanitize(v: &[u8]) -> String {
+    v.iter().filter(|b| **b >= 0x20 && **b < 0x7f).map(|b| *b as char).collect::<String>().trim().to_string()
+}
+
+/// Decode escape sequences (\r, \n, \t, \0, \\ and \xHH) in probe strings.
+pub fn unescape(v: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![];
+    let chars: Vec<char> = v.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '\\' && i + 1 < chars.len() {
+            match chars[i + 1] {
+                'r' => bytes.push(b'\r'),
+                'n' => bytes.push(b'\n'),
+                't' => bytes.push(b'\t'),
+                '0' => bytes.push(0),
+                '\\' => bytes.push(b'\\'),
+                'x' if i + 3 < chars.len() => {
+                    let hex: String = chars[i + 2..i + 4].iter().collect();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(b) => {
+                            bytes.push(b);
+                            i += 2;
+                        },
+                        Err(_) => bytes.extend_from_slice(b"\\x"),
+                    }
+                },
+                c => {
+                    bytes.push(b'\\');
+                    let mut tmp = [0u8; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut tmp).as_bytes());
+                },
+            }
+            i += 2;
+            continue;
+        }
+        let mut tmp = [0u8; 4];
+        bytes.extend_from_slice(chars[i].encode_utf8(&mut tmp).as_bytes());
+        i += 1;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service_probe(probe_name: &str, service_name: &str, match_pattern: &str, product: &str, version: &str) -> db::ServiceProbe {
+        db::ServiceProbe {
+            probe_name: probe_name.to_string(),
+            probe_ports: String::new(),
+            probe_string: String::new(),
+            service_name: service_name.to_string(),
+            match_pattern: match_pattern.to_string(),
+            product: product.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn unescape_sequences() {
+        assert_eq!(unescape("GET / HTTP/1.0\\r\\n\\r\\n"), b"GET / HTTP/1.0\r\n\r\n".to_vec());
+        assert_eq!(unescape("\\t\\0\\\\"), vec![b'\t', 0, b'\\']);
+        assert_eq!(unescape("\\x16\\x03\\x01\\xff"), vec![0x16, 0x03, 0x01, 0xff]);
+        assert_eq!(unescape("\\x4A\\x4a"), b"JJ".to_vec());
+        // Invalid or incomplete \x escapes are kept as written
+        assert_eq!(unescape("\\xzz"), b"\\xzz".to_vec());
+        assert_eq!(unescape("a\\x"), b"a\\x".to_vec());
+        assert_eq!(unescape("a\\x4"), b"a\\x4".to_vec());
+        // Unknown escapes and a trailing backslash are kept too
+        assert_eq!(unescape("\\q\\"), b"\\q\\".to_vec());
+        assert_eq!(unescape("caf\\é"), "caf\\é".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn capture_expansion() {
+        let detector = ServiceDetector::new(vec![
+            service_probe(NULL_PROBE, "ssh", r"^SSH-[\d.]+-OpenSSH_([\w.]+)", "OpenSSH", "$1"),
+            service_probe("GetRequest", "http", r"Server: (\w+)/([\d.]+)", "$1", "$2"),
+            service_probe("GetRequest", "http", r"[", "Invalid", ""),
+        ]);
+        let info = detector.match_response(NULL_PROBE, b"SSH-2.0-OpenSSH_8.2p1 Ubuntu-4\r\n").unwrap();
+        assert_eq!(info.service_name, "ssh");
+        assert_eq!(info.get_detail(), "OpenSSH 8.2p1");
+        let info = detector.match_response("GetRequest", b"HTTP/1.1 200 OK\r\nServer: nginx/1.18.0\r\n\r\n").unwrap();
+        assert_eq!(info.product, "nginx");
+        assert_eq!(info.version, "1.18.0");
+        // Rules only match responses to their own probe
+        assert!(detector.match_response(NULL_PROBE, b"Server: nginx/1.18.0").is_none());
+        assert!(detector.match_response("GetRequest", b"SSH-2.0-OpenSSH_8.2p1").is_none());
+    }
+
+    #[test]
+    fn first_line_of_banner() {
+        assert_eq!(get_first_line(b"220 mail.example.com ESMTP\r\n250 OK\r\n"), "220 mail.example.com ESMTP");
+        assert_eq!(get_first_line(b"\x00\x01ok\x7f"), "ok");
+        let long = "x".repeat(MAX_BANNER_LEN + 10);
+        let banner = get_first_line(long.as_bytes());
+        assert_eq!(banner, format!("{}...", "x".repeat(MAX_BANNER_LEN)));
+        assert_eq!(get_first_line(b""), "");
+    }
+}
@@ -0,0 +1,567 @@
+use std::{io, thread, time};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use pnet::datalink::NetworkInterface;
+use pnet::packet::Packet;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmpv6::Icmpv6Types;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::{Ipv4Flags, MutableIpv4Packet};
+use pnet::packet::tcp::{MutableTcpPacket, TcpFlags, TcpOption, TcpPacket};
+use pnet::transport::TransportChannelType::{Layer3, Layer4};
+use pnet::transport::TransportProtocol::{Ipv4, Ipv6};
+use pnet::transport::{icmp_packet_iter, icmpv6_packet_iter, ipv4_packet_iter, tcp_packet_iter, TransportReceiver};
+use rand::Rng;
+use nerve_base::ScanStatus;
+use nerve::PortScanType;
+use super::{PortState, PortStateResult};
+use super::os::OsFingerprint;
+use super::timing::{self, RttTracker, Timing};
+
+const TCP_HEADER_LEN: usize = 32;
+/// Length of an IPv4 header without options.
+pub const IPV4_HEADER_LEN: usize = 20;
+const INITIAL_SEQUENCE: u32 = 0x6e73_6361;
+// Range of randomized source ports
+const MIN_RANDOM_PORT: u16 = 1024;
+const MAX_RANDOM_PORT: u16 = 65535;
+
+/// Structure for raw socket TCP port scan (SYN, FIN, Xmas, Null).
+///
+/// Every probed port gets a state. SYN scans report SYN/ACK as open, RST as closed
+/// and silence or ICMP unreachable as filtered. FIN, Xmas and Null scans report RST as closed,
+/// ICMP unreachable as filtered and silence as open|filtered.
+///
+/// IPv6 targets are probed the same way over ICMPv6. IPv6 replies are read without
+/// their IP header, so fingerprints of IPv6 hosts carry no TTL.
+pub struct TcpScanner {
+    /// IP Address of target host.
+    target_ipaddr: IpAddr,
+    /// Source IP Address. Chosen by the routing table if not set.
+    src_ipaddr: Option<IpAddr>,
+    /// Interface to send from. Chosen by the routing table if not set.
+    interface: Option<NetworkInterface>,
+    /// Source port of probes.
+    src_port: u16,
+    /// Use a random source port for each probe.
+    random_src_port: bool,
+    /// List of target ports.
+    target_ports: Vec<u16>,
+    /// Type of probe.
+    scan_type: PortScanType,
+    /// Probe pacing and retransmission.
+    timing: Timing,
+    /// Timeout setting of port scan.
+    timeout: Duration,
+}
+
+impl TcpScanner {
+    pub fn new(target_ipaddr: IpAddr) -> TcpScanner {
+        TcpScanner {
+            target_ipaddr: target_ipaddr,
+            src_ipaddr: None,
+            interface: None,
+            src_port: 65432,
+            random_src_port: false,
+            target_ports: vec![],
+            scan_type: PortScanType::SynScan,
+            timing: Timing::new(),
+            timeout: Duration::from_millis(30000),
+        }
+    }
+    pub fn set_src_ipaddr(&mut self, src_ipaddr: IpAddr){
+        self.src_ipaddr = Some(src_ipaddr);
+    }
+    pub fn set_interface(&mut self, interface: NetworkInterface){
+        self.interface = Some(interface);
+    }
+    pub fn set_src_port(&mut self, src_port: u16){
+        self.src_port = src_port;
+    }
+    pub fn set_random_src_port(&mut self, random_src_port: bool){
+        self.random_src_port = random_src_port;
+    }
+    pub fn set_ports(&mut self, ports: Vec<u16>){
+        self.target_ports = ports;
+    }
+    pub fn set_scan_type(&mut self, scan_type: PortScanType){
+        self.scan_type = scan_type;
+    }
+    pub fn set_timing(&mut self, timing: Timing){
+        self.timing = timing;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        self.timeout = timeout;
+    }
+    pub fn run_scan(&self) -> Result<PortStateResult, String> {
+        let start_time = Instant::now();
+        let src_ipaddr = match self.src_ipaddr {
+            Some(src_ipaddr) => src_ipaddr,
+            None => get_src_ipaddr(self.target_ipaddr)?,
+        };
+        // IPv4 probes carry their own header, so replies are read with theirs and the TTL is available
+        let (tcp_protocol, icmp_protocol) = match self.target_ipaddr {
+            IpAddr::V4(_) => (Layer3(IpNextHeaderProtocols::Tcp), Layer4(Ipv4(IpNextHeaderProtocols::Icmp))),
+            IpAddr::V6(_) => (Layer4(Ipv6(IpNextHeaderProtocols::Tcp)), Layer4(Ipv6(IpNextHeaderProtocols::Icmpv6))),
+        };
+        let (mut tx, tcp_rx) = match pnet::transport::transport_channel(4096, tcp_protocol) {
+            Ok((tx, rx)) => (tx, rx),
+            Err(e) => return Err(format!("Failed to open TCP channel: {}", e)),
+        };
+        if let Some(interface) = &self.interface {
+            bind_to_interface(tx.socket.fd, interface, src_ipaddr)?;
+        }
+        let (_icmp_tx, icmp_rx) = match pnet::transport::transport_channel(4096, icmp_protocol) {
+            Ok((tx, rx)) => (tx, rx),
+            Err(e) => return Err(format!("Failed to open ICMP channel: {}", e)),
+        };
+        let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+        let port_states: Arc<Mutex<Vec<(u16, PortState)>>> = Arc::new(Mutex::new(vec![]));
+        let rtt: Arc<Mutex<RttTracker<u16>>> = Arc::new(Mutex::new(RttTracker::new(&self.timing)));
+        // (source port, destination port) of every probe sent, to match replies
+        let probes: Arc<Mutex<HashSet<(u16, u16)>>> = Arc::new(Mutex::new(HashSet::new()));
+        let fingerprint: Arc<Mutex<Option<OsFingerprint>>> = Arc::new(Mutex::new(None));
+        let tcp_handle = {
+            let stop = Arc::clone(&stop);
+            let port_states = Arc::clone(&port_states);
+            let rtt = Arc::clone(&rtt);
+            let probes = Arc::clone(&probes);
+            let fingerprint = Arc::clone(&fingerprint);
+            let target_ipaddr = self.target_ipaddr;
+            let scan_type = self.scan_type;
+            thread::spawn(move || receive_tcp_packets(tcp_rx, target_ipaddr, &probes, scan_type, &stop, &port_states, &rtt, &fingerprint))
+        };
+        let icmp_handle = {
+            let stop = Arc::clone(&stop);
+            let port_states = Arc::clone(&port_states);
+            let rtt = Arc::clone(&rtt);
+            let probes = Arc::clone(&probes);
+            let target_ipaddr = self.target_ipaddr;
+            thread::spawn(move || receive_icmp_packets(icmp_rx, target_ipaddr, &probes, &stop, &port_states, &rtt))
+        };
+        let mut scan_status = ScanStatus::Done;
+        let mut buf = [0u8; TCP_HEADER_LEN];
+        let mut ip_buf = [0u8; IPV4_HEADER_LEN + TCP_HEADER_LEN];
+        let mut rng = rand::thread_rng();
+        // Probes that get no reply are sent again in the next round
+        'rounds: for _ in 0..self.timing.max_retries as usize + 1 {
+            let pending: Vec<u16> = {
+                let answered = port_states.lock().unwrap();
+                self.target_ports.iter().cloned().filter(|port| !answered.iter().any(|(p, _)| p == port)).collect()
+            };
+            if pending.is_empty() {
+                break;
+            }
+            for port in pending {
+                if Instant::now().duration_since(start_time) > self.timeout {
+                    scan_status = ScanStatus::Timeout;
+                    break 'rounds;
+                }
+                thread::sleep(self.timing.get_send_delay());
+                let src_port = if self.random_src_port { rng.gen_range(MIN_RANDOM_PORT, MAX_RANDOM_PORT) } else { self.src_port };
+                let packet = build_tcp_packet(&mut buf, src_ipaddr, src_port, self.target_ipaddr, port, self.scan_type);
+                probes.lock().unwrap().insert((src_port, port));
+                rtt.lock().unwrap().on_send(port);
+                let _result = match (src_ipaddr, self.target_ipaddr) {
+                    (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                        let ip_packet = build_ipv4_packet(&mut ip_buf, src, dst, IpNextHeaderProtocols::Tcp, packet.packet());
+                        tx.send_to(ip_packet, self.target_ipaddr)
+                    },
+                    _ => tx.send_to(packet, self.target_ipaddr),
+                };
+            }
+            let elapsed = Instant::now().duration_since(start_time);
+            if elapsed >= self.timeout {
+                break;
+            }
+            let remaining = self.timeout - elapsed;
+            let probe_timeout = rtt.lock().unwrap().get_timeout();
+            timing::wait_for_replies(if remaining < probe_timeout { remaining } else { probe_timeout }, || {
+                port_states.lock().unwrap().len() >= self.target_ports.len()
+            });
+            // Probes sent again wait longer, until a new reply is measured
+            rtt.lock().unwrap().back_off();
+        }
+        *stop.lock().unwrap() = true;
+        #[cfg(not(target_os = "windows"))]
+        {
+            let _ = tcp_handle.join();
+            let _ = icmp_handle.join();
+        }
+        #[cfg(target_os = "windows")]
+        {
+            drop(tcp_handle);
+            drop(icmp_handle);
+        }
+        let answered = port_states.lock().unwrap().clone();
+        let no_reply_state = match self.scan_type {
+            PortScanType::SynScan => PortState::Filtered,
+            _ => PortState::OpenFiltered,
+        };
+        let mut ports: Vec<(u16, PortState)> = vec![];
+        for port in &self.target_ports {
+            let state = match answered.iter().find(|(p, _)| p == port) {
+                Some((_, state)) => *state,
+                None => no_reply_state,
+            };
+            ports.push((*port, state));
+        }
+        ports.sort_by_key(|(port, _)| *port);
+        let srtt = rtt.lock().unwrap().get_srtt();
+        let fingerprint = fingerprint.lock().unwrap().clone();
+        Ok(PortStateResult {
+            ports: ports,
+            rtt: srtt,
+            fingerprint: fingerprint,
+            scan_status: scan_status,
+        })
+    }
+}
+
+// Ask the routing table which local address reaches the target
+pub fn get_src_ipaddr(target_ipaddr: IpAddr) -> Result<IpAddr, String> {
+    let bind_addr = match target_ipaddr {
+        IpAddr::V4(_) => "0.0.0.0:0",
+        IpAddr::V6(_) => "[::]:0",
+    };
+    let socket = match UdpSocket::bind(bind_addr) {
+        Ok(socket) => socket,
+        Err(e) => return Err(format!("Failed to get source address: {}", e)),
+    };
+    if let Err(e) = socket.connect(SocketAddr::new(target_ipaddr, 80)) {
+        return Err(format!("Failed to get source address: {}", e));
+    }
+    match socket.local_addr() {
+        Ok(addr) => Ok(addr.ip()),
+        Err(e) => Err(format!("Failed to get source address: {}", e)),
+    }
+}
+
+/// Send only through the interface, from `src_ipaddr`.
+///
+/// Without this the routing table picks the egress interface, and the OS may fill in
+/// a source address that does not match the one used for checksums.
+#[cfg(unix)]
+pub fn bind_to_interface(fd: libc::c_int, interface: &NetworkInterface, src_ipaddr: IpAddr) -> Result<(), String> {
+    if let Err(e) = bind_device(fd, interface, src_ipaddr.is_ipv6()) {
+        return Err(format!("Failed to bind socket to interface {}: {}", interface.name, e));
+    }
+    let ret = match src_ipaddr {
+        IpAddr::V4(ipv4_addr) => {
+            let mut addr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_addr = libc::in_addr { s_addr: u32::from(ipv4_addr).to_be() };
+            let len = std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            unsafe { libc::bind(fd, &addr as *const libc::sockaddr_in as *const libc::sockaddr, len) }
+        },
+        IpAddr::V6(ipv6_addr) => {
+            let mut addr: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+            addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            addr.sin6_addr = libc::in6_addr { s6_addr: ipv6_addr.octets() };
+            // Link-local addresses need the interface as scope
+            addr.sin6_scope_id = interface.index;
+            let len = std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+            unsafe { libc::bind(fd, &addr as *const libc::sockaddr_in6 as *const libc::sockaddr, len) }
+        },
+    };
+    if ret != 0 {
+        return Err(format!("Failed to bind socket to {}: {}", src_ipaddr, io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn bind_to_interface<T>(_fd: T, _interface: &NetworkInterface, _src_ipaddr: IpAddr) -> Result<(), String> {
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(fd: libc::c_int, interface: &NetworkInterface, _ipv6: bool) -> io::Result<()> {
+    let name = interface.name.as_bytes();
+    let ret = unsafe { libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, name.as_ptr() as *const libc::c_void, name.len() as libc::socklen_t) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn bind_device(fd: libc::c_int, interface: &NetworkInterface, ipv6: bool) -> io::Result<()> {
+    let index: libc::c_uint = interface.index;
+    let (level, name) = if ipv6 { (libc::IPPROTO_IPV6, libc::IPV6_BOUND_IF) } else { (libc::IPPROTO_IP, libc::IP_BOUND_IF) };
+    let ret = unsafe { libc::setsockopt(fd, level, name, &index as *const libc::c_uint as *const libc::c_void, std::mem::size_of::<libc::c_uint>() as libc::socklen_t) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Other systems have no per-socket interface option, the source address is still bound
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios"))))]
+fn bind_device(_fd: libc::c_int, _interface: &NetworkInterface, _ipv6: bool) -> io::Result<()> {
+    Ok(())
+}
+
+/// Wrap a transport packet in an IPv4 header for a Layer3 channel.
+///
+/// An unspecified source address is filled in by the OS.
+pub fn build_ipv4_packet<'a>(buf: &'a mut [u8], src_ipaddr: Ipv4Addr, dst_ipaddr: Ipv4Addr, protocol: IpNextHeaderProtocol, payload: &[u8]) -> MutableIpv4Packet<'a> {
+    let total_len = IPV4_HEADER_LEN + payload.len();
+    let mut ip_packet = MutableIpv4Packet::new(&mut buf[..total_len]).unwrap();
+    ip_packet.set_version(4);
+    ip_packet.set_header_length(5);
+    ip_packet.set_total_length(total_len as u16);
+    ip_packet.set_identification(rand::random::<u16>());
+    ip_packet.set_flags(Ipv4Flags::DontFragment);
+    ip_packet.set_ttl(64);
+    ip_packet.set_next_level_protocol(protocol);
+    ip_packet.set_source(src_ipaddr);
+    ip_packet.set_destination(dst_ipaddr);
+    ip_packet.set_payload(payload);
+    let checksum = pnet::packet::ipv4::checksum(&ip_packet.to_immutable());
+    ip_packet.set_checksum(checksum);
+    ip_packet
+}
+
+fn build_tcp_packet(buf: &mut [u8], src_ipaddr: IpAddr, src_port: u16, dst_ipaddr: IpAddr, dst_port: u16, scan_type: PortScanType) -> MutableTcpPacket<'_> {
+    let mut tcp_packet = MutableTcpPacket::new(buf).unwrap();
+    tcp_packet.set_source(src_port);
+    tcp_packet.set_destination(dst_port);
+    tcp_packet.set_window(64240);
+    tcp_packet.set_data_offset(8);
+    tcp_packet.set_urgent_ptr(0);
+    tcp_packet.set_sequence(INITIAL_SEQUENCE);
+    tcp_packet.set_options(&[TcpOption::mss(1460)
+    , TcpOption::sack_perm()
+    , TcpOption::nop()
+    , TcpOption::nop()
+    , TcpOption::wscale(7)]);
+    let flags = match scan_type {
+        PortScanType::SynScan => TcpFlags::SYN,
+        PortScanType::FinScan => TcpFlags::FIN,
+        PortScanType::XmasScan => TcpFlags::FIN | TcpFlags::URG | TcpFlags::PSH,
+        PortScanType::NullScan => 0,
+    };
+    tcp_packet.set_flags(flags);
+    let checksum = match (src_ipaddr, dst_ipaddr) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => pnet::packet::tcp::ipv4_checksum(&tcp_packet.to_immutable(), &src, &dst),
+        (IpAddr::V6(src), IpAddr::V6(dst)) => pnet::packet::tcp::ipv6_checksum(&tcp_packet.to_immutable(), &src, &dst),
+        _ => 0,
+    };
+    tcp_packet.set_checksum(checksum);
+    tcp_packet
+}
+
+fn get_reply_state(flags: u16, scan_type: PortScanType) -> Option<PortState> {
+    if flags & TcpFlags::RST != 0 {
+        return Some(PortState::Closed);
+    }
+    match scan_type {
+        PortScanType::SynScan if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK => Some(PortState::Open),
+        _ => None,
+    }
+}
+
+fn set_port_state(port_states: &Arc<Mutex<Vec<(u16, PortState)>>>, rtt: &Arc<Mutex<RttTracker<u16>>>, port: u16, state: PortState) {
+    rtt.lock().unwrap().on_reply(&port);
+    let mut port_states = port_states.lock().unwrap();
+    match port_states.iter_mut().find(|(p, _)| *p == port) {
+        Some(entry) => {
+            // A TCP reply is stronger evidence than any ICMP error
+            if entry.1 == PortState::Filtered {
+                entry.1 = state;
+            }
+        },
+        None => port_states.push((port, state)),
+    }
+}
+
+fn handle_tcp_reply(
+    packet: &TcpPacket,
+    ttl: Option<u8>,
+    probes: &Arc<Mutex<HashSet<(u16, u16)>>>,
+    scan_type: PortScanType,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>,
+    fingerprint: &Arc<Mutex<Option<OsFingerprint>>>) {
+    if !probes.lock().unwrap().contains(&(packet.get_destination(), packet.get_source())) {
+        return;
+    }
+    if let Some(state) = get_reply_state(packet.get_flags(), scan_type) {
+        if state == PortState::Open {
+            let mut fingerprint = fingerprint.lock().unwrap();
+            if fingerprint.is_none() {
+                *fingerprint = Some(OsFingerprint::from_syn_ack(ttl, packet));
+            }
+        }
+        set_port_state(port_states, rtt, packet.get_source(), state);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn receive_tcp_packets(
+    mut rx: TransportReceiver,
+    target_ipaddr: IpAddr,
+    probes: &Arc<Mutex<HashSet<(u16, u16)>>>,
+    scan_type: PortScanType,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>,
+    fingerprint: &Arc<Mutex<Option<OsFingerprint>>>) {
+    match target_ipaddr {
+        IpAddr::V4(_) => {
+            let mut iter = ipv4_packet_iter(&mut rx);
+            loop {
+                if let Ok(Some((packet, addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+                    if addr == target_ipaddr {
+                        if let Some(tcp_packet) = TcpPacket::new(packet.payload()) {
+                            handle_tcp_reply(&tcp_packet, Some(packet.get_ttl()), probes, scan_type, port_states, rtt, fingerprint);
+                        }
+                    }
+                }
+                if *stop.lock().unwrap() {
+                    break;
+                }
+            }
+        },
+        IpAddr::V6(_) => {
+            let mut iter = tcp_packet_iter(&mut rx);
+            loop {
+                if let Ok(Some((packet, addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+                    if addr == target_ipaddr {
+                        handle_tcp_reply(&packet, None, probes, scan_type, port_states, rtt, fingerprint);
+                    }
+                }
+                if *stop.lock().unwrap() {
+                    break;
+                }
+            }
+        },
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn receive_tcp_packets(
+    mut rx: TransportReceiver,
+    target_ipaddr: IpAddr,
+    probes: &Arc<Mutex<HashSet<(u16, u16)>>>,
+    scan_type: PortScanType,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>,
+    fingerprint: &Arc<Mutex<Option<OsFingerprint>>>) {
+    let mut iter = ipv4_packet_iter(&mut rx);
+    loop {
+        if let Ok((packet, addr)) = iter.next() {
+            if addr == target_ipaddr {
+                if let Some(tcp_packet) = TcpPacket::new(packet.payload()) {
+                    handle_tcp_reply(&tcp_packet, Some(packet.get_ttl()), probes, scan_type, port_states, rtt, fingerprint);
+                }
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
+
+// Extract the destination port of our own TCP probe quoted in an ICMP error.
+fn get_quoted_tcp_port(quoted: &[u8], target_ipaddr: IpAddr, probes: &Arc<Mutex<HashSet<(u16, u16)>>>) -> Option<u16> {
+    let (header_len, quoted_dst) = match target_ipaddr {
+        IpAddr::V4(_) => {
+            if quoted.len() < 20 {
+                return None;
+            }
+            let mut octets = [0u8; 4];
+            octets.copy_from_slice(&quoted[16..20]);
+            (((quoted[0] & 0x0f) as usize) * 4, IpAddr::from(octets))
+        },
+        IpAddr::V6(_) => {
+            if quoted.len() < 40 {
+                return None;
+            }
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&quoted[24..40]);
+            (40, IpAddr::from(octets))
+        },
+    };
+    // Only the first 8 bytes of the TCP header are guaranteed to be quoted
+    if quoted_dst != target_ipaddr || quoted.len() < header_len + 8 {
+        return None;
+    }
+    let tcp = &quoted[header_len..];
+    let src_port = u16::from_be_bytes([tcp[0], tcp[1]]);
+    let dst_port = u16::from_be_bytes([tcp[2], tcp[3]]);
+    if !probes.lock().unwrap().contains(&(src_port, dst_port)) {
+        return None;
+    }
+    Some(dst_port)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn receive_icmp_packets(
+    mut rx: TransportReceiver,
+    target_ipaddr: IpAddr,
+    probes: &Arc<Mutex<HashSet<(u16, u16)>>>,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>) {
+    match target_ipaddr {
+        IpAddr::V4(_) => {
+            let mut iter = icmp_packet_iter(&mut rx);
+            loop {
+                if let Ok(Some((packet, _addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+                    // Unreachable errors may come from a router or firewall on the path
+                    if packet.get_icmp_type() == IcmpTypes::DestinationUnreachable && packet.payload().len() > 4 {
+                        if let Some(port) = get_quoted_tcp_port(&packet.payload()[4..], target_ipaddr, probes) {
+                            set_port_state(port_states, rtt, port, PortState::Filtered);
+                        }
+                    }
+                }
+                if *stop.lock().unwrap() {
+                    break;
+                }
+            }
+        },
+        IpAddr::V6(_) => {
+            let mut iter = icmpv6_packet_iter(&mut rx);
+            loop {
+                if let Ok(Some((packet, _addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+                    if packet.get_icmpv6_type() == Icmpv6Types::DestinationUnreachable && packet.payload().len() > 4 {
+                        if let Some(port) = get_quoted_tcp_port(&packet.payload()[4..], target_ipaddr, probes) {
+                            set_port_state(port_states, rtt, port, PortState::Filtered);
+                        }
+                    }
+                }
+                if *stop.lock().unwrap() {
+                    break;
+                }
+            }
+        },
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn receive_icmp_packets(
+    mut rx: TransportReceiver,
+    target_ipaddr: IpAddr,
+    probes: &Arc<Mutex<HashSet<(u16, u16)>>>,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>) {
+    let mut iter = icmp_packet_iter(&mut rx);
+    loop {
+        if let Ok((packet, _addr)) = iter.next() {
+            if packet.get_icmp_type() == IcmpTypes::DestinationUnreachable && packet.payload().len() > 4 {
+                if let Some(port) = get_quoted_tcp_port(&packet.payload()[4..], target_ipaddr, probes) {
+                    set_port_state(port_states, rtt, port, PortState::Filtered);
+                }
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Names of timing templates, from slowest to fastest.
+pub const TIMING_TEMPLATES: [&str; 6] = ["paranoid", "sneaky", "polite", "normal", "aggressive", "insane"];
+
+/// Probe pacing and retransmission settings shared by scanners.
+#[derive(Clone)]
+pub struct Timing {
+    /// Name of the template the settings are based on.
+    pub name: &'static str,
+    /// Delay between two probes.
+    pub probe_interval: Duration,
+    /// Number of times an unanswered probe is sent again.
+    pub max_retries: u8,
+    /// Probe timeout used until round-trip times have been measured.
+    pub initial_rtt_timeout: Duration,
+    /// Lower bound of the adaptive probe timeout.
+    pub min_rtt_timeout: Duration,
+    /// Upper bound of the adaptive probe timeout.
+    pub max_rtt_timeout: Duration,
+    /// Send schedule shared by every clone of these settings, set by `set_rate`.
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Timing {
+    pub fn new() -> Timing {
+        Timing::from_template("normal").unwrap()
+    }
+    /// Return the settings of a template by name or number (0-5).
+    pub fn from_template(name: &str) -> Option<Timing> {
+        let index = match name.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) => TIMING_TEMPLATES.iter().position(|t| *t == name.to_lowercase())?,
+        };
+        let (interval_ms, max_retries, initial_ms, min_ms, max_ms) = match index {
+            0 => (300000, 5, 10000, 100, 30000),
+            1 => (15000, 5, 5000, 100, 15000),
+            2 => (400, 3, 2000, 100, 10000),
+            3 => (1, 2, 1000, 100, 10000),
+            4 => (0, 1, 500, 100, 1250),
+            5 => (0, 0, 250, 50, 300),
+            _ => return None,
+        };
+        Some(Timing {
+            name: TIMING_TEMPLATES[index],
+            probe_interval: Duration::from_millis(interval_ms),
+            max_retries: max_retries,
+            initial_rtt_timeout: Duration::from_millis(initial_ms),
+            min_rtt_timeout: Duration::from_millis(min_ms),
+            max_rtt_timeout: Duration::from_millis(max_ms),
+            rate_limiter: None,
+        })
+    }
+    /// Limit probes to the given number of packets per second.
+    ///
+    /// The limit applies to all scanners given a clone of these settings,
+    /// so hosts scanned in parallel share it.
+    pub fn set_rate(&mut self, pps: u32){
+        if pps > 0 {
+            self.probe_interval = Duration::from_micros(1_000_000 / pps as u64);
+            self.rate_limiter = Some(RateLimiter::new(self.probe_interval));
+        }
+    }
+    /// True if `set_rate` limits the rate shared by all scanners.
+    pub fn is_rate_limited(&self) -> bool {
+        self.rate_limiter.is_some()
+    }
+    /// Time to wait before sending the next probe.
+    pub fn get_send_delay(&self) -> Duration {
+        match self.rate_limiter {
+            Some(ref rate_limiter) => rate_limiter.reserve(),
+            None => self.probe_interval,
+        }
+    }
+    pub fn set_max_retries(&mut self, max_retries: u8){
+        self.max_retries = max_retries;
+    }
+    /// Ex: "interval 1ms, max retries 2, probe timeout 100ms-10s"
+    pub fn get_description(&self) -> String {
+        if self.is_rate_limited() {
+            return format!("rate {} pps, max retries {}, probe timeout {:?}-{:?}", 1_000_000 / self.probe_interval.as_micros().max(1), self.max_retries, self.min_rtt_timeout, self.max_rtt_timeout);
+        }
+        format!("interval {:?}, max retries {}, probe timeout {:?}-{:?}", self.probe_interval, self.max_retries, self.min_rtt_timeout, self.max_rtt_timeout)
+    }
+    /// Worst case time to probe the given number of targets.
+    pub fn get_max_scan_time(&self, probe_count: usize) -> Duration {
+        let rounds = self.max_retries as u32 + 1;
+        (self.probe_interval * probe_count as u32 + self.max_rtt_timeout) * rounds
+    }
+}
+
+/// Send slots spaced by a fixed interval, shared between threads.
+#[derive(Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    next_send: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> RateLimiter {
+        RateLimiter {
+            interval: interval,
+            next_send: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+    /// Take the next free send slot and return how long to wait for it.
+    pub fn reserve(&self) -> Duration {
+        let mut next_send = self.next_send.lock().unwrap();
+        let now = Instant::now();
+        let send_time = if *next_send > now { *next_send } else { now };
+        *next_send = send_time + self.interval;
+        send_time.duration_since(now)
+    }
+}
+
+/// Smoothed round-trip time and probe timeout (RFC 6298).
+#[derive(Clone, Copy)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    timeout: Duration,
+    min_timeout: Duration,
+    max_timeout: Duration,
+}
+
+impl RttEstimator {
+    pub fn new(timing: &Timing) -> RttEstimator {
+        RttEstimator {
+            srtt: None,
+            rttvar: Duration::from_millis(0),
+            timeout: timing.initial_rtt_timeout,
+            min_timeout: timing.min_rtt_timeout,
+            max_timeout: timing.max_rtt_timeout,
+        }
+    }
+    pub fn add_sample(&mut self, rtt: Duration){
+        match self.srtt {
+            Some(srtt) => {
+                let delta = if srtt > rtt { srtt - rtt } else { rtt - srtt };
+                self.rttvar = (self.rttvar * 3 + delta) / 4;
+                self.srtt = Some((srtt * 7 + rtt) / 8);
+            },
+            None => {
+                self.rttvar = rtt / 2;
+                self.srtt = Some(rtt);
+            },
+        }
+        let timeout = self.srtt.unwrap() + self.rttvar * 4;
+        self.timeout = self.clamp(timeout);
+    }
+    /// Double the timeout after probes went unanswered. The next sample resets it.
+    pub fn back_off(&mut self){
+        self.timeout = self.clamp(self.timeout * 2);
+    }
+    fn clamp(&self, timeout: Duration) -> Duration {
+        if timeout < self.min_timeout {
+            self.min_timeout
+        }else if timeout > self.max_timeout {
+            self.max_timeout
+        }else{
+            timeout
+        }
+    }
+    /// Smoothed round-trip time, if any reply has been measured.
+    pub fn get_srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+    /// Time to wait for a reply before a probe is considered lost.
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// Send times of outstanding probes, used to sample round-trip times.
+///
+/// Replies to retransmitted probes are not sampled because it is unknown
+/// which transmission they answer.
+pub struct RttTracker<K: Eq + Hash> {
+    estimator: RttEstimator,
+    sent: HashMap<K, (Instant, bool)>,
+}
+
+impl<K: Eq + Hash> RttTracker<K> {
+    pub fn new(timing: &Timing) -> RttTracker<K> {
+        RttTracker {
+            estimator: RttEstimator::new(timing),
+            sent: HashMap::new(),
+        }
+    }
+    pub fn on_send(&mut self, key: K){
+        let now = Instant::now();
+        self.sent.entry(key).and_modify(|e| *e = (now, true)).or_insert((now, false));
+    }
+    /// Record a reply and return the round-trip time if it could be measured.
+    pub fn on_reply(&mut self, key: &K) -> Option<Duration> {
+        match self.sent.remove(key) {
+            Some((sent_time, false)) => {
+                let rtt = Instant::now().duration_since(sent_time);
+                self.estimator.add_sample(rtt);
+                Some(rtt)
+            },
+            _ => None,
+        }
+    }
+    pub fn get_srtt(&self) -> Option<Duration> {
+        self.estimator.get_srtt()
+    }
+    pub fn get_timeout(&self) -> Duration {
+        self.estimator.get_timeout()
+    }
+    pub fn back_off(&mut self){
+        self.estimator.back_off();
+    }
+}
+
+/// Wait up to `timeout` for replies, returning early once `is_done` is true.
+pub fn wait_for_replies<F: Fn() -> bool>(timeout: Duration, is_done: F) {
+    let start_time = Instant::now();
+    while Instant::now().duration_since(start_time) < timeout {
+        if is_done() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_is_shared_by_clones() {
+        let mut timing = Timing::new();
+        timing.set_rate(10);
+        let other = timing.clone();
+        assert_eq!(timing.get_send_delay(), Duration::from_millis(0));
+        // The second slot is taken through the clone and is one interval later
+        let delay = other.get_send_delay();
+        assert!(delay > Duration::from_millis(90) && delay <= Duration::from_millis(100));
+        let delay = timing.get_send_delay();
+        assert!(delay > Duration::from_millis(190) && delay <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn interval_without_rate() {
+        let timing = Timing::from_template("polite").unwrap();
+        assert!(!timing.is_rate_limited());
+        assert_eq!(timing.get_send_delay(), Duration::from_millis(400));
+        assert_eq!(timing.clone().get_send_delay(), Duration::from_millis(400));
+    }
+
+    fn timing(initial_ms: u64, min_ms: u64, max_ms: u64) -> Timing {
+        let mut timing = Timing::new();
+        timing.initial_rtt_timeout = Duration::from_millis(initial_ms);
+        timing.min_rtt_timeout = Duration::from_millis(min_ms);
+        timing.max_rtt_timeout = Duration::from_millis(max_ms);
+        timing
+    }
+
+    #[test]
+    fn rtt_estimator_updates() {
+        let mut rtt = RttEstimator::new(&timing(1000, 1, 10000));
+        assert_eq!(rtt.get_srtt(), None);
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(1000));
+        // First sample: SRTT = R, RTTVAR = R/2, RTO = SRTT + 4 * RTTVAR
+        rtt.add_sample(Duration::from_millis(100));
+        assert_eq!(rtt.get_srtt(), Some(Duration::from_millis(100)));
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(300));
+        // RTTVAR = 3/4 * 50 + 1/4 * |100 - 300| = 87.5, SRTT = 7/8 * 100 + 1/8 * 300 = 125
+        rtt.add_sample(Duration::from_millis(300));
+        assert_eq!(rtt.get_srtt(), Some(Duration::from_millis(125)));
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(475));
+        // Same RTT as SRTT: RTTVAR = 3/4 * 87.5 = 65.625
+        rtt.add_sample(Duration::from_millis(125));
+        assert_eq!(rtt.get_srtt(), Some(Duration::from_millis(125)));
+        assert_eq!(rtt.get_timeout(), Duration::from_micros(387500));
+    }
+
+    #[test]
+    fn rtt_timeout_clamp() {
+        let mut rtt = RttEstimator::new(&timing(1000, 100, 500));
+        rtt.add_sample(Duration::from_millis(1));
+        assert_eq!(rtt.get_srtt(), Some(Duration::from_millis(1)));
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(100));
+        let mut rtt = RttEstimator::new(&timing(1000, 100, 500));
+        rtt.add_sample(Duration::from_millis(400));
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn rtt_back_off() {
+        let mut rtt = RttEstimator::new(&timing(1000, 100, 5000));
+        rtt.back_off();
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(2000));
+        rtt.back_off();
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(4000));
+        rtt.back_off();
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(5000));
+        // A new sample sets the timeout from the estimate again
+        rtt.add_sample(Duration::from_millis(100));
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(300));
+        assert_eq!(rtt.get_srtt(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn rtt_tracker_skips_retransmissions() {
+        let mut rtt: RttTracker<u16> = RttTracker::new(&timing(1000, 100, 5000));
+        rtt.on_send(80);
+        rtt.on_send(443);
+        rtt.on_send(443);
+        // Reply to a retransmitted probe is not sampled
+        assert_eq!(rtt.on_reply(&443), None);
+        assert_eq!(rtt.get_srtt(), None);
+        assert!(rtt.on_reply(&80).is_some());
+        assert!(rtt.get_srtt().is_some());
+        // Replies to unknown or already answered probes are ignored
+        assert_eq!(rtt.on_reply(&80), None);
+        assert_eq!(rtt.on_reply(&22), None);
+        rtt.back_off();
+        assert_eq!(rtt.get_timeout(), Duration::from_millis(200));
+    }
+}
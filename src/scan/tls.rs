@@ -0,0 +1,260 @@
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+use openssl::asn1::Asn1Time;
+use openssl::nid::Nid;
+use openssl::pkey::Id;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::{X509, X509NameRef, X509VerifyResult};
+
+/// Ports on which TLS is spoken from the start of the connection.
+pub const TLS_PORTS: [u16; 14] = [443, 465, 563, 636, 853, 989, 990, 992, 993, 994, 995, 5061, 6697, 8443];
+/// Time allowed for connect and handshake.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(3000);
+
+/// Certificate and session details of a TLS port.
+#[derive(Clone)]
+pub struct TlsInfo {
+    pub protocol_version: String,
+    pub subject: String,
+    pub subject_alt_names: Vec<String>,
+    pub issuer: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// Days until the certificate expires. Negative if already expired.
+    pub days_left: i32,
+    pub key_type: String,
+    pub key_bits: u32,
+    pub self_signed: bool,
+}
+
+impl TlsInfo {
+    pub fn get_validity(&self) -> String {
+        let status = if self.days_left < 0 {
+            format!("expired {} days ago", -self.days_left)
+        }else{
+            format!("expires in {} days", self.days_left)
+        };
+        format!("{} - {} ({})", self.not_before, self.not_after, status)
+    }
+    pub fn get_issuer(&self) -> String {
+        if self.self_signed {
+            format!("{} (self-signed)", self.issuer)
+        }else{
+            self.issuer.to_string()
+        }
+    }
+    pub fn get_key(&self) -> String {
+        format!("{} {} bits", self.key_type, self.key_bits)
+    }
+}
+
+/// Structure for TLS handshake and certificate inspection.
+///
+/// Certificates are not verified, so self-signed and expired
+/// certificates can be inspected too.
+pub struct TlsInspector {
+    ip_addr: IpAddr,
+    server_name: String,
+    timeout: Duration,
+}
+
+impl TlsInspector {
+    pub fn new(ip_addr: IpAddr) -> TlsInspector {
+        TlsInspector {
+            ip_addr: ip_addr,
+            server_name: String::new(),
+            timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+        }
+    }
+    /// Set host name sent as SNI.
+    pub fn set_server_name(&mut self, server_name: String){
+        self.server_name = server_name;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        if timeout < self.timeout {
+            self.timeout = timeout;
+        }
+    }
+    /// Perform a handshake on the port and return the server certificate details.
+    pub fn get_tls_info(&self, port: u16) -> Result<TlsInfo, String> {
+        let addr = SocketAddr::new(self.ip_addr, port);
+        let stream = match TcpStream::connect_timeout(&addr, self.timeout) {
+            Ok(stream) => stream,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        stream.set_read_timeout(Some(self.timeout)).ok();
+        stream.set_write_timeout(Some(self.timeout)).ok();
+        let mut builder = match SslConnector::builder(SslMethod::tls()) {
+            Ok(builder) => builder,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        builder.set_verify(SslVerifyMode::NONE);
+        // Accept legacy protocols and ciphers so old servers can be inspected
+        builder.set_min_proto_version(None).ok();
+        builder.set_cipher_list("ALL:@SECLEVEL=0").ok();
+        let connector = builder.build();
+        let mut config = match connector.configure() {
+            Ok(config) => config,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        config.set_verify_hostname(false);
+        if self.server_name.is_empty() {
+            config.set_use_server_name_indication(false);
+        }
+        let ssl_stream = match config.connect(&self.server_name, stream) {
+            Ok(ssl_stream) => ssl_stream,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        let ssl = ssl_stream.ssl();
+        let cert = match ssl.peer_certificate() {
+            Some(cert) => cert,
+            None => return Err(String::from("No certificate presented")),
+        };
+        get_cert_info(&cert, ssl.version_str().to_string())
+    }
+}
+
+fn get_cert_info(cert: &X509, protocol_version: String) -> Result<TlsInfo, String> {
+    let pkey = match cert.public_key() {
+        Ok(pkey) => pkey,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let key_type = match pkey.id() {
+        Id::RSA => "RSA",
+        Id::DSA => "DSA",
+        Id::EC => "EC",
+        Id::ED25519 => "Ed25519",
+        Id::ED448 => "Ed448",
+        _ => "Unknown",
+    };
+    let days_left = match Asn1Time::days_from_now(0) {
+        Ok(now) => match now.diff(cert.not_after()) {
+            Ok(diff) => diff.days,
+            Err(_) => 0,
+        },
+        Err(_) => 0,
+    };
+    let mut subject_alt_names: Vec<String> = vec![];
+    if let Some(names) = cert.subject_alt_names() {
+        for name in names.iter() {
+            if let Some(dns) = name.dnsname() {
+                subject_alt_names.push(format!("DNS:{}", dns));
+            }else if let Some(ip) = name.ipaddress() {
+                subject_alt_names.push(format!("IP:{}", format_ipaddress(ip)));
+            }else if let Some(email) = name.email() {
+                subject_alt_names.push(format!("email:{}", email));
+            }else if let Some(uri) = name.uri() {
+                subject_alt_names.push(format!("URI:{}", uri));
+            }
+        }
+    }
+    let self_signed = cert.issued(cert) == X509VerifyResult::OK && cert.verify(&pkey).unwrap_or(false);
+    Ok(TlsInfo {
+        protocol_version: protocol_version,
+        subject: format_name(cert.subject_name()),
+        subject_alt_names: subject_alt_names,
+        issuer: format_name(cert.issuer_name()),
+        not_before: cert.not_before().to_string(),
+        not_after: cert.not_after().to_string(),
+        days_left: days_left,
+        key_type: key_type.to_string(),
+        key_bits: pkey.bits(),
+        self_signed: self_signed,
+    })
+}
+
+fn format_name(name: &X509NameRef) -> String {
+    let mut entries: Vec<String> = vec![];
+    for entry in name.entries() {
+        let key = match entry.object().nid() {
+            Nid::UNDEF => entry.object().to_string(),
+            nid => nid.short_name().unwrap_or("?").to_string(),
+        };
+        let value = String::from_utf8_lossy(entry.data().as_slice()).to_string();
+        entries.push(format!("{}={}", key, value));
+    }
+    entries.join(", ")
+}
+
+fn format_ipaddress(ip: &[u8]) -> String {
+    if ip.len() == 4 {
+        let mut octets = [0u8; 4];
+        octets.copy_from_slice(ip);
+        IpAddr::from(octets).to_string()
+    }else if ip.len() == 16 {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(ip);
+        IpAddr::from(octets).to_string()
+    }else{
+        ip.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(":")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::ssl::SslAcceptor;
+    use openssl::x509::X509NameBuilder;
+    use openssl::x509::extension::SubjectAlternativeName;
+
+    fn build_self_signed_cert() -> (X509, PKey<Private>) {
+        let pkey = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "nscan.test").unwrap();
+        let name = name.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&pkey).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(30).unwrap()).unwrap();
+        let san = SubjectAlternativeName::new().dns("nscan.test").ip("127.0.0.1").build(&builder.x509v3_context(None, None)).unwrap();
+        builder.append_extension(san).unwrap();
+        builder.sign(&pkey, MessageDigest::sha256()).unwrap();
+        (builder.build(), pkey)
+    }
+
+    #[test]
+    fn inspect_self_signed_listener() {
+        let (cert, pkey) = build_self_signed_cert();
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert).unwrap();
+        acceptor.set_private_key(&pkey).unwrap();
+        let acceptor = acceptor.build();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = acceptor.accept(stream);
+        });
+        let inspector = TlsInspector::new(IpAddr::from([127, 0, 0, 1]));
+        let info = inspector.get_tls_info(port).unwrap();
+        server.join().unwrap();
+        assert_eq!(info.subject, "CN=nscan.test");
+        assert_eq!(info.issuer, "CN=nscan.test");
+        assert!(info.self_signed);
+        assert_eq!(info.subject_alt_names, vec!["DNS:nscan.test".to_string(), "IP:127.0.0.1".to_string()]);
+        assert_eq!(info.key_type, "RSA");
+        assert_eq!(info.key_bits, 2048);
+        assert!(info.days_left >= 29 && info.days_left <= 30);
+        assert!(info.protocol_version.starts_with("TLS"));
+        assert!(info.get_issuer().ends_with("(self-signed)"));
+    }
+
+    #[test]
+    fn closed_port_is_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let inspector = TlsInspector::new(IpAddr::from([127, 0, 0, 1]));
+        assert!(inspector.get_tls_info(port).is_err());
+    }
+}
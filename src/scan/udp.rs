@@ -0,0 +1,335 @@
+use std::{thread, time};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use pnet::packet::Packet;
+use pnet::packet::icmp::IcmpTypes;
+use pnet::packet::icmpv6::Icmpv6Types;
+use pnet::transport::TransportChannelType::Layer4;
+use pnet::transport::TransportProtocol::{Ipv4, Ipv6};
+use pnet::transport::{icmp_packet_iter, icmpv6_packet_iter, TransportReceiver};
+use nerve_base::ScanStatus;
+use super::{PortState, PortStateResult};
+use super::timing::{self, RttTracker, Timing};
+
+const ICMP_PORT_UNREACHABLE: u8 = 3;
+const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+
+// DNS standard query for the root NS records
+const DNS_PROBE: &[u8] = &[
+    0x12, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x02, 0x00, 0x01,
+];
+// TFTP read request (octet mode)
+const TFTP_PROBE: &[u8] = b"\x00\x01nscan.txt\x00octet\x00";
+// ONC RPC NULL call to portmapper v2
+const PORTMAP_PROBE: &[u8] = &[
+    0x72, 0xfe, 0x1d, 0x13, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02,
+    0x00, 0x01, 0x86, 0xa0, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+];
+// NTP v4 client request
+const NTP_PROBE: &[u8] = &[
+    0xe3, 0x00, 0x04, 0xfa, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+// NetBIOS Name Service node status request for "*"
+const NETBIOS_PROBE: &[u8] = b"\x80\xf0\x00\x10\x00\x01\x00\x00\x00\x00\x00\x00\x20CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\x00\x00\x21\x00\x01";
+// SNMPv1 GetRequest for sysDescr.0 with community "public"
+const SNMP_PROBE: &[u8] = &[
+    0x30, 0x29, 0x02, 0x01, 0x00, 0x04, 0x06, 0x70, 0x75, 0x62, 0x6c, 0x69,
+    0x63, 0xa0, 0x1c, 0x02, 0x04, 0x4e, 0x53, 0x43, 0x4e, 0x02, 0x01, 0x00,
+    0x02, 0x01, 0x00, 0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06, 0x01,
+    0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+];
+// MS-SQL Server Resolution Protocol broadcast request
+const MSSQL_PROBE: &[u8] = &[0x02];
+// SSDP discovery request
+const SSDP_PROBE: &[u8] = b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: ssdp:all\r\n\r\n";
+// mDNS query for _services._dns-sd._udp.local PTR
+const MDNS_PROBE: &[u8] = b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x09_services\x07_dns-sd\x04_udp\x05local\x00\x00\x0c\x00\x01";
+
+/// Return protocol-specific payload for well-known ports, or an empty datagram otherwise.
+pub fn get_probe_payload(port: u16) -> &'static [u8] {
+    match port {
+        53 => DNS_PROBE,
+        69 => TFTP_PROBE,
+        111 => PORTMAP_PROBE,
+        123 => NTP_PROBE,
+        137 => NETBIOS_PROBE,
+        161 => SNMP_PROBE,
+        1434 => MSSQL_PROBE,
+        1900 => SSDP_PROBE,
+        5353 => MDNS_PROBE,
+        _ => &[],
+    }
+}
+
+/// Structure for UDP port scan.  
+/// 
+/// Ports that reply are open, ports answered with ICMP port unreachable are closed, 
+/// other ICMP unreachable errors mark ports filtered, and silent ports are open|filtered. 
+pub struct UdpScanner {
+    /// IP Address of target host.  
+    target_ipaddr: IpAddr,
+    /// Source port of probes. 0 lets the OS choose one.  
+    src_port: u16,
+    /// List of target ports.  
+    target_ports: Vec<u16>,
+    /// Probe pacing and retransmission.  
+    timing: Timing,
+    /// Timeout setting of port scan.  
+    timeout: Duration,
+}
+
+impl UdpScanner {
+    pub fn new(target_ipaddr: IpAddr) -> UdpScanner {
+        UdpScanner {
+            target_ipaddr: target_ipaddr,
+            src_port: 0,
+            target_ports: vec![],
+            timing: Timing::new(),
+            timeout: Duration::from_millis(30000),
+        }
+    }
+    pub fn set_src_port(&mut self, src_port: u16){
+        self.src_port = src_port;
+    }
+    pub fn set_ports(&mut self, ports: Vec<u16>){
+        self.target_ports = ports;
+    }
+    pub fn set_timing(&mut self, timing: Timing){
+        self.timing = timing;
+    }
+    pub fn set_timeout(&mut self, timeout: Duration){
+        self.timeout = timeout;
+    }
+    pub fn run_scan(&self) -> Result<PortStateResult, String> {
+        let start_time = Instant::now();
+        let bind_addr = match self.target_ipaddr {
+            IpAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), self.src_port),
+            IpAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), self.src_port),
+        };
+        let socket = match UdpSocket::bind(bind_addr) {
+            Ok(socket) => socket,
+            Err(e) => return Err(format!("Failed to bind UDP socket: {}", e)),
+        };
+        let local_port = match socket.local_addr() {
+            Ok(addr) => addr.port(),
+            Err(e) => return Err(format!("Failed to bind UDP socket: {}", e)),
+        };
+        let recv_socket = match socket.try_clone() {
+            Ok(socket) => socket,
+            Err(e) => return Err(format!("Failed to bind UDP socket: {}", e)),
+        };
+        let protocol = match self.target_ipaddr {
+            IpAddr::V4(_) => Layer4(Ipv4(pnet::packet::ip::IpNextHeaderProtocols::Icmp)),
+            IpAddr::V6(_) => Layer4(Ipv6(pnet::packet::ip::IpNextHeaderProtocols::Icmpv6)),
+        };
+        let (_tx, icmp_rx) = match pnet::transport::transport_channel(4096, protocol) {
+            Ok((tx, rx)) => (tx, rx),
+            Err(e) => return Err(format!("Failed to open ICMP channel: {}", e)),
+        };
+        let stop: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
+        let port_states: Arc<Mutex<Vec<(u16, PortState)>>> = Arc::new(Mutex::new(vec![]));
+        let rtt: Arc<Mutex<RttTracker<u16>>> = Arc::new(Mutex::new(RttTracker::new(&self.timing)));
+        let udp_handle = {
+            let stop = Arc::clone(&stop);
+            let port_states = Arc::clone(&port_states);
+            let rtt = Arc::clone(&rtt);
+            let target_ipaddr = self.target_ipaddr;
+            thread::spawn(move || receive_udp_packets(recv_socket, target_ipaddr, &stop, &port_states, &rtt))
+        };
+        let icmp_handle = {
+            let stop = Arc::clone(&stop);
+            let port_states = Arc::clone(&port_states);
+            let rtt = Arc::clone(&rtt);
+            let target_ipaddr = self.target_ipaddr;
+            thread::spawn(move || receive_icmp_packets(icmp_rx, target_ipaddr, local_port, &stop, &port_states, &rtt))
+        };
+        let mut scan_status = ScanStatus::Done;
+        // Probes that get no reply are sent again in the next round
+        'rounds: for _ in 0..self.timing.max_retries as usize + 1 {
+            let pending: Vec<u16> = {
+                let answered = port_states.lock().unwrap();
+                self.target_ports.iter().cloned().filter(|port| !answered.iter().any(|(p, _)| p == port)).collect()
+            };
+            if pending.is_empty() {
+                break;
+            }
+            for port in pending {
+                if Instant::now().duration_since(start_time) > self.timeout {
+                    scan_status = ScanStatus::Timeout;
+                    break 'rounds;
+                }
+                thread::sleep(self.timing.get_send_delay());
+                rtt.lock().unwrap().on_send(port);
+                let _result = socket.send_to(get_probe_payload(port), SocketAddr::new(self.target_ipaddr, port));
+            }
+            let elapsed = Instant::now().duration_since(start_time);
+            if elapsed >= self.timeout {
+                break;
+            }
+            let remaining = self.timeout - elapsed;
+            let probe_timeout = rtt.lock().unwrap().get_timeout();
+            timing::wait_for_replies(if remaining < probe_timeout { remaining } else { probe_timeout }, || {
+                port_states.lock().unwrap().len() >= self.target_ports.len()
+            });
+            // Probes sent again wait longer, until a new reply is measured
+            rtt.lock().unwrap().back_off();
+        }
+        *stop.lock().unwrap() = true;
+        let _ = udp_handle.join();
+        #[cfg(not(target_os = "windows"))]
+        let _ = icmp_handle.join();
+        #[cfg(target_os = "windows")]
+        drop(icmp_handle);
+        let answered = port_states.lock().unwrap().clone();
+        let mut ports: Vec<(u16, PortState)> = vec![];
+        for port in &self.target_ports {
+            let state = match answered.iter().find(|(p, _)| p == port) {
+                Some((_, state)) => *state,
+                None => PortState::OpenFiltered,
+            };
+            ports.push((*port, state));
+        }
+        ports.sort_by_key(|(port, _)| *port);
+        let srtt = rtt.lock().unwrap().get_srtt();
+        Ok(PortStateResult {
+            ports: ports,
+            rtt: srtt,
+            fingerprint: None,
+            scan_status: scan_status,
+        })
+    }
+}
+
+fn set_port_state(port_states: &Arc<Mutex<Vec<(u16, PortState)>>>, rtt: &Arc<Mutex<RttTracker<u16>>>, port: u16, state: PortState) {
+    rtt.lock().unwrap().on_reply(&port);
+    let mut port_states = port_states.lock().unwrap();
+    match port_states.iter_mut().find(|(p, _)| *p == port) {
+        Some(entry) => {
+            // A UDP reply is stronger evidence than any ICMP error
+            if entry.1 != PortState::Open {
+                entry.1 = state;
+            }
+        },
+        None => port_states.push((port, state)),
+    }
+}
+
+fn receive_udp_packets(
+    socket: UdpSocket,
+    target_ipaddr: IpAddr,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>) {
+    let _ = socket.set_read_timeout(Some(Duration::from_millis(100)));
+    let mut buf = [0u8; 2048];
+    loop {
+        if let Ok((_size, src_addr)) = socket.recv_from(&mut buf) {
+            if src_addr.ip() == target_ipaddr {
+                set_port_state(port_states, rtt, src_addr.port(), PortState::Open);
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
+
+// Extract the destination port of our own UDP datagram quoted in an ICMP error.
+fn get_quoted_udp_port(quoted: &[u8], is_ipv6: bool, local_port: u16) -> Option<u16> {
+    let header_len = if is_ipv6 {
+        40
+    }else{
+        if quoted.is_empty() {
+            return None;
+        }
+        ((quoted[0] & 0x0f) as usize) * 4
+    };
+    if quoted.len() < header_len + 8 {
+        return None;
+    }
+    let udp = pnet::packet::udp::UdpPacket::new(&quoted[header_len..])?;
+    if udp.get_source() != local_port {
+        return None;
+    }
+    Some(udp.get_destination())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn receive_icmp_packets(
+    mut rx: TransportReceiver,
+    target_ipaddr: IpAddr,
+    local_port: u16,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>) {
+    match target_ipaddr {
+        IpAddr::V4(_) => {
+            let mut iter = icmp_packet_iter(&mut rx);
+            loop {
+                if let Ok(Some((packet, addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+                    if addr == target_ipaddr && packet.get_icmp_type() == IcmpTypes::DestinationUnreachable && packet.payload().len() > 4 {
+                        if let Some(port) = get_quoted_udp_port(&packet.payload()[4..], false, local_port) {
+                            set_port_state(port_states, rtt, port, get_unreachable_state(packet.get_icmp_code().0, ICMP_PORT_UNREACHABLE));
+                        }
+                    }
+                }
+                if *stop.lock().unwrap() {
+                    break;
+                }
+            }
+        },
+        IpAddr::V6(_) => {
+            let mut iter = icmpv6_packet_iter(&mut rx);
+            loop {
+                if let Ok(Some((packet, addr))) = iter.next_with_timeout(time::Duration::from_millis(100)) {
+                    if addr == target_ipaddr && packet.get_icmpv6_type() == Icmpv6Types::DestinationUnreachable && packet.payload().len() > 4 {
+                        if let Some(port) = get_quoted_udp_port(&packet.payload()[4..], true, local_port) {
+                            set_port_state(port_states, rtt, port, get_unreachable_state(packet.get_icmpv6_code().0, ICMPV6_PORT_UNREACHABLE));
+                        }
+                    }
+                }
+                if *stop.lock().unwrap() {
+                    break;
+                }
+            }
+        },
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn receive_icmp_packets(
+    mut rx: TransportReceiver,
+    target_ipaddr: IpAddr,
+    local_port: u16,
+    stop: &Arc<Mutex<bool>>,
+    port_states: &Arc<Mutex<Vec<(u16, PortState)>>>,
+    rtt: &Arc<Mutex<RttTracker<u16>>>) {
+    let mut iter = icmp_packet_iter(&mut rx);
+    loop {
+        if let Ok((packet, addr)) = iter.next() {
+            if addr == target_ipaddr && packet.get_icmp_type() == IcmpTypes::DestinationUnreachable && packet.payload().len() > 4 {
+                if let Some(port) = get_quoted_udp_port(&packet.payload()[4..], false, local_port) {
+                    set_port_state(port_states, rtt, port, get_unreachable_state(packet.get_icmp_code().0, ICMP_PORT_UNREACHABLE));
+                }
+            }
+        }
+        if *stop.lock().unwrap() {
+            break;
+        }
+    }
+}
+
+fn get_unreachable_state(code: u8, port_unreachable_code: u8) -> PortState {
+    if code == port_unreachable_code {
+        PortState::Closed
+    }else{
+        PortState::Filtered
+    }
+}
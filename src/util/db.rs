@@ -12,11 +12,34 @@ pub struct Service {
 #[derive(Clone)]
 pub struct Oui {
     pub mac_addr: String,
+    /// Ex: 00:1B:C5 or 00:1B:C5:00:10:00/36
     pub mac_prefix: String,
+    /// Length of the prefix in bits. 24 for MA-L, 28 for MA-M, 36 for MA-S.
+    pub prefix_bits: u8,
     pub vendor_name: String,
     pub vendor_name_detail: String,
 }
 
+#[derive(Clone)]
+pub struct ServiceProbe {
+    pub probe_name: String,
+    pub probe_ports: String,
+    pub probe_string: String,
+    pub service_name: String,
+    pub match_pattern: String,
+    pub product: String,
+    pub version: String,
+}
+
+#[derive(Clone)]
+pub struct OsSignature {
+    pub os_family: String,
+    pub initial_ttl: u8,
+    pub window_size: String,
+    pub mss: String,
+    pub tcp_options: String,
+}
+
 pub fn get_db_connection() -> Result<Connection, String> {
     let file_path = sys::get_db_file_path();
     let c = Connection::open(file_path);
@@ -29,13 +52,17 @@ pub fn get_db_connection() -> Result<Connection, String> {
 pub async fn update_db() -> Result<(), String> {
     let save_path = sys::get_db_file_path();
     match sys::download_file(sys::DB_FILE_URL, save_path.to_str().unwrap()).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(format!("{}", e)),
+        Ok(_) => {},
+        Err(e) => return Err(format!("{}", e)),
     }
+    let mut conn = get_db_connection()?;
+    migrate_service(&conn)?;
+    migrate_os_signature(&conn)?;
+    migrate_oui(&mut conn)
 }
 
 pub fn init_db() {
-    let conn = match get_db_connection() {
+    let mut conn = match get_db_connection() {
         Ok(conn) => conn,
         Err(e) => panic!(e),
     };
@@ -45,17 +72,32 @@ pub fn init_db() {
         PORT_NUMBER TEXT, 
         PROTOCOL TEXT, 
         SERVICE_NAME TEXT,
-        DESCRIPTION TEXT);
+        DESCRIPTION TEXT,
+        FREQUENCY REAL DEFAULT 0);
      CREATE TABLE IF NOT EXISTS OUI ( 
         SERIAL_ID INTEGER PRIMARY KEY AUTOINCREMENT,  
         MAC_PREFIX TEXT, 
         VENDOR_NAME TEXT,
-        VENDOR_NAME_DETAIL TEXT); 
+        VENDOR_NAME_DETAIL TEXT,
+        PREFIX_BITS INTEGER,
+        PREFIX_HEX TEXT); 
     ";
     match conn.execute_batch(sql_str) {
         Ok(_) => {},
         Err(err) => println!("Error: Create Table: {}", err),
     }
+    match migrate_service(&conn) {
+        Ok(_) => {},
+        Err(err) => println!("Error: Migrate SERVICE: {}", err),
+    }
+    match migrate_os_signature(&conn) {
+        Ok(_) => {},
+        Err(err) => println!("Error: Migrate OS_SIGNATURE: {}", err),
+    }
+    match migrate_oui(&mut conn) {
+        Ok(_) => {},
+        Err(err) => println!("Error: Migrate OUI: {}", err),
+    }
     match conn.close(){
         Ok(_) => {},
         Err(err) => println!("Error: Failed to close database : {:?}", err), 
@@ -69,9 +111,9 @@ pub fn delete_service(tx: &Transaction) -> Result<(), String> {
     }
 }
 
-pub fn insert_service(tx: &Transaction, service: Service) -> Result<(), String> {
-    let sql_str_ins = "INSERT INTO SERVICE (PORT_NUMBER,PROTOCOL,SERVICE_NAME,DESCRIPTION) VALUES(?1,?2,?3,?4);";
-    match tx.execute(sql_str_ins, params![service.port_number,service.protocol,service.service_name,service.description]) {
+pub fn insert_service(tx: &Transaction, service: Service, frequency: f64) -> Result<(), String> {
+    let sql_str_ins = "INSERT INTO SERVICE (PORT_NUMBER,PROTOCOL,SERVICE_NAME,DESCRIPTION,FREQUENCY) VALUES(?1,?2,?3,?4,?5);";
+    match tx.execute(sql_str_ins, params![service.port_number,service.protocol,service.service_name,service.description,frequency]) {
         Ok(_) => Ok(()),
         Err(err) => Err(format!("{}", err)),
     }
@@ -84,9 +126,40 @@ pub fn delete_oui(tx: &Transaction) -> Result<(), String> {
     }
 }
 
-pub fn insert_oui(tx: &Transaction, oui: Oui) -> Result<(), String> {
-    let sql_str_ins = "INSERT INTO OUI (MAC_PREFIX,VENDOR_NAME,VENDOR_NAME_DETAIL) VALUES(?1,?2,?3);";
-    match tx.execute(sql_str_ins, params![oui.mac_prefix,oui.vendor_name,oui.vendor_name_detail]) {
+/// `prefix_hex` is the prefix as 12 hex digits, with the bits after the prefix cleared.
+pub fn insert_oui(tx: &Transaction, oui: Oui, prefix_hex: &str) -> Result<(), String> {
+    let sql_str_ins = "INSERT INTO OUI (MAC_PREFIX,VENDOR_NAME,VENDOR_NAME_DETAIL,PREFIX_BITS,PREFIX_HEX) VALUES(?1,?2,?3,?4,?5);";
+    match tx.execute(sql_str_ins, params![oui.mac_prefix,oui.vendor_name,oui.vendor_name_detail,oui.prefix_bits,prefix_hex]) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+pub fn delete_service_probe(tx: &Transaction) -> Result<(), String> {
+    match tx.execute_batch("DELETE FROM SERVICE_PROBE; DELETE FROM sqlite_sequence WHERE name='SERVICE_PROBE';") {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+pub fn insert_service_probe(tx: &Transaction, probe: ServiceProbe) -> Result<(), String> {
+    let sql_str_ins = "INSERT INTO SERVICE_PROBE (PROBE_NAME,PROBE_PORTS,PROBE_STRING,SERVICE_NAME,MATCH_PATTERN,PRODUCT,VERSION) VALUES(?1,?2,?3,?4,?5,?6,?7);";
+    match tx.execute(sql_str_ins, params![probe.probe_name,probe.probe_ports,probe.probe_string,probe.service_name,probe.match_pattern,probe.product,probe.version]) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+pub fn delete_os_signature(tx: &Transaction) -> Result<(), String> {
+    match tx.execute_batch("DELETE FROM OS_SIGNATURE; DELETE FROM sqlite_sequence WHERE name='OS_SIGNATURE';") {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("{}", err)),
+    }
+}
+
+pub fn insert_os_signature(tx: &Transaction, signature: OsSignature) -> Result<(), String> {
+    let sql_str_ins = "INSERT INTO OS_SIGNATURE (OS_FAMILY,INITIAL_TTL,WINDOW_SIZE,MSS,TCP_OPTIONS) VALUES(?1,?2,?3,?4,?5);";
+    match tx.execute(sql_str_ins, params![signature.os_family,signature.initial_ttl,signature.window_size,signature.mss,signature.tcp_options]) {
         Ok(_) => Ok(()),
         Err(err) => Err(format!("{}", err)),
     }
@@ -112,12 +185,34 @@ pub fn update_service(file_path: &String) -> Result<(), String> {
         Err(e) => return Err(format!("{}", e)),
     }
     for row in rows {
+        if row.starts_with("#"){
+            continue;
+        }
         let v: Vec<&str>;
         if file_path.contains(".csv") || file_path.contains(".CSV"){
             v = row.trim().split(",").collect();
         }else{
             v = row.split_whitespace().collect();
         }
+        //nmap-services format. v[0]:service_name, v[1]:port_number/transport_protocol, v[2]:frequency, v[3..]:comment
+        if v.len() >= 3 && v[1].contains("/") {
+            let port_proto: Vec<&str> = v[1].splitn(2, "/").collect();
+            if !sys::is_numeric(port_proto[0]) || !sys::is_numeric(v[2]) {
+                continue;
+            }
+            let description: Vec<&str> = v[3..].iter().map(|w| w.trim_start_matches("#")).filter(|w| !w.is_empty()).collect();
+            let service = Service {
+                port_number: String::from(port_proto[0]),
+                protocol: String::from(port_proto[1]),
+                service_name: String::from(v[0]),
+                description: description.join(" "),
+            };
+            match insert_service(&tx, service, v[2].parse().unwrap_or(0.0)) {
+                Ok(_) => {},
+                Err(e) => return Err(format!("{}", e)),
+            }
+            continue;
+        }
         if v.len() < 4 {
             continue;
         }
@@ -131,7 +226,7 @@ pub fn update_service(file_path: &String) -> Result<(), String> {
             service_name: String::from(v[0]),
             description: String::from(v[3]),
         };
-        match insert_service(&tx, service) {
+        match insert_service(&tx, service, 0.0) {
             Ok(_) => {},
             Err(e) => return Err(format!("{}", e)),
         }
@@ -142,6 +237,10 @@ pub fn update_service(file_path: &String) -> Result<(), String> {
     }
 }
 
+/// Replace vendor prefixes with the prefixes in a file.
+///
+/// Accepts the IEEE registry CSV exports (MA-L, MA-M, MA-S, CID, IAB),
+/// Wireshark manuf files and `prefix,short name,vendor name` CSV files.
 pub fn update_oui(file_path: &String)  -> Result<(), String> {
     let mut conn = match get_db_connection() {
         Ok(conn) => conn,
@@ -152,7 +251,12 @@ pub fn update_oui(file_path: &String)  -> Result<(), String> {
         Ok(content) => content,
         Err(e) => return Err(format!("{}", e)),
     };
-    let rows: Vec<&str> = text.split("\n").collect();
+    let is_csv = file_path.to_lowercase().ends_with(".csv") || text.starts_with("Registry,");
+    let rows: Vec<Vec<String>> = if is_csv {
+        parse_csv(&text)
+    }else{
+        text.lines().map(|line| split_manuf_line(line)).collect()
+    };
     let tx = match conn.transaction(){
         Ok(tx) => tx,
         Err(e) => return Err(format!("{}", e)),
@@ -161,44 +265,309 @@ pub fn update_oui(file_path: &String)  -> Result<(), String> {
         Ok(_) => {},
         Err(e) => return Err(format!("{}", e)),
     }
+    let is_ieee = rows.first().map_or(false, |header| header.first().map_or(false, |v| v == "Registry"));
+    for v in rows {
+        if v.is_empty() || v[0].starts_with("#") {
+            continue;
+        }
+        //IEEE: v[0]:registry, v[1]:assignment, v[2]:organization name, v[3]:organization address
+        //Others: v[0]:prefix, v[1]:short name, v[2]:vendor name
+        let (prefix_str, vendor_name, detail_name) = if is_ieee {
+            if v.len() < 3 {
+                continue;
+            }
+            (v[1].to_string(), get_short_vendor_name(&v[2]), v[2].trim().to_string())
+        }else{
+            if v.len() < 2 {
+                continue;
+            }
+            let detail_name = if v.len() > 2 { v[2].trim().to_string() } else { v[1].trim().to_string() };
+            (v[0].to_string(), v[1].trim().to_string(), detail_name)
+        };
+        let (prefix_hex, prefix_bits) = match parse_mac_prefix(&prefix_str) {
+            Some(prefix) => prefix,
+            None => continue,
+        };
+        let oui = Oui {
+            mac_addr: String::new(),
+            mac_prefix: format_mac_prefix(&prefix_hex, prefix_bits),
+            prefix_bits: prefix_bits,
+            vendor_name: vendor_name,
+            vendor_name_detail: detail_name,
+        };
+        match insert_oui(&tx, oui, &prefix_hex) {
+            Ok(_) => {},
+            Err(e) => return Err(format!("{}", e)),
+        }
+    }
+    match tx.commit() {
+        Ok(_) => Ok(()),
+        Err(e) => return Err(format!("{}", e)),
+    }
+}
+
+/// Add prefix length columns to databases created by older versions and fill them in.
+pub fn migrate_oui(conn: &mut Connection) -> Result<(), String> {
+    let has_bits = match conn.prepare("SELECT PREFIX_BITS FROM OUI LIMIT 1") {
+        Ok(_) => true,
+        Err(_) => false,
+    };
+    if !has_bits {
+        if let Err(e) = conn.execute_batch("ALTER TABLE OUI ADD COLUMN PREFIX_BITS INTEGER; ALTER TABLE OUI ADD COLUMN PREFIX_HEX TEXT;") {
+            return Err(format!("{}", e));
+        }
+    }
+    let tx = match conn.transaction(){
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows: Vec<(i64, String)> = {
+        let mut stmt = match tx.prepare("SELECT SERIAL_ID, MAC_PREFIX FROM OUI WHERE PREFIX_HEX IS NULL") {
+            Ok(stmt) => stmt,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        let rows = match stmt.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?))) {
+            Ok(rows) => rows,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        rows.filter_map(|row| row.ok()).collect()
+    };
+    for (serial_id, mac_prefix) in rows {
+        let (prefix_hex, prefix_bits) = match parse_mac_prefix(&mac_prefix) {
+            Some(prefix) => prefix,
+            None => continue,
+        };
+        let sql_str_upd = "UPDATE OUI SET MAC_PREFIX = ?1, PREFIX_BITS = ?2, PREFIX_HEX = ?3, VENDOR_NAME_DETAIL = TRIM(VENDOR_NAME_DETAIL) WHERE SERIAL_ID = ?4;";
+        if let Err(e) = tx.execute(sql_str_upd, params![format_mac_prefix(&prefix_hex, prefix_bits), prefix_bits, prefix_hex, serial_id]) {
+            return Err(format!("{}", e));
+        }
+    }
+    if let Err(e) = tx.execute_batch("CREATE INDEX IF NOT EXISTS OUI_PREFIX_HEX ON OUI (PREFIX_HEX);") {
+        return Err(format!("{}", e));
+    }
+    match tx.commit() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+/// Add the FREQUENCY column to SERVICE tables created without it, and the SERVICE_PROBE table to databases without it.
+pub fn migrate_service(conn: &Connection) -> Result<(), String> {
+    let has_frequency = match conn.prepare("SELECT FREQUENCY FROM SERVICE LIMIT 1") {
+        Ok(_) => true,
+        Err(_) => false,
+    };
+    if !has_frequency {
+        if let Err(e) = conn.execute_batch("ALTER TABLE SERVICE ADD COLUMN FREQUENCY REAL DEFAULT 0;") {
+            return Err(format!("{}", e));
+        }
+    }
+    let sql_str = 
+    "CREATE TABLE IF NOT EXISTS SERVICE_PROBE ( 
+        SERIAL_ID INTEGER PRIMARY KEY AUTOINCREMENT,  
+        PROBE_NAME TEXT, 
+        PROBE_PORTS TEXT, 
+        PROBE_STRING TEXT, 
+        SERVICE_NAME TEXT, 
+        MATCH_PATTERN TEXT, 
+        PRODUCT TEXT, 
+        VERSION TEXT); 
+    ";
+    match conn.execute_batch(sql_str) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+/// Add the OS_SIGNATURE table to databases created without it.
+pub fn migrate_os_signature(conn: &Connection) -> Result<(), String> {
+    let sql_str = 
+    "CREATE TABLE IF NOT EXISTS OS_SIGNATURE ( 
+        SERIAL_ID INTEGER PRIMARY KEY AUTOINCREMENT,  
+        OS_FAMILY TEXT, 
+        INITIAL_TTL INTEGER, 
+        WINDOW_SIZE TEXT, 
+        MSS TEXT, 
+        TCP_OPTIONS TEXT); 
+    ";
+    match conn.execute_batch(sql_str) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+/// Replace service probe rules with the rules in a tab-separated file.
+pub fn update_service_probe(file_path: &String) -> Result<(), String> {
+    let mut conn = match get_db_connection() {
+        Ok(conn) => conn,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let data = read_to_string(file_path);
+    let text = match data {
+        Ok(content) => content,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows: Vec<&str> = text.split("\n").collect();
+    let tx = match conn.transaction(){
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    match delete_service_probe(&tx) {
+        Ok(_) => {},
+        Err(e) => return Err(format!("{}", e)),
+    }
     for row in rows {
         if row.starts_with("#"){
             continue;
         }
-        let v: Vec<&str>;
-        if file_path.contains(".csv") || file_path.contains(".CSV"){
-            v = row.trim().split(",").collect();
-        }else{
-            v = row.split_whitespace().collect();
+        let v: Vec<&str> = row.trim_end_matches("\r").split("\t").collect();
+        if v.len() < 5 || v[0].is_empty() || v[4].is_empty() {
+            continue;
         }
-        if v.len() < 3 {
+        //v[0]:probe_name, v[1]:probe_ports, v[2]:probe_string, v[3]:service_name, v[4]:match_pattern, v[5]:product, v[6]:version
+        let probe = ServiceProbe {
+            probe_name: String::from(v[0]),
+            probe_ports: String::from(v[1]),
+            probe_string: String::from(v[2]),
+            service_name: String::from(v[3]),
+            match_pattern: String::from(v[4]),
+            product: String::from(*v.get(5).unwrap_or(&"")),
+            version: String::from(*v.get(6).unwrap_or(&"")),
+        };
+        match insert_service_probe(&tx, probe) {
+            Ok(_) => {},
+            Err(e) => return Err(format!("{}", e)),
+        }
+    }
+    match tx.commit() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+/// Replace OS signatures with the signatures in a tab-separated file.
+pub fn update_os_signature(file_path: &String) -> Result<(), String> {
+    let mut conn = match get_db_connection() {
+        Ok(conn) => conn,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let data = read_to_string(file_path);
+    let text = match data {
+        Ok(content) => content,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows: Vec<&str> = text.split("\n").collect();
+    let tx = match conn.transaction(){
+        Ok(tx) => tx,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    match delete_os_signature(&tx) {
+        Ok(_) => {},
+        Err(e) => return Err(format!("{}", e)),
+    }
+    for row in rows {
+        if row.starts_with("#"){
             continue;
         }
-        //Vendor detail name may contain spaces
-        let mut detail_name: String = String::new();
-        if v.len() > 3{
-            for i in 2..v.len() {
-                detail_name = format!("{} {}", detail_name, v[i]);
-            }
-        }else{
-            detail_name = String::from(v[2]);
+        let v: Vec<&str> = row.trim_end_matches("\r").split("\t").collect();
+        if v.len() < 2 || v[0].is_empty() {
+            continue;
         }
-        //v[0]:service_name, v[1]:port_number, v[2]:transport_protocol, v[3]:description
-        let oui = Oui {
-            mac_addr: String::new(),
-            mac_prefix: String::from(v[0]),
-            vendor_name: String::from(v[1]),
-            vendor_name_detail: detail_name,
+        //v[0]:os_family, v[1]:initial_ttl, v[2]:window_size, v[3]:mss, v[4]:tcp_options
+        let signature = OsSignature {
+            os_family: String::from(v[0]),
+            initial_ttl: v[1].parse().unwrap_or(0),
+            window_size: String::from(*v.get(2).unwrap_or(&"")),
+            mss: String::from(*v.get(3).unwrap_or(&"")),
+            tcp_options: String::from(*v.get(4).unwrap_or(&"")),
         };
-        match insert_oui(&tx, oui) {
+        match insert_os_signature(&tx, signature) {
             Ok(_) => {},
             Err(e) => return Err(format!("{}", e)),
         }
     }
     match tx.commit() {
         Ok(_) => Ok(()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+pub fn get_os_signatures(conn: &Connection) -> Result<Vec<OsSignature>, String> {
+    let sql_str = 
+    "SELECT 
+        OS_FAMILY, 
+        IFNULL(INITIAL_TTL, 0), 
+        IFNULL(WINDOW_SIZE, ''), 
+        IFNULL(MSS, ''), 
+        IFNULL(TCP_OPTIONS, '') 
+     FROM 
+        OS_SIGNATURE 
+     ORDER BY SERIAL_ID";
+    let mut stmt = match conn.prepare(sql_str) {
+        Ok(stmt) => stmt,
         Err(e) => return Err(format!("{}", e)),
+    };
+    let rows = match stmt.query_map(params![], |row| {
+            Ok(OsSignature {
+                os_family: row.get(0)?,
+                initial_ttl: row.get(1)?,
+                window_size: row.get(2)?,
+                mss: row.get(3)?,
+                tcp_options: row.get(4)?,
+            })
+        }) {
+        Ok(rows) => rows,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let mut signatures: Vec<OsSignature> = vec![];
+    for row in rows {
+        match row {
+            Ok(signature) => signatures.push(signature),
+            Err(e) => return Err(format!("{}", e)),
+        }
     }
+    Ok(signatures)
+}
+
+pub fn get_service_probes(conn: &Connection) -> Result<Vec<ServiceProbe>, String> {
+    let sql_str = 
+    "SELECT 
+        PROBE_NAME, 
+        IFNULL(PROBE_PORTS, ''), 
+        IFNULL(PROBE_STRING, ''), 
+        IFNULL(SERVICE_NAME, ''), 
+        MATCH_PATTERN, 
+        IFNULL(PRODUCT, ''), 
+        IFNULL(VERSION, '') 
+     FROM 
+        SERVICE_PROBE 
+     ORDER BY SERIAL_ID";
+    let mut stmt = match conn.prepare(sql_str) {
+        Ok(stmt) => stmt,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows = match stmt.query_map(params![], |row| {
+            Ok(ServiceProbe {
+                probe_name: row.get(0)?,
+                probe_ports: row.get(1)?,
+                probe_string: row.get(2)?,
+                service_name: row.get(3)?,
+                match_pattern: row.get(4)?,
+                product: row.get(5)?,
+                version: row.get(6)?,
+            })
+        }) {
+        Ok(rows) => rows,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let mut probes: Vec<ServiceProbe> = vec![];
+    for row in rows {
+        match row {
+            Ok(probe) => probes.push(probe),
+            Err(e) => return Err(format!("{}", e)),
+        }
+    }
+    Ok(probes)
 }
 
 pub fn get_service(conn: &Connection, port_number: &str, protocol: &str) -> Result<Service, String> {
@@ -229,34 +598,318 @@ pub fn get_service(conn: &Connection, port_number: &str, protocol: &str) -> Resu
     }
 }
 
-pub fn get_vendor_info(conn: &Connection, mac_addr: &str) -> Result<Oui, String>{
-    if mac_addr.len() < 17{
-        return Err(String::from("Invalid mac address"))
+/// Return port numbers registered for the service name (case-insensitive).
+pub fn get_service_ports(conn: &Connection, service_name: &str, protocol: &str) -> Result<Vec<u16>, String> {
+    let sql_str = 
+    "SELECT DISTINCT 
+        CAST(PORT_NUMBER AS INTEGER) AS PORT 
+     FROM 
+        SERVICE  
+     WHERE 
+        LOWER(SERVICE_NAME) = LOWER(:service_name) 
+        AND PROTOCOL = :protocol 
+        AND CAST(PORT_NUMBER AS INTEGER) BETWEEN 1 AND 65535 
+     ORDER BY PORT";
+    let mut stmt = match conn.prepare(sql_str) {
+        Ok(stmt) => stmt,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows = match stmt.query_map_named(&[(":service_name", &service_name), (":protocol", &protocol)], |row| row.get::<_, i64>(0)) {
+        Ok(rows) => rows,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let mut ports: Vec<u16> = vec![];
+    for row in rows {
+        match row {
+            Ok(port) => ports.push(port as u16),
+            Err(e) => return Err(format!("{}", e)),
+        }
     }
-    let prefix8 = &mac_addr[0..8].to_uppercase();
-    let prefix11 = &mac_addr[0..11].to_uppercase();
+    Ok(ports)
+}
+
+/// Return the `n` most frequently open ports of the protocol. 
+/// 
+/// Ports without frequency data are ordered by port number.
+pub fn get_top_ports(conn: &Connection, protocol: &str, n: u16) -> Result<Vec<u16>, String> {
     let sql_str = 
+    "SELECT 
+        CAST(PORT_NUMBER AS INTEGER) AS PORT, 
+        MAX(IFNULL(FREQUENCY, 0)) AS FREQ 
+     FROM 
+        SERVICE  
+     WHERE 
+        PROTOCOL = :protocol 
+        AND CAST(PORT_NUMBER AS INTEGER) BETWEEN 1 AND 65535 
+     GROUP BY PORT 
+     ORDER BY FREQ DESC, PORT ASC 
+     LIMIT :n";
+    let mut stmt = match conn.prepare(sql_str) {
+        Ok(stmt) => stmt,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows = match stmt.query_map_named(&[(":protocol", &protocol), (":n", &n)], |row| row.get::<_, i64>(0)) {
+        Ok(rows) => rows,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let mut ports: Vec<u16> = vec![];
+    for row in rows {
+        match row {
+            Ok(port) => ports.push(port as u16),
+            Err(e) => return Err(format!("{}", e)),
+        }
+    }
+    Ok(ports)
+}
+
+/// Vendor of the longest registered prefix (MA-L, MA-M, MA-S or CID) of the MAC address.
+pub fn get_vendor_info(conn: &Connection, mac_addr: &str) -> Result<Oui, String>{
+    let mac_value = match parse_mac_prefix(mac_addr) {
+        Some((mac_hex, 48)) => u64::from_str_radix(&mac_hex, 16).unwrap(),
+        _ => return Err(String::from("Invalid mac address")),
+    };
+    let mut candidates: Vec<String> = (1..=48).map(|bits| format!("{:012X}", mask_mac(mac_value, bits))).collect();
+    candidates.dedup();
+    let sql_str = format!(
     "SELECT 
         MAC_PREFIX,
+        PREFIX_BITS,
+        PREFIX_HEX,
         VENDOR_NAME, 
         VENDOR_NAME_DETAIL 
      FROM 
         OUI  
      WHERE 
-        (MAC_PREFIX = :prefix8 OR MAC_PREFIX LIKE :prefix11 + '%')   
-     ORDER BY MAC_PREFIX DESC";
-    let oui = conn.query_row_named(sql_str, &[(":prefix8", &prefix8), (":prefix11", &prefix11)], 
-    |row| {
-            Ok(Oui {
+        PREFIX_HEX IN ({})", vec!["?"; candidates.len()].join(","));
+    let mut stmt = match conn.prepare(&sql_str) {
+        Ok(stmt) => stmt,
+        Err(e) => return Err(format!("{}", e)),
+    };
+    let rows = match stmt.query_map(candidates.iter(), |row| {
+            let prefix_hex: String = row.get(2)?;
+            Ok((prefix_hex, Oui {
                 mac_addr: mac_addr.to_string(),
                 mac_prefix: row.get(0)?,
-                vendor_name: row.get(1)?,
-                vendor_name_detail: row.get(2)?,
-            })
-        }
-    );
-    match oui {
-        Ok(oui) => Ok(oui),
+                prefix_bits: row.get(1)?,
+                vendor_name: row.get(3)?,
+                vendor_name_detail: row.get(4)?,
+            }))
+        }) {
+        Ok(rows) => rows,
         Err(e) => return Err(format!("{}", e)),
+    };
+    let mut best: Option<Oui> = None;
+    for row in rows {
+        let (prefix_hex, oui) = match row {
+            Ok(row) => row,
+            Err(e) => return Err(format!("{}", e)),
+        };
+        // A candidate of another length can equal the prefix only by its trailing zero bits
+        if prefix_hex != format!("{:012X}", mask_mac(mac_value, oui.prefix_bits)) {
+            continue;
+        }
+        if best.as_ref().map_or(true, |b| oui.prefix_bits > b.prefix_bits) {
+            best = Some(oui);
+        }
+    }
+    match best {
+        Some(oui) => Ok(oui),
+        None => Err(format!("No vendor found for {}", mac_addr)),
+    }
+}
+
+// Clear the bits after the prefix of a 48-bit MAC address
+fn mask_mac(mac_value: u64, bits: u8) -> u64 {
+    let bits = if bits > 48 { 48 } else { bits };
+    mac_value & !((1u64 << (48 - bits)) - 1)
+}
+
+/// Parse a MAC prefix such as `00:1B:C5`, `001BC5000`, `00-1B-C5` or `00:1B:C5:00:10:00/36`.
+///
+/// Returns the prefix as 12 hex digits with the bits after the prefix cleared, and the prefix length.
+/// Without `/bits`, the length is 4 bits per hex digit.
+fn parse_mac_prefix(v: &str) -> Option<(String, u8)> {
+    let v = v.trim();
+    let (addr_str, bits_str) = match v.find("/") {
+        Some(i) => (&v[..i], Some(&v[i + 1..])),
+        None => (v, None),
+    };
+    let hex: String = addr_str.chars().filter(|c| *c != ':' && *c != '-' && *c != '.').collect();
+    if hex.is_empty() || hex.len() > 12 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let bits: u8 = match bits_str {
+        Some(bits_str) => bits_str.trim().parse().ok()?,
+        None => hex.len() as u8 * 4,
+    };
+    if bits == 0 || bits > 48 {
+        return None;
+    }
+    let mac_value = u64::from_str_radix(&format!("{:0<12}", hex), 16).ok()?;
+    Some((format!("{:012X}", mask_mac(mac_value, bits)), bits))
+}
+
+// Ex: 00:1B:C5 for 24 bits, 00:1B:C5:00:10:00/36 otherwise
+fn format_mac_prefix(prefix_hex: &str, prefix_bits: u8) -> String {
+    let octets: Vec<&str> = (0..6).map(|i| &prefix_hex[i * 2..i * 2 + 2]).collect();
+    if prefix_bits == 24 {
+        octets[..3].join(":")
+    }else{
+        format!("{}/{}", octets.join(":"), prefix_bits)
+    }
+}
+
+// Short name in the style of Wireshark manuf files. Ex: Shinko Technos co.,ltd. -> ShinkoTe
+fn get_short_vendor_name(name: &str) -> String {
+    name.chars().filter(|c| c.is_ascii_alphanumeric()).take(8).collect()
+}
+
+// Fields of a manuf line: prefix, short name and vendor name separated by tabs or spaces
+fn split_manuf_line(line: &str) -> Vec<String> {
+    let line = line.trim();
+    if line.contains("\t") {
+        return line.split("\t").map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect();
+    }
+    let v: Vec<&str> = line.split_whitespace().collect();
+    if v.len() > 3 {
+        //Vendor name may contain spaces
+        vec![v[0].to_string(), v[1].to_string(), v[2..].join(" ")]
+    }else{
+        v.iter().map(|s| s.to_string()).collect()
+    }
+}
+
+// Records of a CSV text. Quoted fields may contain commas, newlines and "" for a quote.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records: Vec<Vec<String>> = vec![];
+    let mut record: Vec<String> = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                }else{
+                    in_quotes = false;
+                }
+            }else{
+                field.push(c);
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => record.push(std::mem::replace(&mut field, String::new())),
+            '\r' => {},
+            '\n' => {
+                record.push(std::mem::replace(&mut field, String::new()));
+                if record.iter().any(|v| !v.is_empty()) {
+                    records.push(std::mem::replace(&mut record, vec![]));
+                }else{
+                    record.clear();
+                }
+            },
+            _ => field.push(c),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|v| !v.is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_quoted_and_escaped_fields() {
+        let text = "Registry,Assignment,Organization Name\r\nMA-L,001BC5,\"Acme, Inc.\"\r\n\r\nMA-M,001BC50,\"The \"\"Best\"\" Co\"\nMA-S,001BC5001,\"Line one\nLine two\",";
+        let records = parse_csv(text);
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0], vec!["Registry", "Assignment", "Organization Name"]);
+        assert_eq!(records[1], vec!["MA-L", "001BC5", "Acme, Inc."]);
+        assert_eq!(records[2], vec!["MA-M", "001BC50", "The \"Best\" Co"]);
+        assert_eq!(records[3], vec!["MA-S", "001BC5001", "Line one\nLine two", ""]);
+    }
+
+    #[test]
+    fn mac_prefix_forms() {
+        assert_eq!(parse_mac_prefix("00:1B:C5"), Some((String::from("001BC5000000"), 24)));
+        assert_eq!(parse_mac_prefix("00-1b-c5-0"), Some((String::from("001BC5000000"), 28)));
+        assert_eq!(parse_mac_prefix("001BC5001"), Some((String::from("001BC5001000"), 36)));
+        assert_eq!(parse_mac_prefix("00:1B:C5:00:1F:FF/36"), Some((String::from("001BC5001000"), 36)));
+        assert_eq!(parse_mac_prefix("00:1B:C5:00:10:00/49"), None);
+        assert_eq!(parse_mac_prefix("00:1B:G5"), None);
+        assert_eq!(format_mac_prefix("001BC5000000", 24), "00:1B:C5");
+        assert_eq!(format_mac_prefix("001BC5001000", 36), "00:1B:C5:00:10:00/36");
+    }
+
+    // Table in the layout before PREFIX_BITS and PREFIX_HEX were added
+    fn create_legacy_oui_db(prefixes: &[(&str, &str)]) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE OUI (SERIAL_ID INTEGER PRIMARY KEY AUTOINCREMENT, MAC_PREFIX TEXT, VENDOR_NAME TEXT, VENDOR_NAME_DETAIL TEXT);").unwrap();
+        for (mac_prefix, vendor_name) in prefixes {
+            conn.execute("INSERT INTO OUI (MAC_PREFIX, VENDOR_NAME, VENDOR_NAME_DETAIL) VALUES (?1, ?2, ?3);", params![mac_prefix, vendor_name, format!(" {} ", vendor_name)]).unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn longest_prefix_match() {
+        let mut conn = create_legacy_oui_db(&[
+            ("00:1B:C5", "Large"),
+            ("00:1B:C5:00:00:00/28", "Medium"),
+            ("00:1B:C5:00:10:00/36", "Small"),
+        ]);
+        migrate_oui(&mut conn).unwrap();
+        let oui = get_vendor_info(&conn, "00:1b:c5:00:10:05").unwrap();
+        assert_eq!((oui.vendor_name.as_str(), oui.prefix_bits), ("Small", 36));
+        assert_eq!(oui.vendor_name_detail, "Small");
+        let oui = get_vendor_info(&conn, "00:1B:C5:0F:00:01").unwrap();
+        assert_eq!((oui.vendor_name.as_str(), oui.prefix_bits), ("Medium", 28));
+        let oui = get_vendor_info(&conn, "00:1B:C5:00:20:00").unwrap();
+        assert_eq!((oui.vendor_name.as_str(), oui.prefix_bits), ("Medium", 28));
+        let oui = get_vendor_info(&conn, "00:1B:C5:10:00:01").unwrap();
+        assert_eq!((oui.vendor_name.as_str(), oui.prefix_bits, oui.mac_prefix.as_str()), ("Large", 24, "00:1B:C5"));
+        assert!(get_vendor_info(&conn, "00:1B:C6:00:00:01").is_err());
+        assert!(get_vendor_info(&conn, "00:1B:C5").is_err());
+    }
+
+    #[test]
+    fn migrate_service_on_old_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE SERVICE (SERIAL_ID INTEGER PRIMARY KEY AUTOINCREMENT, PORT_NUMBER TEXT, PROTOCOL TEXT, SERVICE_NAME TEXT, DESCRIPTION TEXT);
+            INSERT INTO SERVICE (PORT_NUMBER, PROTOCOL, SERVICE_NAME, DESCRIPTION) VALUES ('80', 'tcp', 'http', 'World Wide Web HTTP');
+            INSERT INTO SERVICE (PORT_NUMBER, PROTOCOL, SERVICE_NAME, DESCRIPTION) VALUES ('22', 'tcp', 'ssh', 'SSH Remote Login Protocol');").unwrap();
+        assert!(get_top_ports(&conn, "tcp", 10).is_err());
+        migrate_service(&conn).unwrap();
+        migrate_service(&conn).unwrap();
+        assert_eq!(get_top_ports(&conn, "tcp", 10).unwrap(), vec![22, 80]);
+        conn.execute("UPDATE SERVICE SET FREQUENCY = 0.5 WHERE PORT_NUMBER = '80';", params![]).unwrap();
+        assert_eq!(get_top_ports(&conn, "tcp", 1).unwrap(), vec![80]);
+        assert!(get_service_probes(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrate_os_signature_creates_table() {
+        let conn = Connection::open_in_memory().unwrap();
+        assert!(get_os_signatures(&conn).is_err());
+        migrate_os_signature(&conn).unwrap();
+        migrate_os_signature(&conn).unwrap();
+        assert!(get_os_signatures(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn migrate_oui_twice() {
+        let mut conn = create_legacy_oui_db(&[("001BC5", "Large")]);
+        migrate_oui(&mut conn).unwrap();
+        migrate_oui(&mut conn).unwrap();
+        let oui = get_vendor_info(&conn, "00:1B:C5:AA:BB:CC").unwrap();
+        assert_eq!((oui.mac_prefix.as_str(), oui.prefix_bits), ("00:1B:C5", 24));
     }
 }
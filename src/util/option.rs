@@ -1,26 +1,92 @@
 use nerve::PortScanType;
+use crate::scan::PortState;
+use crate::scan::host::HostProbe;
+use crate::scan::rdns;
+use crate::scan::timing::Timing;
 use super::sys;
+use std::cmp;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
+use rand::seq::SliceRandom;
+
+/// Default number of hosts scanned in parallel.  
+pub const DEFAULT_PARALLELISM: usize = 8;
+/// Host scans of larger networks require --allow-large.  
+pub const LARGE_HOST_SCAN: usize = 4096;
+
+/// Type of port scan.  
+/// 
+/// SynScan, FinScan, XmasScan and NullScan use raw sockets. 
+/// ConnectScan uses the OS TCP stack and works without administrator privileges. 
+/// UdpScan sends UDP probes and listens for ICMP errors.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScanType {
+    SynScan,
+    FinScan,
+    XmasScan,
+    NullScan,
+    ConnectScan,
+    UdpScan,
+}
+
+impl ScanType {
+    /// Return probe type for raw socket scans, or None otherwise.
+    pub fn to_raw_scan_type(&self) -> Option<PortScanType> {
+        match *self {
+            ScanType::SynScan => Some(PortScanType::SynScan),
+            ScanType::FinScan => Some(PortScanType::FinScan),
+            ScanType::XmasScan => Some(PortScanType::XmasScan),
+            ScanType::NullScan => Some(PortScanType::NullScan),
+            ScanType::ConnectScan | ScanType::UdpScan => None,
+        }
+    }
+}
 
 pub struct PortOption{
     pub ip_addr: String,
-    pub start_port: u16,
-    pub end_port: u16,
+    pub targets: Vec<IpAddr>,
+    pub parallelism: usize,
+    pub ports: Vec<u16>,
+    pub top_ports: u16,
     pub app_port: u16,
-    pub scan_type: PortScanType,
+    pub src_port_specified: bool,
+    pub random_src_port: bool,
+    pub randomize_ports: bool,
+    pub scan_type: ScanType,
+    pub service_detect: bool,
+    pub tls_inspect: bool,
+    pub os_detect: bool,
+    pub show_closed: bool,
+    pub show_filtered: bool,
     pub use_wordlist: bool,
     pub wordlist_path: String,
     pub if_name: String,
+    pub timing: Timing,
     pub timeout: Duration,
+    pub timeout_specified: bool,
     pub save_path: String,
 }
 
 pub struct HostOption{
     pub ip_addr: String,
+    pub targets: Vec<IpAddr>,
+    pub excludes: Vec<(IpAddr, IpAddr)>,
+    pub if_name: String,
     pub scan_host_addr: bool,
     pub use_wordlist: bool,
     pub wordlist_path: String,
+    pub arp: bool,
+    pub probes: Vec<HostProbe>,
+    pub connect_discovery: bool,
+    pub resolve: bool,
+    pub dns_server: Option<SocketAddr>,
+    pub dns_parallelism: usize,
+    pub name_discovery: bool,
+    pub os_detect: bool,
+    pub timing: Timing,
     pub timeout: Duration,
+    pub timeout_specified: bool,
     pub save_path: String,
 }
 
@@ -44,28 +110,70 @@ impl PortOption {
     pub fn new() -> PortOption {
         let port_option = PortOption {
             ip_addr: String::new(),
-            start_port: 0,
-            end_port: 0,
+            targets: vec![],
+            parallelism: DEFAULT_PARALLELISM,
+            ports: vec![],
+            top_ports: 0,
             app_port: 65432,
-            scan_type: PortScanType::SynScan,
+            src_port_specified: false,
+            random_src_port: false,
+            randomize_ports: false,
+            scan_type: ScanType::SynScan,
+            service_detect: false,
+            tls_inspect: false,
+            os_detect: false,
+            show_closed: false,
+            show_filtered: false,
             use_wordlist: false,
             wordlist_path: String::new(),
             if_name: String::new(),
+            timing: Timing::new(),
             timeout: Duration::from_millis(30000),
+            timeout_specified: false,
             save_path: String::new(),
         };
         return port_option;
     }
-    pub fn set_option(&mut self, arg_value: String){
-        let a_vec: Vec<&str> = arg_value.split(":").collect();
-        let addr = a_vec[0].to_string();
-        let port_range = a_vec[1].to_string();
-        let range: Vec<&str> = port_range.split("-").collect();
-        let s_port: u16 = range[0].parse().unwrap();
-        let e_port: u16 = range[1].parse().unwrap();
-        self.ip_addr = addr;
-        self.start_port = s_port;
-        self.end_port = e_port;
+    pub fn set_option(&mut self, arg_value: String) -> Result<(), String> {
+        let (target, ports) = sys::split_target_ports(&arg_value)?;
+        self.targets = sys::parse_target_hosts(&target)?;
+        self.ip_addr = target;
+        if let Some(ports) = ports {
+            self.ports = sys::parse_port_list(&ports)?;
+        }
+        Ok(())
+    }
+    pub fn set_ports(&mut self, ports_str: String) -> Result<(), String> {
+        self.ports = sys::parse_port_list(&ports_str)?;
+        Ok(())
+    }
+    /// Scan the given hosts, such as the hosts found by host discovery.
+    pub fn set_targets(&mut self, ip_addr: String, targets: Vec<IpAddr>){
+        self.ip_addr = ip_addr;
+        self.targets = targets;
+    }
+    /// Remove excluded addresses from the targets.
+    pub fn set_exclude(&mut self, exclude_str: String) -> Result<(), String> {
+        let excludes = sys::parse_exclude_list(&exclude_str)?;
+        self.targets.retain(|target| !sys::is_excluded(&excludes, target));
+        if self.targets.is_empty() {
+            return Err(format!("All targets in {} are excluded", self.ip_addr));
+        }
+        Ok(())
+    }
+    pub fn set_parallelism(&mut self, n_str: String){
+        let n: usize = n_str.parse().unwrap();
+        self.parallelism = n;
+    }
+    pub fn set_top_ports(&mut self, n_str: String){
+        let n: u16 = n_str.parse().unwrap();
+        self.top_ports = n;
+    }
+    /// Merge ports picked from the SERVICE table into the target port list.
+    pub fn add_ports(&mut self, ports: Vec<u16>){
+        self.ports.extend(ports);
+        self.ports.sort();
+        self.ports.dedup();
     }
     pub fn set_file_path(&mut self, file_path: String){
         if !file_path.is_empty() {
@@ -78,23 +186,168 @@ impl PortOption {
             self.if_name = if_name;
         }
     }
+    pub fn set_scan_type(&mut self, scan_type: String){
+        match scan_type.to_lowercase().as_str() {
+            "fin" => {self.scan_type = ScanType::FinScan},
+            "xmas" => {self.scan_type = ScanType::XmasScan},
+            "null" => {self.scan_type = ScanType::NullScan},
+            "connect" => {self.scan_type = ScanType::ConnectScan},
+            "udp" => {self.scan_type = ScanType::UdpScan},
+            _ => {self.scan_type = ScanType::SynScan},
+        }
+    }
+    /// Set source port of probes. "random" picks a new port for each probe.
+    pub fn set_source_port(&mut self, port_str: String){
+        if port_str.to_lowercase() == "random" {
+            self.random_src_port = true;
+        }else{
+            let port: u16 = port_str.parse().unwrap();
+            self.app_port = port;
+            self.src_port_specified = true;
+        }
+    }
+    pub fn set_randomize_ports(&mut self, randomize_ports: bool){
+        self.randomize_ports = randomize_ports;
+    }
+    /// Return target ports in the order they are probed.
+    pub fn get_probe_ports(&self) -> Vec<u16> {
+        let mut ports = self.ports.clone();
+        if self.randomize_ports {
+            ports.shuffle(&mut rand::thread_rng());
+        }
+        ports
+    }
+    pub fn set_service_detect(&mut self, service_detect: bool){
+        self.service_detect = service_detect;
+    }
+    pub fn set_tls_inspect(&mut self, tls_inspect: bool){
+        self.tls_inspect = tls_inspect;
+    }
+    pub fn set_os_detect(&mut self, os_detect: bool){
+        self.os_detect = os_detect;
+    }
+    /// Set additional port states to report. Ex: closed,filtered
+    pub fn set_show_states(&mut self, states: String){
+        for state in states.to_lowercase().split(",") {
+            match state.trim() {
+                "closed" => {self.show_closed = true},
+                "filtered" => {self.show_filtered = true},
+                "all" => {
+                    self.show_closed = true;
+                    self.show_filtered = true;
+                },
+                _ => {},
+            }
+        }
+    }
+    /// Open and open|filtered ports are always reported.
+    pub fn is_shown_state(&self, state: PortState) -> bool {
+        match state {
+            PortState::Open | PortState::OpenFiltered => true,
+            PortState::Closed => self.show_closed,
+            PortState::Filtered => self.show_filtered,
+        }
+    }
+    pub fn set_timing(&mut self, name: String){
+        if let Some(timing) = Timing::from_template(&name) {
+            self.timing = timing;
+        }
+    }
+    pub fn set_rate(&mut self, pps_str: String){
+        let pps: u32 = pps_str.parse().unwrap();
+        self.timing.set_rate(pps);
+    }
+    pub fn set_max_retries(&mut self, n_str: String){
+        let n: u8 = n_str.parse().unwrap();
+        self.timing.set_max_retries(n);
+    }
     pub fn set_timeout(&mut self, ms_str: String){
         let timeout: u64 = ms_str.parse().unwrap();
         self.timeout = Duration::from_millis(timeout);
+        self.timeout_specified = true;
+    }
+    /// Without -t, allow slow timings enough time to probe every target.
+    pub fn adjust_timeout(&mut self, probe_count: usize){
+        if !self.timeout_specified {
+            // Hosts scanned in parallel share the --rate limit
+            let probe_count = if self.timing.is_rate_limited() {
+                probe_count * cmp::max(cmp::min(self.parallelism, self.targets.len()), 1)
+            }else{
+                probe_count
+            };
+            let max_scan_time = self.timing.get_max_scan_time(probe_count);
+            if max_scan_time > self.timeout {
+                self.timeout = max_scan_time;
+            }
+        }
     }
     pub fn set_save_path(&mut self, save_path: String){
         self.save_path = save_path;
     }
+    pub fn get_scan_type_name(&self) -> String {
+        match self.scan_type {
+            ScanType::SynScan => String::from("Syn Scan"),
+            ScanType::FinScan => String::from("Fin Scan"),
+            ScanType::XmasScan => String::from("Xmas Scan"),
+            ScanType::NullScan => String::from("Null Scan"),
+            ScanType::ConnectScan => String::from("Connect Scan"),
+            ScanType::UdpScan => String::from("UDP Scan"),
+        }
+    }
+    pub fn get_protocol(&self) -> String {
+        match self.scan_type {
+            ScanType::UdpScan => String::from("udp"),
+            _ => String::from("tcp"),
+        }
+    }
     pub fn show_options(&self){
         sys::print_fix32("Port Scan Options", sys::FillStr::Hyphen);
-        println!("{}IP Address: {}", sys::SPACE4, self.ip_addr);
-        println!("{}Port Range: {}-{}", sys::SPACE4, self.start_port, self.end_port);
-        match self.scan_type {
-            PortScanType::SynScan => {println!("{}Scan Type: Syn Scan", sys::SPACE4);},
-            PortScanType::FinScan => {println!("{}Scan Type: Fin Scan", sys::SPACE4);},
-            PortScanType::XmasScan => {println!("{}Scan Type: Xmas Scan", sys::SPACE4);},
-            PortScanType::NullScan => {println!("{}Scan Type: Null Scan", sys::SPACE4);},
+        if self.targets.is_empty() {
+            // Sweep targets are the hosts found by host discovery
+            println!("{}Target: Up hosts in {}", sys::SPACE4, self.ip_addr);
+        }else if self.targets.len() == 1 && self.targets[0].to_string() == self.ip_addr {
+            println!("{}IP Address: {}", sys::SPACE4, self.ip_addr);
+        }else if self.targets.len() == 1 {
+            println!("{}Target: {} ({})", sys::SPACE4, self.ip_addr, self.targets[0]);
+        }else{
+            println!("{}Target: {} ({} hosts)", sys::SPACE4, self.ip_addr, self.targets.len());
+        }
+        println!("{}Ports: {} ({} ports)", sys::SPACE4, sys::format_port_list(&self.ports), self.ports.len());
+        if self.top_ports > 0 {
+            println!("{}Top Ports: {}", sys::SPACE4, self.top_ports);
+        }
+        if self.use_wordlist {
+            println!("{}Port list: {}", sys::SPACE4, self.wordlist_path);
+        }
+        println!("{}Scan Type: {}", sys::SPACE4, self.get_scan_type_name());
+        if self.scan_type != ScanType::ConnectScan {
+            if self.random_src_port {
+                println!("{}Source Port: random", sys::SPACE4);
+            }else if self.scan_type == ScanType::UdpScan && !self.src_port_specified {
+                println!("{}Source Port: chosen by the OS", sys::SPACE4);
+            }else{
+                println!("{}Source Port: {}", sys::SPACE4, self.app_port);
+            }
+        }
+        if self.randomize_ports {
+            println!("{}Port Order: random", sys::SPACE4);
+        }
+        if self.service_detect {
+            println!("{}Service Detection: On", sys::SPACE4);
         }
+        if self.tls_inspect {
+            println!("{}TLS Inspection: On", sys::SPACE4);
+        }
+        if self.os_detect {
+            println!("{}OS Detection: On", sys::SPACE4);
+        }
+        if self.show_closed || self.show_filtered {
+            let mut states: Vec<&str> = vec![];
+            if self.show_closed { states.push("closed"); }
+            if self.show_filtered { states.push("filtered"); }
+            println!("{}Show: open,{}", sys::SPACE4, states.join(","));
+        }
+        println!("{}Timing: {} ({})", sys::SPACE4, self.timing.name, self.timing.get_description());
         sys::print_fix32("", sys::FillStr::Hyphen);
     }
 }
@@ -103,18 +356,39 @@ impl HostOption {
     pub fn new() -> HostOption {
         let host_option = HostOption {
             ip_addr: String::new(),
+            targets: vec![],
+            excludes: vec![],
+            if_name: String::new(),
             scan_host_addr: true,
             use_wordlist: false,
             wordlist_path: String::new(),
+            arp: false,
+            probes: vec![],
+            connect_discovery: false,
+            resolve: true,
+            dns_server: None,
+            dns_parallelism: rdns::DEFAULT_DNS_PARALLELISM,
+            name_discovery: false,
+            os_detect: false,
+            timing: Timing::new(),
             timeout: Duration::from_millis(30000),
+            timeout_specified: false,
             save_path: String::new(),
         };
         return host_option;
     }
+    /// Set before set_option, so that addresses without a prefix length use the netmask of this interface.
+    pub fn set_if_name(&mut self, if_name: String){
+        if !if_name.is_empty() {
+            self.if_name = if_name;
+        }
+    }
+    /// Set after set_exclude, so that excluded hosts do not count towards MAX_TARGET_HOSTS.
     pub fn set_option(&mut self, arg_value: String){
-        match sys::get_network_address(arg_value){
-            Ok(ip_str) =>{
-                self.ip_addr = ip_str;
+        match sys::get_host_targets(&arg_value, &self.if_name, &self.excludes){
+            Ok((ip_addr, targets)) =>{
+                self.ip_addr = ip_addr;
+                self.targets = targets;
             },
             Err(e) => {
                 error!("{}", e.to_string());
@@ -122,16 +396,126 @@ impl HostOption {
             },
         }
     }
-    pub fn set_file_path(&mut self, file_path: String){
-        if !file_path.is_empty() {
-            self.scan_host_addr = false;
-            self.use_wordlist = true;
-            self.wordlist_path = file_path;   
+    /// Read target hosts from the word list, after set_exclude. Returns a message for each line that could not be parsed.
+    pub fn set_file_path(&mut self, file_path: String) -> Vec<String> {
+        if file_path.is_empty() {
+            return vec![];
         }
+        let text = match fs::read_to_string(&file_path) {
+            Ok(text) => text,
+            Err(e) => {
+                error!("Could not open or find file: {}", e);
+                std::process::exit(0);
+            },
+        };
+        let (targets, errors) = sys::parse_host_list(&text, &self.excludes);
+        self.scan_host_addr = false;
+        self.use_wordlist = true;
+        self.wordlist_path = file_path;
+        self.targets = targets;
+        errors
+    }
+    /// Set before set_option and set_file_path, which leave out excluded hosts.
+    pub fn set_exclude(&mut self, exclude_str: String){
+        match sys::parse_exclude_list(&exclude_str){
+            Ok(excludes) => {
+                self.excludes = excludes;
+            },
+            Err(e) => {
+                error!("{}", e.to_string());
+                std::process::exit(0);
+            },
+        }
+    }
+    pub fn is_excluded(&self, ip_addr: &IpAddr) -> bool {
+        sys::is_excluded(&self.excludes, ip_addr)
+    }
+    /// Number of target hosts left after exclusions, counted up to one past the maximum.
+    /// 
+    /// IPv6 networks are discovered with multicast and not probed host by host.
+    pub fn get_host_count(&self) -> usize {
+        if self.is_ipv6() {
+            return 0;
+        }
+        self.targets.iter().filter(|host| !self.is_excluded(host)).count()
+    }
+    pub fn is_ipv6(&self) -> bool {
+        self.scan_host_addr && self.ip_addr.contains(":")
+    }
+    pub fn set_os_detect(&mut self, os_detect: bool){
+        self.os_detect = os_detect;
+    }
+    pub fn set_arp(&mut self, arp: bool){
+        self.arp = arp;
+    }
+    /// Without raw sockets, sweep treats hosts that answer the TCP connect scan as up.
+    pub fn set_connect_discovery(&mut self, connect_discovery: bool){
+        self.connect_discovery = connect_discovery;
+    }
+    pub fn add_probe(&mut self, probe_str: String){
+        if let Ok(probes) = HostProbe::parse(&probe_str) {
+            for probe in probes {
+                if !self.probes.contains(&probe) {
+                    self.probes.push(probe);
+                }
+            }
+        }
+    }
+    pub fn set_resolve(&mut self, resolve: bool){
+        self.resolve = resolve;
+    }
+    pub fn set_dns_server(&mut self, server_str: String){
+        self.dns_server = sys::parse_dns_server(&server_str).ok();
+    }
+    pub fn set_dns_parallelism(&mut self, n_str: String){
+        let n: usize = n_str.parse().unwrap();
+        self.dns_parallelism = n;
+    }
+    /// Query up hosts with mDNS, NetBIOS and SSDP for their names and services.
+    pub fn set_name_discovery(&mut self, name_discovery: bool){
+        self.name_discovery = name_discovery;
+    }
+    /// Ex: "ICMP echo"
+    pub fn get_discovery_name(&self) -> String {
+        if self.is_ipv6() {
+            String::from("ICMPv6 echo and NDP")
+        }else if self.connect_discovery {
+            String::from("TCP connect scan")
+        }else if self.arp {
+            String::from("ARP")
+        }else if self.probes.is_empty() {
+            String::from("ICMP echo")
+        }else{
+            let names: Vec<String> = self.probes.iter().map(|probe| probe.get_name()).collect();
+            names.join(", ")
+        }
+    }
+    pub fn set_timing(&mut self, name: String){
+        if let Some(timing) = Timing::from_template(&name) {
+            self.timing = timing;
+        }
+    }
+    pub fn set_rate(&mut self, pps_str: String){
+        let pps: u32 = pps_str.parse().unwrap();
+        self.timing.set_rate(pps);
+    }
+    pub fn set_max_retries(&mut self, n_str: String){
+        let n: u8 = n_str.parse().unwrap();
+        self.timing.set_max_retries(n);
     }
     pub fn set_timeout(&mut self, ms_str: String){
         let timeout: u64 = ms_str.parse().unwrap();
         self.timeout = Duration::from_millis(timeout);
+        self.timeout_specified = true;
+    }
+    /// Without -t, allow slow timings enough time to probe every target.
+    pub fn adjust_timeout(&mut self, probe_count: usize){
+        if !self.timeout_specified {
+            let max_scan_time = self.timing.get_max_scan_time(probe_count);
+            if max_scan_time > self.timeout {
+                self.timeout = max_scan_time;
+            }
+        }
     }
     pub fn set_save_path(&mut self, save_path: String){
         self.save_path = save_path;
@@ -139,10 +523,33 @@ impl HostOption {
     pub fn show_options(&self){
         sys::print_fix32("Host Scan Options", sys::FillStr::Hyphen);
         if self.scan_host_addr {
-            println!("{}Target Network: {}", sys::SPACE4, self.ip_addr);
+            if self.is_ipv6() {
+                println!("{}Target Network: {} (multicast discovery)", sys::SPACE4, self.ip_addr);
+            }else{
+                println!("{}Target Network: {} ({} hosts)", sys::SPACE4, self.ip_addr, self.get_host_count());
+            }
         }else{
-            println!("{}Target: Specified in word list {}", sys::SPACE4, self.wordlist_path);
+            println!("{}Target: Specified in word list {} ({} hosts)", sys::SPACE4, self.wordlist_path, self.get_host_count());
+        }
+        if !self.excludes.is_empty() {
+            println!("{}Excluded: {} entries", sys::SPACE4, self.excludes.len());
+        }
+        if !self.if_name.is_empty() {
+            println!("{}Interface: {}", sys::SPACE4, self.if_name);
+        }
+        println!("{}Discovery: {}", sys::SPACE4, self.get_discovery_name());
+        if !self.resolve {
+            println!("{}Reverse DNS: Off", sys::SPACE4);
+        }else if let Some(dns_server) = self.dns_server {
+            println!("{}Reverse DNS: {}", sys::SPACE4, dns_server);
+        }
+        if self.name_discovery {
+            println!("{}Name Discovery: mDNS, NetBIOS, SSDP", sys::SPACE4);
+        }
+        if self.os_detect {
+            println!("{}OS Detection: On", sys::SPACE4);
         }
+        println!("{}Timing: {} ({})", sys::SPACE4, self.timing.name, self.timing.get_description());
         sys::print_fix32("", sys::FillStr::Hyphen);
     }
 }
@@ -3,9 +3,13 @@ use std::io::prelude::*;
 use std::fs::{self, File};
 use std::{env, io};
 use std::path::{PathBuf};
-use std::net::{IpAddr};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
 use std::str::FromStr;
-use ipnet::{Ipv4Net, Ipv6Net};
+use ipnet::{IpNet, Ipv4Net};
+use regex::Regex;
+use dns_lookup::lookup_host;
 
 #[cfg(any(unix, macos))]
 use sudo::RunningAs;
@@ -18,6 +22,7 @@ pub const NSCAN_DATA_DIR: &str = "data";
 pub const NSCAN_DB_FILE: &str = "nscan.db";
 pub const SPACE4: &str = "    ";
 pub const DB_FILE_URL: &str = "https://github.com/toref-sh/files/raw/main/nscan/nscan.db";
+pub const MAX_TARGET_HOSTS: usize = 65536;
 
 #[allow(dead_code)]
 pub enum FillStr{
@@ -46,25 +51,368 @@ pub fn get_db_file_path() -> PathBuf{
     return path;
 }
 
-pub fn get_network_address(ip_str: String) -> Result<String, String>{
-    let addr = IpAddr::from_str(&ip_str);
-    match addr {
-        Ok(ip_addr) => {
-            match ip_addr {
-                IpAddr::V4(ipv4_addr) => {
-                    let net: Ipv4Net = Ipv4Net::new(ipv4_addr, 24).unwrap();
-                    Ok(net.network().to_string())
+/// Network to scan for `-n 10.0.0.5` or `-n 10.0.0.0/22`.
+/// 
+/// Without a prefix length, the netmask of the local interface on that
+/// network is used, or /24 (/64 for IPv6) if there is none.
+/// If `if_name` is given, only that interface is considered.
+pub fn get_network_address(ip_str: String, if_name: &str) -> Result<IpNet, String>{
+    let net = if ip_str.contains("/") {
+        match IpNet::from_str(&ip_str) {
+            Ok(net) => net,
+            Err(_) => return Err(String::from("Invalid CIDR")),
+        }
+    }else{
+        let ip_addr = match IpAddr::from_str(&ip_str) {
+            Ok(ip_addr) => ip_addr,
+            Err(_) => return Err(String::from("Invalid IP Address")),
+        };
+        let prefix_len = match get_local_prefix_len(ip_addr, if_name) {
+            Some(prefix_len) => prefix_len,
+            None => if ip_addr.is_ipv4() { 24 } else { 64 },
+        };
+        IpNet::new(ip_addr, prefix_len).unwrap()
+    };
+    Ok(net.trunc())
+}
+
+/// Expand host scan targets such as `10.0.0.5,10.1.0.0/24,10.2.0.10-50` into IPv4 addresses.
+///
+/// Addresses without a prefix length stand for their network, as in `get_network_address`.
+/// A single IPv6 network is returned without addresses.
+/// Excluded addresses and duplicates are removed before at most one more than MAX_TARGET_HOSTS addresses are returned.
+/// Returns the normalized target string as well. Ex: "10.0.0.0/24,10.2.0.10-50"
+pub fn get_host_targets(v: &str, if_name: &str, excludes: &[(IpAddr, IpAddr)]) -> Result<(String, Vec<IpAddr>), String> {
+    let mut names: Vec<String> = vec![];
+    let mut hosts: Vec<IpAddr> = vec![];
+    let mut seen: HashSet<IpAddr> = HashSet::new();
+    for item in v.split(",") {
+        let item = item.trim();
+        if item.is_empty() {
+            return Err(format!("Empty entry in target list: {}", v));
+        }
+        let (name, (start, end)) = if item.contains("-") {
+            (item.to_string(), parse_ipv4_range(item)?)
+        }else{
+            let net = match get_network_address(item.to_string(), if_name)? {
+                IpNet::V4(net) => net,
+                IpNet::V6(net) => {
+                    // IPv6 networks are discovered with multicast, not host by host
+                    if v.contains(",") {
+                        return Err(format!("IPv6 network {} can not be combined with other targets", net));
+                    }
+                    return Ok((net.to_string(), vec![]));
                 },
-                IpAddr::V6(ipv6_addr) => {
-                    let net: Ipv6Net = Ipv6Net::new(ipv6_addr, 24).unwrap();
-                    Ok(net.network().to_string())
+            };
+            (net.to_string(), get_ipv4_host_range(&net))
+        };
+        names.push(name);
+        add_ipv4_hosts(start, end, excludes, &mut hosts, &mut seen);
+    }
+    Ok((names.join(","), hosts))
+}
+
+// First and last host of a network, as listed by Ipv4Net::hosts
+fn get_ipv4_host_range(net: &Ipv4Net) -> (Ipv4Addr, Ipv4Addr) {
+    if net.prefix_len() >= 31 {
+        (net.network(), net.broadcast())
+    }else{
+        (Ipv4Addr::from(u32::from(net.network()) + 1), Ipv4Addr::from(u32::from(net.broadcast()) - 1))
+    }
+}
+
+// Add addresses from start to end that are not excluded, up to one more than MAX_TARGET_HOSTS.
+// Excluded ranges are skipped in one step, so large exclusions do not slow down the expansion.
+fn add_ipv4_hosts(start: Ipv4Addr, end: Ipv4Addr, excludes: &[(IpAddr, IpAddr)], hosts: &mut Vec<IpAddr>, seen: &mut HashSet<IpAddr>) {
+    let end = u32::from(end) as u64;
+    let mut addr = u32::from(start) as u64;
+    while addr <= end && hosts.len() <= MAX_TARGET_HOSTS {
+        let ipv4_addr = Ipv4Addr::from(addr as u32);
+        let exclude_end = excludes.iter().filter_map(|exclude| match exclude {
+            (IpAddr::V4(exclude_start), IpAddr::V4(exclude_end)) if *exclude_start <= ipv4_addr && ipv4_addr <= *exclude_end => Some(u32::from(*exclude_end) as u64),
+            _ => None,
+        }).max();
+        match exclude_end {
+            Some(exclude_end) => addr = exclude_end + 1,
+            None => {
+                let host = IpAddr::V4(ipv4_addr);
+                if seen.insert(host) {
+                    hosts.push(host);
+                }
+                addr += 1;
+            },
+        }
+    }
+}
+
+/// Parse a host list with one or more addresses, CIDR blocks, ranges or hostnames per line.
+///
+/// Text after # and blank lines are ignored. Excluded addresses and duplicates are removed before
+/// at most one more than MAX_TARGET_HOSTS addresses are returned.
+/// Returns the hosts and a message for each entry that could not be parsed. Ex: "line 3: Invalid target: 10.0.0"
+pub fn parse_host_list(text: &str, excludes: &[(IpAddr, IpAddr)]) -> (Vec<IpAddr>, Vec<String>) {
+    let mut hosts: Vec<IpAddr> = vec![];
+    let mut seen: HashSet<IpAddr> = HashSet::new();
+    let mut errors: Vec<String> = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = match line.find("#") {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        for item in line.split(",").map(|item| item.trim()).filter(|item| !item.is_empty()) {
+            match parse_target_hosts(item) {
+                Ok(item_hosts) => {
+                    for host in item_hosts {
+                        if hosts.len() > MAX_TARGET_HOSTS {
+                            break;
+                        }
+                        if !is_excluded(excludes, &host) && seen.insert(host) {
+                            hosts.push(host);
+                        }
+                    }
                 },
+                Err(e) => errors.push(format!("line {}: {}", i + 1, e)),
             }
-        },
+        }
+    }
+    (hosts, errors)
+}
+
+/// Parse exclusions such as `10.0.0.5,10.0.0.0/28,10.0.0.100-120` into inclusive address ranges.
+///
+/// A path to an existing file is read instead, one or more entries per line. Lines starting with # are ignored.
+pub fn parse_exclude_list(v: &str) -> Result<Vec<(IpAddr, IpAddr)>, String> {
+    let text = if Path::new(v).is_file() {
+        match fs::read_to_string(v) {
+            Ok(text) => text,
+            Err(e) => return Err(format!("Failed to read {}: {}", v, e)),
+        }
+    }else{
+        v.to_string()
+    };
+    let mut excludes: Vec<(IpAddr, IpAddr)> = vec![];
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#") {
+            continue;
+        }
+        for item in line.split(",") {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+            if let Ok(ip_addr) = IpAddr::from_str(item) {
+                excludes.push((ip_addr, ip_addr));
+            }else if item.contains("/") {
+                match IpNet::from_str(item) {
+                    Ok(net) => excludes.push((net.network(), net.broadcast())),
+                    Err(_) => return Err(format!("Invalid CIDR: {}", item)),
+                }
+            }else if item.contains("-") {
+                let (start_addr, end_addr) = parse_ipv4_range(item)?;
+                excludes.push((IpAddr::V4(start_addr), IpAddr::V4(end_addr)));
+            }else{
+                return Err(format!("Invalid exclusion: {}", item));
+            }
+        }
+    }
+    Ok(excludes)
+}
+
+pub fn is_excluded(excludes: &[(IpAddr, IpAddr)], ip_addr: &IpAddr) -> bool {
+    excludes.iter().any(|(start, end)| start.is_ipv4() == ip_addr.is_ipv4() && start <= ip_addr && ip_addr <= end)
+}
+
+/// True if any exclusion covers an address of the network.
+pub fn is_overlapping(excludes: &[(IpAddr, IpAddr)], net: &IpNet) -> bool {
+    let (network, broadcast) = (net.network(), net.broadcast());
+    excludes.iter().any(|(start, end)| start.is_ipv4() == network.is_ipv4() && *start <= broadcast && *end >= network)
+}
+
+// Prefix length of the local interface address whose network contains the address
+fn get_local_prefix_len(ip_addr: IpAddr, if_name: &str) -> Option<u8> {
+    let interfaces = pnet::datalink::interfaces();
+    let ip = interfaces.iter()
+        .filter(|iface| if_name.is_empty() || iface.name == if_name)
+        .flat_map(|iface| iface.ips.iter())
+        .find(|ip| ip.contains(ip_addr))?;
+    Some(ip.prefix())
+}
+
+pub fn is_hostname(v: &str) -> bool {
+    let re = Regex::new(r"^[A-Za-z0-9]([A-Za-z0-9\-\.]*[A-Za-z0-9])?$").unwrap();
+    re.is_match(v) && v.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// Split `target:ports` into target and port list.
+/// 
+/// IPv6 targets are written in brackets (`[2001:db8::1]:1-1000`). A bare IPv6
+/// address or network without ports is also accepted. Zone IDs (`fe80::1%eth0`)
+/// are rejected; the interface is selected with `-i` instead.
+pub fn split_target_ports(v: &str) -> Result<(String, Option<String>), String> {
+    if v.contains("%") {
+        return Err(format!("Zone IDs are not supported: {}. Use -i to select the interface", v));
+    }
+    if v.starts_with("[") {
+        let end = match v.find("]") {
+            Some(end) => end,
+            None => return Err(format!("Missing ']' in {}", v)),
+        };
+        let target = v[1..end].to_string();
+        let rest = &v[end + 1..];
+        if rest.is_empty() {
+            return Ok((target, None));
+        }
+        if !rest.starts_with(":") {
+            return Err(format!("Expected ':' after ']' in {}", v));
+        }
+        return Ok((target, Some(rest[1..].to_string())));
+    }
+    if v.matches(":").count() > 1 {
+        return Ok((v.to_string(), None));
+    }
+    let a_vec: Vec<&str> = v.splitn(2, ":").collect();
+    Ok((a_vec[0].to_string(), a_vec.get(1).map(|p| p.to_string())))
+}
+
+/// Parse DNS server such as `192.168.1.1`, `192.168.1.1:5353` or `[2001:db8::53]:53`. The port defaults to 53.
+pub fn parse_dns_server(v: &str) -> Result<SocketAddr, String> {
+    if let Ok(ip_addr) = IpAddr::from_str(v) {
+        return Ok(SocketAddr::new(ip_addr, 53));
+    }
+    match SocketAddr::from_str(v) {
+        Ok(addr) => Ok(addr),
+        Err(_) => Err(format!("Invalid DNS server: {}", v)),
+    }
+}
+
+/// Expand target such as `10.0.0.5`, `10.0.0.0/28`, `10.0.0.1-20` or a host name into IP addresses.
+/// 
+/// Host names are resolved via DNS and the first IPv4 address is preferred.
+pub fn parse_target_hosts(target: &str) -> Result<Vec<IpAddr>, String> {
+    let target = target.trim();
+    if let Ok(ip_addr) = IpAddr::from_str(target) {
+        return Ok(vec![ip_addr]);
+    }
+    if target.contains("/") {
+        let net = match IpNet::from_str(target) {
+            Ok(net) => net,
+            Err(_) => return Err(format!("Invalid CIDR: {}", target)),
+        };
+        let hosts: Vec<IpAddr> = net.hosts().take(MAX_TARGET_HOSTS + 1).collect();
+        if hosts.len() > MAX_TARGET_HOSTS {
+            return Err(format!("Too many hosts in {} (max {})", target, MAX_TARGET_HOSTS));
+        }
+        return Ok(hosts);
+    }
+    let range: Vec<&str> = target.splitn(2, "-").collect();
+    if range.len() == 2 && Ipv4Addr::from_str(range[0]).is_ok() {
+        let (start_addr, end_addr) = parse_ipv4_range(target)?;
+        let (start, end) = (u32::from(start_addr), u32::from(end_addr));
+        if (end - start) as usize >= MAX_TARGET_HOSTS {
+            return Err(format!("Too many hosts in {} (max {})", target, MAX_TARGET_HOSTS));
+        }
+        return Ok((start..=end).map(|addr| IpAddr::V4(Ipv4Addr::from(addr))).collect());
+    }
+    if is_hostname(target) {
+        let ips = match lookup_host(target) {
+            Ok(ips) => ips,
+            Err(e) => return Err(format!("Failed to resolve {}: {}", target, e)),
+        };
+        return match ips.iter().find(|ip| ip.is_ipv4()).or(ips.first()) {
+            Some(ip_addr) => Ok(vec![*ip_addr]),
+            None => Err(format!("Failed to resolve {}", target)),
+        };
+    }
+    Err(format!("Invalid target: {}", target))
+}
+
+/// Start and end address of `10.0.0.1-20` (end is the last octet) or `10.0.0.1-10.0.1.20`.
+pub fn parse_ipv4_range(target: &str) -> Result<(Ipv4Addr, Ipv4Addr), String> {
+    let range: Vec<&str> = target.trim().splitn(2, "-").collect();
+    let start_addr = match Ipv4Addr::from_str(range[0]) {
+        Ok(start_addr) if range.len() == 2 => start_addr,
+        _ => return Err(format!("Invalid IP address range: {}", target)),
+    };
+    let end_addr = match Ipv4Addr::from_str(range[1]) {
+        Ok(end_addr) => end_addr,
         Err(_) => {
-            Err(String::from("Invalid IP Address"))
+            match range[1].parse::<u8>() {
+                Ok(last_octet) => {
+                    let o = start_addr.octets();
+                    Ipv4Addr::new(o[0], o[1], o[2], last_octet)
+                },
+                Err(_) => return Err(format!("Invalid IP address range: {}", target)),
+            }
+        },
+    };
+    if start_addr > end_addr {
+        return Err(format!("Invalid IP address range: {} (start address is greater than end address)", target));
+    }
+    Ok((start_addr, end_addr))
+}
+
+/// Parse port list such as `22,80,443,8000-8100` into sorted port numbers without duplicates.
+pub fn parse_port_list(port_str: &str) -> Result<Vec<u16>, String> {
+    let mut ports: Vec<u16> = vec![];
+    for item in port_str.split(",") {
+        let item = item.trim();
+        if item.is_empty() {
+            return Err(format!("Empty entry in port list: {}", port_str));
+        }
+        if item.contains("-") {
+            let range: Vec<&str> = item.splitn(2, "-").collect();
+            let start_port = parse_port_number(range[0])?;
+            let end_port = parse_port_number(range[1])?;
+            if start_port > end_port {
+                return Err(format!("Invalid port range: {} (start port is greater than end port)", item));
+            }
+            for port in start_port..=end_port {
+                ports.push(port);
+            }
+        }else{
+            ports.push(parse_port_number(item)?);
+        }
+    }
+    ports.sort();
+    ports.dedup();
+    Ok(ports)
+}
+
+fn parse_port_number(v: &str) -> Result<u16, String> {
+    match v.trim().parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(format!("Invalid port number: {} (must be 1-65535)", v.trim())),
+    }
+}
+
+/// Group sorted port numbers into contiguous (start, end) ranges.
+pub fn get_port_ranges(ports: &[u16]) -> Vec<(u16, u16)> {
+    let mut ranges: Vec<(u16, u16)> = vec![];
+    for port in ports {
+        match ranges.last_mut() {
+            Some(range) if range.1 != u16::MAX && range.1 + 1 == *port => {
+                range.1 = *port;
+            },
+            _ => {
+                ranges.push((*port, *port));
+            },
+        }
+    }
+    ranges
+}
+
+/// Format port numbers in the same notation accepted by `parse_port_list`.
+pub fn format_port_list(ports: &[u16]) -> String {
+    let mut items: Vec<String> = vec![];
+    for (start_port, end_port) in get_port_ranges(ports) {
+        if start_port == end_port {
+            items.push(start_port.to_string());
+        }else{
+            items.push(format!("{}-{}", start_port, end_port));
         }
     }
+    items.join(",")
 }
 
 pub fn print_fix32(msg: &str, fill_str: FillStr){
@@ -147,3 +495,164 @@ fn read<T: std::str::FromStr>() -> T {
     s.trim().parse().ok().unwrap()
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_ipv4_target_ports() {
+        assert_eq!(split_target_ports("192.168.1.8:1-1000").unwrap(), (String::from("192.168.1.8"), Some(String::from("1-1000"))));
+        assert_eq!(split_target_ports("192.168.1.0/28").unwrap(), (String::from("192.168.1.0/28"), None));
+    }
+
+    #[test]
+    fn split_bracketed_ipv6_target_ports() {
+        assert_eq!(split_target_ports("[2001:db8::1]:22,80").unwrap(), (String::from("2001:db8::1"), Some(String::from("22,80"))));
+        assert_eq!(split_target_ports("[2001:db8::/120]:443").unwrap(), (String::from("2001:db8::/120"), Some(String::from("443"))));
+        assert_eq!(split_target_ports("[::1]").unwrap(), (String::from("::1"), None));
+        assert_eq!(split_target_ports("[::1]:").unwrap(), (String::from("::1"), Some(String::new())));
+        assert_eq!(split_target_ports("2001:db8::1").unwrap(), (String::from("2001:db8::1"), None));
+    }
+
+    #[test]
+    fn split_malformed_ipv6_target() {
+        assert!(split_target_ports("[2001:db8::1").unwrap_err().contains("Missing ']'"));
+        assert!(split_target_ports("[2001:db8::1]22").unwrap_err().contains("Expected ':'"));
+    }
+
+    #[test]
+    fn host_targets_from_networks_and_ranges() {
+        let (name, hosts) = get_host_targets("10.0.0.0/30,10.0.0.1-3", "", &[]).unwrap();
+        assert_eq!(name, "10.0.0.0/30,10.0.0.1-3");
+        assert_eq!(hosts, vec![IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]), IpAddr::from([10, 0, 0, 3])]);
+        assert!(get_host_targets("10.0.0.0/30,,10.0.0.8", "", &[]).unwrap_err().contains("Empty entry"));
+    }
+
+    #[test]
+    fn host_targets_ipv6_network() {
+        let (name, hosts) = get_host_targets("2001:db8::/64", "", &[]).unwrap();
+        assert_eq!(name, "2001:db8::/64");
+        assert!(hosts.is_empty());
+        assert!(get_host_targets("10.0.0.0/30,2001:db8::/64", "", &[]).unwrap_err().contains("can not be combined"));
+    }
+
+    #[test]
+    fn host_targets_exclude_before_limit() {
+        let excludes = parse_exclude_list("10.0.0.0/16").unwrap();
+        let (_, hosts) = get_host_targets("10.0.0.0/15", "", &excludes).unwrap();
+        assert_eq!(hosts.len(), 65535);
+        assert_eq!(hosts[0], IpAddr::from([10, 1, 0, 0]));
+        assert_eq!(hosts[hosts.len() - 1], IpAddr::from([10, 1, 255, 254]));
+        let (_, hosts) = get_host_targets("10.0.0.0/15", "", &[]).unwrap();
+        assert_eq!(hosts.len(), MAX_TARGET_HOSTS + 1);
+        let excludes = parse_exclude_list("10.0.0.2-10.0.1.255,10.0.2.1").unwrap();
+        let (_, hosts) = get_host_targets("10.0.0.1-10.0.2.2", "", &excludes).unwrap();
+        assert_eq!(hosts, vec![IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 2, 0]), IpAddr::from([10, 0, 2, 2])]);
+        let (_, hosts) = get_host_targets("10.0.0.0/31,10.0.0.5/32", "", &[]).unwrap();
+        assert_eq!(hosts, vec![IpAddr::from([10, 0, 0, 0]), IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 5])]);
+    }
+
+    #[test]
+    fn host_list_line_forms() {
+        let text = "# office hosts\n10.0.0.5\n\n10.0.1.0/30\n10.0.2.1-3 # printers\n10.0.3.1, 2001:db8::1,10.0.0.5\n   \nlocalhost\n";
+        let (hosts, errors) = parse_host_list(text, &[]);
+        assert!(errors.is_empty());
+        let expected: Vec<IpAddr> = [
+            "10.0.0.5",
+            "10.0.1.1", "10.0.1.2",
+            "10.0.2.1", "10.0.2.2", "10.0.2.3",
+            "10.0.3.1", "2001:db8::1",
+            "127.0.0.1",
+        ].iter().map(|s| IpAddr::from_str(s).unwrap()).collect();
+        assert_eq!(hosts, expected);
+    }
+
+    #[test]
+    fn host_list_errors_have_line_numbers() {
+        let text = "10.0.0.1\n10.0.0.0/33\n# 10.0.0.x\n10.0.0.2, 10.0.0.9-1\n10.0.0.1-zz\n10.0.0.0/8\n-bad-\n";
+        let (hosts, errors) = parse_host_list(text, &[]);
+        assert_eq!(hosts, vec![IpAddr::from_str("10.0.0.1").unwrap(), IpAddr::from_str("10.0.0.2").unwrap()]);
+        assert_eq!(errors, vec![
+            String::from("line 2: Invalid CIDR: 10.0.0.0/33"),
+            format!("line 4: {}", parse_target_hosts("10.0.0.9-1").unwrap_err()),
+            String::from("line 5: Invalid IP address range: 10.0.0.1-zz"),
+            format!("line 6: Too many hosts in 10.0.0.0/8 (max {})", MAX_TARGET_HOSTS),
+            String::from("line 7: Invalid target: -bad-"),
+        ]);
+    }
+
+    #[test]
+    fn exclude_cidr_and_range() {
+        let excludes = parse_exclude_list("10.0.0.5, 10.0.1.0/28,10.0.2.100-120").unwrap();
+        assert_eq!(excludes, vec![
+            (IpAddr::from([10, 0, 0, 5]), IpAddr::from([10, 0, 0, 5])),
+            (IpAddr::from([10, 0, 1, 0]), IpAddr::from([10, 0, 1, 15])),
+            (IpAddr::from([10, 0, 2, 100]), IpAddr::from([10, 0, 2, 120])),
+        ]);
+        assert!(is_excluded(&excludes, &IpAddr::from([10, 0, 0, 5])));
+        assert!(!is_excluded(&excludes, &IpAddr::from([10, 0, 0, 6])));
+        assert!(is_excluded(&excludes, &IpAddr::from([10, 0, 1, 0])));
+        assert!(is_excluded(&excludes, &IpAddr::from([10, 0, 1, 15])));
+        assert!(!is_excluded(&excludes, &IpAddr::from([10, 0, 1, 16])));
+        assert!(is_excluded(&excludes, &IpAddr::from([10, 0, 2, 120])));
+        assert!(!is_excluded(&excludes, &IpAddr::from([10, 0, 2, 99])));
+    }
+
+    #[test]
+    fn exclude_overlapping_entries() {
+        let excludes = parse_exclude_list("10.0.0.0/28,10.0.0.10-20,10.0.0.12").unwrap();
+        for last in 0..=20u8 {
+            assert!(is_excluded(&excludes, &IpAddr::from([10, 0, 0, last])));
+        }
+        assert!(!is_excluded(&excludes, &IpAddr::from([10, 0, 0, 21])));
+    }
+
+    #[test]
+    fn exclude_matches_ip_version() {
+        let excludes = parse_exclude_list("2001:db8::/126").unwrap();
+        assert!(is_excluded(&excludes, &IpAddr::from_str("2001:db8::3").unwrap()));
+        assert!(!is_excluded(&excludes, &IpAddr::from_str("2001:db8::4").unwrap()));
+        assert!(!is_excluded(&excludes, &IpAddr::from([0, 0, 0, 1])));
+    }
+
+    #[test]
+    fn exclude_list_from_file() {
+        let path = env::temp_dir().join(format!("nscan-exclude-{}.txt", std::process::id()));
+        fs::write(&path, "# office\n10.0.0.1\n\n10.0.0.8/30, 10.0.0.20\n").unwrap();
+        let excludes = parse_exclude_list(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(excludes.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn invalid_exclusions() {
+        assert!(parse_exclude_list("10.0.0.0/33").unwrap_err().contains("Invalid CIDR"));
+        assert!(parse_exclude_list("office").unwrap_err().contains("Invalid exclusion"));
+        // Not resolved as a host name
+        assert_eq!(parse_exclude_list("my-host").unwrap_err(), "Invalid IP address range: my-host");
+        assert!(parse_exclude_list("10.0.0.9-1").unwrap_err().contains("start address is greater"));
+    }
+
+    #[test]
+    fn exclude_large_range() {
+        let excludes = parse_exclude_list("10.0.0.0-10.255.255.255").unwrap();
+        assert_eq!(excludes, vec![(IpAddr::from([10, 0, 0, 0]), IpAddr::from([10, 255, 255, 255]))]);
+    }
+
+    #[test]
+    fn ipv6_network_overlap() {
+        let net = IpNet::from_str("2001:db8::/64").unwrap();
+        // IPv6 discovery can not skip hosts, so any overlap is refused
+        assert!(is_overlapping(&parse_exclude_list("2001:db8::1").unwrap(), &net));
+        assert!(is_overlapping(&parse_exclude_list("2001:db8::/48").unwrap(), &net));
+        assert!(!is_overlapping(&parse_exclude_list("2001:db8:1::/64").unwrap(), &net));
+        assert!(!is_overlapping(&parse_exclude_list("10.0.0.0/8").unwrap(), &net));
+    }
+
+    #[test]
+    fn reject_zone_id() {
+        assert!(split_target_ports("[fe80::1%eth0]:22").unwrap_err().contains("Zone IDs are not supported"));
+        assert!(split_target_ports("fe80::1%eth0").unwrap_err().contains("Zone IDs are not supported"));
+    }
+}
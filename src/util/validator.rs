@@ -1,35 +1,57 @@
 use regex::Regex;
-use std::str::FromStr;
-use std::net::IpAddr;
 use std::path::Path;
 use nerve_base::interface;
+use super::sys;
+use crate::scan::host::HostProbe;
+use crate::scan::timing::{Timing, TIMING_TEMPLATES};
 
 pub fn validate_port_opt(v: String) -> Result<(), String> {
-    let re = Regex::new(r"\S+:\d+-\d+$").unwrap();
-    if !re.is_match(&v) {
-        return Err(String::from("Please specify ip address and port number."));
+    let (target, ports) = sys::split_target_ports(&v)?;
+    // Host names are resolved later, everything else is expanded here to check the syntax
+    if target.is_empty() {
+        return Err(String::from("Please specify ip address, network (CIDR), address range or host name"));
     }
-    let a_vec: Vec<&str> = v.split(":").collect();
-    let addr = IpAddr::from_str(a_vec[0]);
-    match addr {
-        Ok(_) => {
-            return Ok(())
-        },
-        Err(_) => {
-            return Err(String::from("Please specify ip address"));
-        }
+    if !sys::is_hostname(&target) {
+        sys::parse_target_hosts(&target)?;
+    }
+    if let Some(ports) = ports {
+        sys::parse_port_list(&ports)?;
+    }
+    Ok(())
+}
+
+pub fn validate_port_list(v: String) -> Result<(), String> {
+    match sys::parse_port_list(&v) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Please specify ports or port ranges, separated by commas. {}", e)),
+    }
+}
+
+pub fn validate_parallel(v: String) -> Result<(), String> {
+    match v.parse::<usize>() {
+        Ok(n) if n > 0 && n <= 256 => Ok(()),
+        _ => Err(String::from("Invalid number of parallel hosts (must be 1-256)")),
+    }
+}
+
+pub fn validate_top_ports(v: String) -> Result<(), String> {
+    match v.parse::<u16>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(String::from("Invalid number of ports (must be 1-65535)")),
     }
 }
 
 pub fn validate_host_opt(v: String) -> Result<(), String> {
-    let addr = IpAddr::from_str(&v);
-    match addr {
-        Ok(_) => {
-            return Ok(())
-        },
-        Err(_) => {
-            return Err(String::from("Please specify ip address"));
-        }
+    match sys::get_host_targets(&v, "", &[]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Please specify ip address, network (CIDR) or range, separated by commas. {}", e)),
+    }
+}
+
+pub fn validate_exclude(v: String) -> Result<(), String> {
+    match sys::parse_exclude_list(&v) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Please specify ip address, network (CIDR), range or file. {}", e)),
     }
 }
 
@@ -71,6 +93,73 @@ pub fn validate_timeout(v: String) -> Result<(), String> {
     Ok(())
 }
 
+pub fn validate_scan_type(v: String) -> Result<(), String> {
+    let valid_types = vec!["syn", "fin", "xmas", "null", "connect", "udp"];
+    if valid_types.contains(&v.to_lowercase().as_str()) {
+        return Ok(())
+    }
+    Err(String::from("Invalid scan type. Specify one of syn, fin, xmas, null, connect, udp"))
+}
+
+pub fn validate_timing(v: String) -> Result<(), String> {
+    match Timing::from_template(&v) {
+        Some(_) => Ok(()),
+        None => Err(format!("Invalid timing template. Specify 0-5 or one of {}", TIMING_TEMPLATES.join(", "))),
+    }
+}
+
+pub fn validate_rate(v: String) -> Result<(), String> {
+    let pps: u32 = match v.parse::<u32>() {
+        Ok(pps) => pps,
+        Err(_) => return Err(String::from("Invalid rate. Specify packets per second (1-1000000)")),
+    };
+    if pps < 1 || pps > 1000000 {
+        return Err(String::from("Invalid rate. Specify packets per second (1-1000000)"))
+    }
+    Ok(())
+}
+
+pub fn validate_source_port(v: String) -> Result<(), String> {
+    if v.to_lowercase() == "random" {
+        return Ok(());
+    }
+    match v.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(()),
+        _ => Err(String::from("Invalid source port. Specify 1-65535 or random")),
+    }
+}
+
+pub fn validate_probe(v: String) -> Result<(), String> {
+    match HostProbe::parse(&v) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("Invalid probe. Specify icmp, timestamp, syn:<ports>, ack:<ports> or udp:<ports>")),
+    }
+}
+
+pub fn validate_dns_server(v: String) -> Result<(), String> {
+    match sys::parse_dns_server(&v) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(String::from("Invalid DNS server. Specify an IP address, with an optional port - Ex: 192.168.1.1, [2001:db8::53]:5353")),
+    }
+}
+
+pub fn validate_max_retries(v: String) -> Result<(), String> {
+    match v.parse::<u8>() {
+        Ok(n) if n <= 50 => Ok(()),
+        _ => Err(String::from("Invalid number of retries. Specify 0-50")),
+    }
+}
+
+pub fn validate_show_states(v: String) -> Result<(), String> {
+    let valid_states = vec!["closed", "filtered", "all"];
+    for state in v.split(",") {
+        if !valid_states.contains(&state.trim().to_lowercase().as_str()) {
+            return Err(String::from("Invalid port state. Specify closed, filtered or all, separated by commas"))
+        }
+    }
+    Ok(())
+}
+
 pub fn validate_interface(v: String) -> Result<(), String> {
     match interface::get_interface_index_by_name(v) {
         Some(_)=>{